@@ -31,7 +31,9 @@ fn cipher() -> Result<Aes256Gcm, AppError> {
         .map_err(|_| AppError::Internal("failed to init Aes256Gcm".into()))
 }
 
-pub fn encrypt_api_key(plain: &str) -> Result<(Vec<u8>, [u8; 12]), AppError> {
+/// AES-256-GCM with `API_KEY_ENC_KEY_BASE64`; also used for other secrets at rest
+/// (JWT signing keys, TOTP secrets).
+pub fn encrypt_secret(plain: &str) -> Result<(Vec<u8>, [u8; 12]), AppError> {
     use rand::RngCore;
 
     let c = cipher()?;
@@ -41,11 +43,29 @@ pub fn encrypt_api_key(plain: &str) -> Result<(Vec<u8>, [u8; 12]), AppError> {
 
     let ciphertext = c
         .encrypt(Nonce::from_slice(&nonce_bytes), plain.as_bytes())
-        .map_err(|_| AppError::Internal("api key encrypt failed".into()))?;
+        .map_err(|_| AppError::Internal("secret encrypt failed".into()))?;
 
     Ok((ciphertext, nonce_bytes))
 }
 
+pub fn decrypt_secret(ciphertext: &[u8], nonce: &[u8]) -> Result<String, AppError> {
+    if nonce.len() != 12 {
+        return Err(AppError::Internal("secret nonce must be 12 bytes".into()));
+    }
+
+    let c = cipher()?;
+
+    let plaintext = c
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| AppError::Internal("secret decrypt failed".into()))?;
+
+    String::from_utf8(plaintext).map_err(|_| AppError::Internal("secret is not valid utf-8".into()))
+}
+
+pub fn encrypt_api_key(plain: &str) -> Result<(Vec<u8>, [u8; 12]), AppError> {
+    encrypt_secret(plain)
+}
+
 pub fn decrypt_api_key(ciphertext: &[u8], nonce: &[u8]) -> Result<String, AppError> {
     if nonce.len() != 12 {
         return Err(AppError::Internal("api key nonce must be 12 bytes".into()));
@@ -1,126 +1,118 @@
-use axum::{extract::FromRequestParts, http::request::Parts};
-use mongodb::{
-    bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document},
-    options::{FindOneAndUpdateOptions, ReturnDocument},
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
 };
 use std::sync::Arc;
 
 use crate::{
-    auth::jwt::sha256_hex,
+    api_key::rate_limit::RateLimit,
+    auth::{jwt::sha256_hex, scopes::ScopedPrincipal},
     errors::AppError,
-    models::{api_key::ApiKeyDoc, user::UserDoc},
+    models::api_key::{ApiKeyAccount, ApiKeyDoc},
     state::AppState,
 };
 
 pub const API_KEY_HEADER: &str = "x-api-key";
 
+/// Owner of the presented API key (a user or an organization), plus the key itself
+/// (after quota accounting).
 #[derive(Clone, Debug)]
-pub struct ApiKeyUser(pub UserDoc);
+pub struct ApiKeyPrincipal(pub ApiKeyAccount, pub ApiKeyDoc);
 
-fn utc_day_yyyymmdd() -> i32 {
-    chrono::Utc::now()
-        .format("%Y%m%d")
-        .to_string()
-        .parse()
-        .unwrap()
-}
-
-fn utc_minute_bucket() -> i64 {
-    chrono::Utc::now().timestamp() / 60
+impl ScopedPrincipal for ApiKeyPrincipal {
+    fn has_scope(&self, scope: &str) -> bool {
+        self.1.scopes.iter().any(|s| s == scope)
+    }
 }
 
-async fn consume_quota(state: &AppState, key_hash: &str) -> Result<ApiKeyDoc, AppError> {
-    let now = BsonDateTime::now();
-    let day = utc_day_yyyymmdd();
-    let minute = utc_minute_bucket();
-
-    // фильтр: ключ активен, не истёк, и есть квота по минуте/дню (либо новый бакет/день)
-    let filter = doc! {
-        "key_hash": key_hash,
-        "active": true,
-        "$and": [
-            { "$or": [
-                { "expires_at": mongodb::bson::Bson::Null },
-                { "expires_at": { "$exists": false } },
-                { "expires_at": { "$gt": now } },
-            ]},
-            { "$or": [
-                { "minute_bucket": { "$ne": minute } },
-                { "$expr": { "$lt": ["$requests_used_minute", "$requests_per_minute"] } },
-            ]},
-            { "$or": [
-                { "usage_day": { "$ne": day } },
-                { "$expr": { "$lt": ["$requests_used_today", "$requests_per_day"] } },
-            ]},
-        ],
-    };
-
-    // update pipeline: при смене окна/дня сбросить счетчики, затем +1
-    let update: Vec<Document> = vec![
-        doc! { "$set": {
-            "last_used_at": now,
-
-            "minute_bucket": { "$cond": [ { "$ne": ["$minute_bucket", minute] }, minute, "$minute_bucket" ] },
-            "requests_used_minute": { "$cond": [ { "$ne": ["$minute_bucket", minute] }, 0i32, "$requests_used_minute" ] },
-
-            "usage_day": { "$cond": [ { "$ne": ["$usage_day", day] }, day, "$usage_day" ] },
-            "requests_used_today": { "$cond": [ { "$ne": ["$usage_day", day] }, 0i64, "$requests_used_today" ] },
-        }},
-        doc! { "$set": {
-            "requests_used_minute": { "$add": ["$requests_used_minute", 1i32] },
-            "requests_used_today": { "$add": ["$requests_used_today", 1i64] },
-        }},
-    ];
-
-    let updated = state.api_keys.find_one_and_update(filter, update).await?;
+impl ApiKeyPrincipal {
+    /// Checks the `x-api-key` header and counts the request against the key's quota
+    /// (not in the usage rollups yet).
+    async fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<Self, AppError> {
+        let v = headers.get(API_KEY_HEADER).ok_or(AppError::Unauthorized)?;
+        let api_key = v.to_str().map_err(|_| AppError::Unauthorized)?;
+        let key_hash = sha256_hex(api_key);
 
-    if let Some(k) = updated {
-        return Ok(k);
+        // refuses keys of disabled users before counting anything
+        let (account, key_doc) = state.quota.acquire(&key_hash).await?;
+        Ok(Self(account, key_doc))
     }
+}
 
-    // дифференцируем: ключ не найден/не активен (401) или квота выбита (429)
-    let exists_active = state
-        .api_keys
-        .find_one(doc! {
-            "key_hash": key_hash,
-            "active": true,
-            "$or": [
-                { "expires_at": mongodb::bson::Bson::Null },
-                { "expires_at": { "$exists": false } },
-                { "expires_at": { "$gt": now } }
-            ]
-        })
-        .await?;
-
-    if exists_active.is_some() {
-        Err(AppError::TooManyRequests)
-    } else {
-        Err(AppError::Unauthorized)
+/// Rejected inside the `/api` layer before a handler ran: the burst limit (429)
+/// or a scope the key lacks (403 `insufficient_scope`).
+fn not_served(resp: &Response) -> bool {
+    match resp.status() {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::FORBIDDEN => resp
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("insufficient_scope")),
+        _ => false,
     }
 }
 
-impl FromRequestParts<Arc<AppState>> for ApiKeyUser {
+impl FromRequestParts<Arc<AppState>> for ApiKeyPrincipal {
     type Rejection = AppError;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &Arc<AppState>,
     ) -> Result<Self, Self::Rejection> {
-        let v = parts
-            .headers
-            .get(API_KEY_HEADER)
-            .ok_or(AppError::Unauthorized)?;
-        let api_key = v.to_str().map_err(|_| AppError::Unauthorized)?;
-        let key_hash = sha256_hex(api_key);
-
-        let key_doc = consume_quota(state.as_ref(), &key_hash).await?;
-
-        let user = state
-            .users
-            .find_one(doc! { "_id": key_doc.user_id })
-            .await?
-            .ok_or(AppError::Unauthorized)?;
+        // already counted by `api_key_quota`
+        if let Some(principal) = parts.extensions.get::<Self>() {
+            return Ok(principal.clone());
+        }
+        let principal = Self::authenticate(state, &parts.headers).await?;
+        state.usage.record(&principal.1)?;
+        Ok(principal)
+    }
+}
 
-        Ok(Self(user))
+/// `/api` layer: authenticates the API key once per request (handlers get it via
+/// `ApiKeyPrincipal`) and adds the `RateLimit-*` headers to the response, including
+/// 429s of the burst limit inside it. Requests rejected before a handler ran are
+/// given back to the quota and left out of the usage rollups.
+pub async fn api_key_quota(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let principal = match ApiKeyPrincipal::authenticate(&state, req.headers()).await {
+        Ok(principal) => principal,
+        Err(e) => return e.into_response(),
+    };
+    let key = principal.1.clone();
+    req.extensions_mut().insert(principal);
+
+    let mut resp = next.run(req).await;
+    let burst_limited = resp.status() == StatusCode::TOO_MANY_REQUESTS;
+    let limit = if not_served(&resp) {
+        match state.quota.refund(&key) {
+            Ok(key) => RateLimit::allowed(&key),
+            Err(e) => {
+                tracing::warn!(error = %e, "API key quota refund failed");
+                RateLimit::allowed(&key)
+            }
+        }
+    } else {
+        if let Err(e) = state.usage.record(&key) {
+            tracing::warn!(error = %e, "API key usage not recorded");
+        }
+        RateLimit::allowed(&key)
+    };
+    let headers = resp.headers_mut();
+    limit.apply(headers);
+    // governor rounds its wait down, and 0 would invite a retry at once
+    if burst_limited {
+        let secs = headers
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok()?.parse::<u64>().ok())
+            .unwrap_or(0);
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
     }
+    resp
 }
@@ -1,3 +1,6 @@
 pub mod crypto;
 pub mod extractor;
 pub mod generate;
+pub mod quota;
+pub mod rate_limit;
+pub mod usage;
@@ -0,0 +1,530 @@
+//! In-process quota layer in front of `ApiKeyStore::consume_quota`.
+//!
+//! Instead of one MongoDB round trip per request, an instance reserves a lease of
+//! `QUOTA_MARGIN * requests_per_minute` requests in one atomic update and serves
+//! them from memory, together with the cached key and its owner (user or
+//! organization). Reserved requests are
+//! already counted in MongoDB, so the limits hold across instances; the error is
+//! on the safe side (up to one unused lease per instance). Leases end with their
+//! minute, day or after QUOTA_CACHE_SECONDS, and the flusher gives unused
+//! requests back. Nothing is reserved for a key whose owner is disabled.
+//!
+//! The instance that changes a key or its owner evicts the cached entries at once.
+//! Other instances see the change when they renew the lease (the key's
+//! `generation` moved, so the owner is reloaded), at the latest after
+//! QUOTA_CACHE_SECONDS. `last_used_at` is as precise as the lease.
+
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api_key::rate_limit::RateLimit,
+    config::Config,
+    errors::AppError,
+    models::api_key::{ApiKeyAccount, ApiKeyDoc, ApiKeyOwner, QuotaAlgorithm},
+    store::{ApiKeyStore, OrganizationStore, UserStore},
+};
+
+fn utc_day_yyyymmdd() -> i32 {
+    chrono::Utc::now()
+        .format("%Y%m%d")
+        .to_string()
+        .parse()
+        .unwrap()
+}
+
+fn utc_minute_bucket() -> i64 {
+    chrono::Utc::now().timestamp() / 60
+}
+
+/// Requests reserved in MongoDB for `minute`/`day` and not served yet.
+#[derive(Debug, Clone, Copy)]
+struct Lease {
+    minute: i64,
+    day: i32,
+    remaining: i32,
+    last_used_at: BsonDateTime,
+}
+
+struct Entry {
+    /// The key as returned by the last reservation, or as read when it was refused.
+    key: ApiKeyDoc,
+    account: ApiKeyAccount,
+    /// When `account` was loaded; the entry is dropped `ttl` later.
+    loaded_at: Instant,
+    lease: Lease,
+    /// Quota was exhausted: reject without asking the store until then.
+    blocked_until: Option<Instant>,
+}
+
+impl Entry {
+    /// Remembers a key whose owner may not use it; nothing is reserved.
+    fn refused(
+        key: ApiKeyDoc,
+        account: ApiKeyAccount,
+        minute: i64,
+        day: i32,
+        now: BsonDateTime,
+    ) -> Self {
+        Self {
+            key,
+            account,
+            loaded_at: Instant::now(),
+            lease: Lease {
+                minute,
+                day,
+                remaining: 0,
+                last_used_at: now,
+            },
+            blocked_until: None,
+        }
+    }
+
+    fn is_fresh(&self, ttl: Duration, now: BsonDateTime) -> bool {
+        self.loaded_at.elapsed() < ttl && self.key.expires_at.is_none_or(|exp| exp > now)
+    }
+}
+
+/// Keys of a disabled user are refused before any quota is reserved for them.
+fn check_usable(account: &ApiKeyAccount) -> Result<(), AppError> {
+    if let ApiKeyAccount::User(user) = account
+        && user.disabled
+    {
+        return Err(AppError::Forbidden("account disabled".into()));
+    }
+    Ok(())
+}
+
+/// What a new lease is reserved with: the owner as cached or just loaded.
+struct Known {
+    account: ApiKeyAccount,
+    loaded_at: Instant,
+    generation: i64,
+    lease_size: i32,
+}
+
+/// The key as seen by one request: counters without the unserved rest of the lease.
+fn served(key: &ApiKeyDoc, remaining: i32) -> ApiKeyDoc {
+    let mut key = key.clone();
+    key.requests_used_minute -= remaining;
+    key.requests_used_today -= i64::from(remaining);
+    key.bucket_tokens = key.bucket_tokens.map(|t| t + f64::from(remaining));
+    key
+}
+
+pub struct QuotaCache {
+    api_keys: Arc<dyn ApiKeyStore>,
+    users: Arc<dyn UserStore>,
+    organizations: Arc<dyn OrganizationStore>,
+    margin: f64,
+    ttl: Duration,
+    /// key hash -> cached key, owner and lease.
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Leases replaced before they were used up (or whose release failed).
+    releases: Mutex<Vec<(ObjectId, Lease)>>,
+}
+
+fn poisoned() -> AppError {
+    AppError::Internal("quota cache lock poisoned".into())
+}
+
+impl QuotaCache {
+    pub fn new(
+        api_keys: Arc<dyn ApiKeyStore>,
+        users: Arc<dyn UserStore>,
+        organizations: Arc<dyn OrganizationStore>,
+        cfg: &Config,
+    ) -> Self {
+        Self {
+            api_keys,
+            users,
+            organizations,
+            margin: cfg.quota_margin,
+            ttl: Duration::from_secs(cfg.quota_cache_seconds.max(1)),
+            entries: Mutex::new(HashMap::new()),
+            releases: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn lease_size(&self, key: &ApiKeyDoc) -> i32 {
+        ((f64::from(key.requests_per_minute) * self.margin) as i32).max(1)
+    }
+
+    /// How long to reject a key whose quota was just exhausted.
+    fn block_for(&self, key: &ApiKeyDoc) -> Duration {
+        let wait = match key.quota_algorithm {
+            QuotaAlgorithm::FixedWindow => Duration::from_millis(
+                60_000 - (chrono::Utc::now().timestamp_millis() % 60_000) as u64,
+            ),
+            // time for one request to become available again
+            QuotaAlgorithm::SlidingWindow | QuotaAlgorithm::TokenBucket => {
+                Duration::from_secs(60) / key.requests_per_minute.max(1) as u32
+            }
+        };
+        wait.min(self.ttl)
+    }
+
+    /// Counts one request with the key behind `key_hash` and returns its owner and
+    /// the key, or `Unauthorized` (unknown/inactive/expired key), `Forbidden`
+    /// (disabled owner) or `TooManyRequests`.
+    pub async fn acquire(&self, key_hash: &str) -> Result<(ApiKeyAccount, ApiKeyDoc), AppError> {
+        let now = BsonDateTime::now();
+        let minute = utc_minute_bucket();
+        let day = utc_day_yyyymmdd();
+
+        // served from the lease, or what is known about the key for a new one
+        let cached = {
+            let mut entries = self.entries.lock().map_err(|_| poisoned())?;
+            match entries.get_mut(key_hash) {
+                Some(e) if e.is_fresh(self.ttl, now) => {
+                    check_usable(&e.account)?;
+                    if e.blocked_until.is_some_and(|t| Instant::now() < t) {
+                        return Err(AppError::TooManyRequests(RateLimit::exceeded(&e.key)));
+                    }
+                    let lease = &mut e.lease;
+                    if lease.minute == minute && lease.day == day && lease.remaining > 0 {
+                        lease.remaining -= 1;
+                        lease.last_used_at = now;
+                        return Ok((e.account.clone(), served(&e.key, lease.remaining)));
+                    }
+                    Some(Known {
+                        account: e.account.clone(),
+                        loaded_at: e.loaded_at,
+                        generation: e.key.generation,
+                        lease_size: self.lease_size(&e.key),
+                    })
+                }
+                _ => None,
+            }
+        };
+        let known = match cached {
+            Some(known) => known,
+            None => self.load(key_hash, minute, day, now).await?,
+        };
+
+        let size = known.lease_size;
+        let mut granted = size;
+        let mut key = self
+            .api_keys
+            .consume_quota(key_hash, now, minute, day, size)
+            .await?;
+        if key.is_none() && size > 1 {
+            granted = 1;
+            key = self
+                .api_keys
+                .consume_quota(key_hash, now, minute, day, 1)
+                .await?;
+        }
+
+        let Some(key) = key else {
+            return self.reject(key_hash, minute, day, now).await;
+        };
+
+        let mut lease = Lease {
+            minute,
+            day,
+            remaining: granted - 1,
+            last_used_at: now,
+        };
+
+        // the key or its owner changed since the owner was loaded
+        let (account, loaded_at) =
+            if known.account.owner() == key.owner && known.generation == key.generation {
+                (known.account, known.loaded_at)
+            } else {
+                let unused = Lease {
+                    remaining: granted,
+                    ..lease
+                };
+                let account = match self.load_account(key.owner).await {
+                    Ok(account) => account,
+                    Err(e) => {
+                        self.give_back(key.id, unused)?;
+                        return Err(e);
+                    }
+                };
+                if let Err(e) = check_usable(&account) {
+                    self.give_back(key.id, unused)?;
+                    self.insert(key_hash, Entry::refused(key, account, minute, day, now))?;
+                    return Err(e);
+                }
+                (account, Instant::now())
+            };
+
+        let mut entries = self.entries.lock().map_err(|_| poisoned())?;
+        let entry = Entry {
+            key: key.clone(),
+            account: account.clone(),
+            loaded_at,
+            lease,
+            blocked_until: None,
+        };
+        if let Some(old) = entries.insert(key_hash.to_string(), entry)
+            && old.lease.remaining > 0
+        {
+            // concurrent reservations for the same window are merged
+            if old.lease.minute == minute && old.lease.day == day {
+                lease.remaining += old.lease.remaining;
+                if let Some(e) = entries.get_mut(key_hash) {
+                    e.lease = lease;
+                }
+            } else {
+                self.give_back(old.key.id, old.lease)?;
+            }
+        }
+
+        Ok((account, served(&key, lease.remaining)))
+    }
+
+    /// Gives back the request `acquire` counted for `key` when it was not served
+    /// after all (burst limit, missing scope). Returns the key as if it had not been
+    /// counted.
+    pub fn refund(&self, key: &ApiKeyDoc) -> Result<ApiKeyDoc, AppError> {
+        let counted = Lease {
+            minute: key.minute_bucket,
+            day: key.usage_day,
+            remaining: 1,
+            last_used_at: key.last_used_at,
+        };
+        let mut entries = self.entries.lock().map_err(|_| poisoned())?;
+        match entries.get_mut(&key.key_hash) {
+            Some(e)
+                if e.key.id == key.id
+                    && e.lease.minute == counted.minute
+                    && e.lease.day == counted.day =>
+            {
+                e.lease.remaining += 1;
+            }
+            // the lease is gone: the store gets the request back with the next flush
+            _ => {
+                drop(entries);
+                self.give_back(key.id, counted)?;
+            }
+        }
+        Ok(served(key, 1))
+    }
+
+    /// Looks up a key that is not cached, together with its owner. A disabled owner
+    /// is remembered (with an empty lease) and refused here, before anything is
+    /// reserved.
+    async fn load(
+        &self,
+        key_hash: &str,
+        minute: i64,
+        day: i32,
+        now: BsonDateTime,
+    ) -> Result<Known, AppError> {
+        let key = self
+            .api_keys
+            .find_active_by_hash(key_hash)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+        let account = self.load_account(key.owner).await?;
+        if let Err(e) = check_usable(&account) {
+            self.insert(key_hash, Entry::refused(key, account, minute, day, now))?;
+            return Err(e);
+        }
+
+        Ok(Known {
+            lease_size: self.lease_size(&key),
+            generation: key.generation,
+            account,
+            loaded_at: Instant::now(),
+        })
+    }
+
+    async fn load_account(&self, owner: ApiKeyOwner) -> Result<ApiKeyAccount, AppError> {
+        let account = match owner {
+            ApiKeyOwner::User(id) => self.users.find_by_id(id).await?.map(ApiKeyAccount::User),
+            ApiKeyOwner::Org(id) => self
+                .organizations
+                .find_by_id(id)
+                .await?
+                .map(ApiKeyAccount::Org),
+        };
+        account.ok_or(AppError::Unauthorized)
+    }
+
+    /// Caches `entry`; the unused rest of the lease it replaces is released.
+    fn insert(&self, key_hash: &str, entry: Entry) -> Result<(), AppError> {
+        let old = self
+            .entries
+            .lock()
+            .map_err(|_| poisoned())?
+            .insert(key_hash.to_string(), entry);
+        match old {
+            Some(old) => self.give_back(old.key.id, old.lease),
+            None => Ok(()),
+        }
+    }
+
+    /// Queues the unused rest of `lease` for the next flush.
+    fn give_back(&self, key_id: ObjectId, lease: Lease) -> Result<(), AppError> {
+        if lease.remaining > 0 {
+            self.releases
+                .lock()
+                .map_err(|_| poisoned())?
+                .push((key_id, lease));
+        }
+        Ok(())
+    }
+
+    /// Forgets the key behind `key_hash` after it was changed, deactivated, rotated
+    /// or deleted; the next request goes to the store.
+    pub fn evict(&self, key_hash: &str) -> Result<(), AppError> {
+        let old = self
+            .entries
+            .lock()
+            .map_err(|_| poisoned())?
+            .remove(key_hash);
+        match old {
+            Some(old) => self.give_back(old.key.id, old.lease),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets every key of `owner` after the owner changed (e.g. was disabled or
+    /// put on another plan).
+    pub fn evict_owner(&self, owner: ApiKeyOwner) -> Result<(), AppError> {
+        self.evict_matching(|key| key.owner == owner)
+    }
+
+    /// Forgets every key on plan `name` after the plan was redefined.
+    pub fn evict_plan(&self, name: &str) -> Result<(), AppError> {
+        self.evict_matching(|key| key.plan.as_deref() == Some(name))
+    }
+
+    fn evict_matching(&self, matches: impl Fn(&ApiKeyDoc) -> bool) -> Result<(), AppError> {
+        let mut evicted = Vec::new();
+        self.entries.lock().map_err(|_| poisoned())?.retain(|_, e| {
+            if !matches(&e.key) {
+                return true;
+            }
+            evicted.push((e.key.id, e.lease));
+            false
+        });
+        for (key_id, lease) in evicted {
+            self.give_back(key_id, lease)?;
+        }
+        Ok(())
+    }
+
+    /// The store refused the request: 429 for a usable key (remembered for a while),
+    /// 401 otherwise. The headers come from the key as it is now, not as cached.
+    async fn reject(
+        &self,
+        key_hash: &str,
+        minute: i64,
+        day: i32,
+        now: BsonDateTime,
+    ) -> Result<(ApiKeyAccount, ApiKeyDoc), AppError> {
+        // дифференцируем: ключ не найден/не активен (401) или квота выбита (429)
+        let Some(key) = self.api_keys.find_active_by_hash(key_hash).await? else {
+            self.evict(key_hash)?;
+            return Err(AppError::Unauthorized);
+        };
+
+        let cached = self
+            .entries
+            .lock()
+            .map_err(|_| poisoned())?
+            .get(key_hash)
+            .filter(|e| e.key.owner == key.owner && e.key.generation == key.generation)
+            .map(|e| (e.account.clone(), e.loaded_at));
+        let (account, loaded_at) = match cached {
+            Some(cached) => cached,
+            None => (self.load_account(key.owner).await?, Instant::now()),
+        };
+        if let Err(e) = check_usable(&account) {
+            self.insert(key_hash, Entry::refused(key, account, minute, day, now))?;
+            return Err(e);
+        }
+
+        let limit = RateLimit::exceeded(&key);
+        let entry = Entry {
+            blocked_until: Some(Instant::now() + self.block_for(&key)),
+            key,
+            account,
+            loaded_at,
+            lease: Lease {
+                minute,
+                day,
+                remaining: 0,
+                last_used_at: now,
+            },
+        };
+        self.insert(key_hash, entry)?;
+        Err(AppError::TooManyRequests(limit))
+    }
+
+    /// Drops entries older than QUOTA_CACHE_SECONDS and gives the unused rest of
+    /// their leases back to the store. Releases that fail are retried next time.
+    pub async fn flush(&self) -> Result<(), AppError> {
+        let now = BsonDateTime::now();
+        let mut releases = std::mem::take(&mut *self.releases.lock().map_err(|_| poisoned())?);
+        self.entries.lock().map_err(|_| poisoned())?.retain(|_, e| {
+            if e.is_fresh(self.ttl, now) {
+                return true;
+            }
+            if e.lease.remaining > 0 {
+                releases.push((e.key.id, e.lease));
+            }
+            false
+        });
+
+        let mut failed = Vec::new();
+        let mut error = None;
+        for (key_id, lease) in releases {
+            let released = self
+                .api_keys
+                .release_quota(
+                    key_id,
+                    lease.minute,
+                    lease.day,
+                    lease.remaining,
+                    lease.last_used_at,
+                )
+                .await;
+            if let Err(e) = released {
+                failed.push((key_id, lease));
+                error = Some(e);
+            }
+        }
+
+        if !failed.is_empty() {
+            self.releases.lock().map_err(|_| poisoned())?.extend(failed);
+        }
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Gives every unused lease back, fresh or not (on shutdown).
+    pub async fn release_all(&self) -> Result<(), AppError> {
+        let entries = std::mem::take(&mut *self.entries.lock().map_err(|_| poisoned())?);
+        for e in entries.into_values() {
+            self.give_back(e.key.id, e.lease)?;
+        }
+        self.flush().await
+    }
+
+    /// Periodically expires cached keys and releases unused leases.
+    pub fn spawn_flusher(self: &Arc<Self>, every: Duration) {
+        let cache = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let Some(cache) = cache.upgrade() else {
+                    break;
+                };
+                if let Err(e) = cache.flush().await {
+                    tracing::warn!(error = %e, "API key quota flush failed");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,224 @@
+//! `RateLimit-*` and `Retry-After` headers of `/api` responses, computed from the
+//! key's counters after quota accounting.
+//!
+//! `RateLimit-Policy` lists both quotas, the minute window then the UTC day:
+//! `60;w=60, 10000;w=86400`. `RateLimit-Limit`, `RateLimit-Remaining` and
+//! `RateLimit-Reset` (seconds) start with the value of the window that is most
+//! restrictive at the moment, followed by both windows in the same order
+//! (list syntax of draft-ietf-httpapi-ratelimit-headers):
+//! `RateLimit-Remaining: 57, 57;w=60, 9950;w=86400`.
+
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use mongodb::bson::DateTime as BsonDateTime;
+use std::cmp::Reverse;
+
+use crate::models::api_key::{previous_minute_weight, ApiKeyDoc, QuotaAlgorithm};
+
+const MINUTE_MS: i64 = 60_000;
+const DAY_MS: i64 = 86_400_000;
+
+static RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+static RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+static RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+static RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    limit: i64,
+    remaining: i64,
+    /// Seconds until the window is fully available again.
+    reset: i64,
+}
+
+/// Minute and day limits of one key at the time of a request.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    minute: Window,
+    day: Window,
+    /// Seconds until a request can succeed again; only for rejected requests.
+    retry_after: Option<i64>,
+}
+
+fn ceil_secs(ms: i64) -> i64 {
+    (ms.max(0) + 999) / 1000
+}
+
+fn utc_day_yyyymmdd(now_ms: i64) -> i32 {
+    chrono::DateTime::from_timestamp_millis(now_ms)
+        .unwrap_or_default()
+        .format("%Y%m%d")
+        .to_string()
+        .parse()
+        .unwrap()
+}
+
+/// Token bucket level refilled up to `now_ms` (`None` in the doc means full).
+fn bucket_tokens(key: &ApiKeyDoc, now_ms: i64) -> f64 {
+    let capacity = f64::from(key.requests_per_minute);
+    let refill = key.bucket_updated_at.map_or(0.0, |at| {
+        (now_ms - at.timestamp_millis()).max(0) as f64 * capacity / MINUTE_MS as f64
+    });
+    (key.bucket_tokens.unwrap_or(capacity) + refill).min(capacity)
+}
+
+/// (previous, current) minute counters as of `minute`.
+fn minute_counters(key: &ApiKeyDoc, minute: i64) -> (i64, i64) {
+    match key.minute_bucket {
+        b if b == minute => (
+            i64::from(key.requests_prev_minute),
+            i64::from(key.requests_used_minute),
+        ),
+        b if b == minute - 1 => (i64::from(key.requests_used_minute), 0),
+        _ => (0, 0),
+    }
+}
+
+/// Milliseconds until the minute limit lets one more request through.
+fn minute_retry_ms(key: &ApiKeyDoc, now_ms: i64) -> i64 {
+    let rpm = i64::from(key.requests_per_minute);
+    let into_minute = now_ms.rem_euclid(MINUTE_MS);
+    match key.quota_algorithm {
+        QuotaAlgorithm::FixedWindow => MINUTE_MS - into_minute,
+        QuotaAlgorithm::TokenBucket => {
+            let missing = 1.0 - bucket_tokens(key, now_ms);
+            (missing * MINUTE_MS as f64 / rpm.max(1) as f64).ceil() as i64
+        }
+        // smallest t into a minute with prev * (60s - t) / 60s + used + 1 <= rpm
+        QuotaAlgorithm::SlidingWindow => {
+            let needed = |prev: i64, used: i64| -> i64 {
+                if prev == 0 {
+                    return 0;
+                }
+                let share = (rpm - 1 - used) as f64 / prev as f64;
+                (MINUTE_MS as f64 * (1.0 - share)).ceil() as i64
+            };
+            let (prev, used) = minute_counters(key, now_ms.div_euclid(MINUTE_MS));
+            if used < rpm {
+                needed(prev, used) - into_minute
+            } else {
+                MINUTE_MS - into_minute + needed(used, 0)
+            }
+        }
+    }
+}
+
+impl RateLimit {
+    fn at(key: &ApiKeyDoc, now_ms: i64) -> Self {
+        let rpm = i64::from(key.requests_per_minute);
+        let minute = now_ms.div_euclid(MINUTE_MS);
+        let until_next_minute = ceil_secs(MINUTE_MS - now_ms.rem_euclid(MINUTE_MS));
+        let (prev, used) = minute_counters(key, minute);
+
+        let minute = match key.quota_algorithm {
+            QuotaAlgorithm::FixedWindow => Window {
+                limit: rpm,
+                remaining: rpm - used,
+                reset: until_next_minute,
+            },
+            QuotaAlgorithm::SlidingWindow => {
+                let weight = previous_minute_weight(BsonDateTime::from_millis(now_ms), minute);
+                Window {
+                    limit: rpm,
+                    remaining: (rpm as f64 - prev as f64 * weight - used as f64).floor() as i64,
+                    // this minute's requests still count during the next one
+                    reset: match (prev, used) {
+                        (0, 0) => 0,
+                        (_, 0) => until_next_minute,
+                        _ => until_next_minute + 60,
+                    },
+                }
+            }
+            QuotaAlgorithm::TokenBucket => {
+                let tokens = bucket_tokens(key, now_ms);
+                Window {
+                    limit: rpm,
+                    remaining: tokens.floor() as i64,
+                    reset: ceil_secs(
+                        ((rpm as f64 - tokens) * MINUTE_MS as f64 / rpm.max(1) as f64) as i64,
+                    ),
+                }
+            }
+        };
+
+        let used_today = if key.usage_day == utc_day_yyyymmdd(now_ms) {
+            key.requests_used_today
+        } else {
+            0
+        };
+        let day = Window {
+            limit: key.requests_per_day,
+            remaining: key.requests_per_day - used_today,
+            reset: ceil_secs(DAY_MS - now_ms.rem_euclid(DAY_MS)),
+        };
+
+        Self {
+            minute: Window {
+                remaining: minute.remaining.max(0),
+                ..minute
+            },
+            day: Window {
+                remaining: day.remaining.max(0),
+                ..day
+            },
+            retry_after: None,
+        }
+    }
+
+    /// Limits of `key` after the current request was counted.
+    pub fn allowed(key: &ApiKeyDoc) -> Self {
+        Self::at(key, BsonDateTime::now().timestamp_millis())
+    }
+
+    /// Limits of `key` whose quota rejected the current request.
+    pub fn exceeded(key: &ApiKeyDoc) -> Self {
+        let now_ms = BsonDateTime::now().timestamp_millis();
+        let mut limit = Self::at(key, now_ms);
+        let retry_after = if limit.day.remaining == 0 {
+            limit.day.reset
+        } else {
+            limit.minute.remaining = 0;
+            ceil_secs(minute_retry_ms(key, now_ms))
+        };
+        limit.retry_after = Some(retry_after.max(1));
+        limit
+    }
+
+    /// The window with the fewest requests left; of two exhausted ones, the one
+    /// that resets later.
+    fn most_restrictive(&self) -> Window {
+        let order = |w: &Window| (w.remaining, Reverse(w.reset));
+        if order(&self.day) < order(&self.minute) {
+            self.day
+        } else {
+            self.minute
+        }
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let policy = format!("{};w=60, {};w=86400", self.minute.limit, self.day.limit);
+        if let Ok(v) = HeaderValue::from_str(&policy) {
+            headers.insert(RATELIMIT_POLICY.clone(), v);
+        }
+        let window = self.most_restrictive();
+        let list = |f: fn(&Window) -> i64| {
+            HeaderValue::from_str(&format!(
+                "{}, {};w=60, {};w=86400",
+                f(&window),
+                f(&self.minute),
+                f(&self.day)
+            ))
+        };
+        if let Ok(v) = list(|w| w.limit) {
+            headers.insert(RATELIMIT_LIMIT.clone(), v);
+        }
+        if let Ok(v) = list(|w| w.remaining) {
+            headers.insert(RATELIMIT_REMAINING.clone(), v);
+        }
+        if let Ok(v) = list(|w| w.reset) {
+            headers.insert(RATELIMIT_RESET.clone(), v);
+        }
+        if let Some(secs) = self.retry_after {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Per-key request counts for the usage rollups (`api_key_usage`).
+//!
+//! Counting happens in memory on the request path; the flusher adds the totals
+//! to the hourly and daily rollups every USAGE_FLUSH_SECONDS, so the stored
+//! usage lags by up to that long. A graceful shutdown flushes what is left, a
+//! crash loses at most one interval. Every increment carries its own id, so
+//! retrying a write that did reach the store does not count it twice.
+
+use chrono::Utc;
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    errors::AppError,
+    models::{
+        api_key::{ApiKeyDoc, ApiKeyOwner},
+        api_key_usage::UsageGranularity,
+    },
+    store::{ApiKeyUsageStore, UsageIncrement},
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    owner: ApiKeyOwner,
+    requests: i64,
+}
+
+pub struct UsageRecorder {
+    store: Arc<dyn ApiKeyUsageStore>,
+    /// (key id, hour start in unix seconds) -> requests not flushed yet.
+    pending: Mutex<HashMap<(ObjectId, i64), Pending>>,
+    /// Increments whose write failed.
+    retry: Mutex<Vec<UsageIncrement>>,
+}
+
+fn poisoned() -> AppError {
+    AppError::Internal("usage recorder lock poisoned".into())
+}
+
+impl UsageRecorder {
+    pub fn new(store: Arc<dyn ApiKeyUsageStore>) -> Self {
+        Self {
+            store,
+            pending: Mutex::new(HashMap::new()),
+            retry: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Counts one request served with `key`.
+    pub fn record(&self, key: &ApiKeyDoc) -> Result<(), AppError> {
+        let hour = UsageGranularity::Hour.period_start(Utc::now().timestamp());
+        let mut pending = self.pending.lock().map_err(|_| poisoned())?;
+        pending
+            .entry((key.id, hour))
+            .or_insert(Pending {
+                owner: key.owner,
+                requests: 0,
+            })
+            .requests += 1;
+        Ok(())
+    }
+
+    /// Adds everything counted so far to the rollups. Increments that could not be
+    /// written are retried as they are (same id) on the next flush.
+    pub async fn flush(&self) -> Result<(), AppError> {
+        let mut increments = std::mem::take(&mut *self.retry.lock().map_err(|_| poisoned())?);
+        let batch = std::mem::take(&mut *self.pending.lock().map_err(|_| poisoned())?);
+        for ((key_id, hour), p) in batch {
+            for granularity in [UsageGranularity::Hour, UsageGranularity::Day] {
+                increments.push(UsageIncrement {
+                    id: ObjectId::new(),
+                    key_id,
+                    owner: p.owner,
+                    granularity,
+                    period_start: BsonDateTime::from_millis(granularity.period_start(hour) * 1000),
+                    requests: p.requests,
+                });
+            }
+        }
+
+        let mut failed = Vec::new();
+        let mut error = None;
+        for increment in increments {
+            if let Err(e) = self.store.add(&increment).await {
+                failed.push(increment);
+                error = Some(e);
+            }
+        }
+
+        if !failed.is_empty() {
+            self.retry.lock().map_err(|_| poisoned())?.extend(failed);
+        }
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Periodically writes the counts to the rollups.
+    pub fn spawn_flusher(self: &Arc<Self>, every: Duration) {
+        let recorder = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                if let Err(e) = recorder.flush().await {
+                    tracing::warn!(error = %e, "API key usage flush failed");
+                }
+            }
+        });
+    }
+}
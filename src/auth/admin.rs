@@ -0,0 +1,62 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use mongodb::bson::oid::ObjectId;
+use std::sync::Arc;
+
+use crate::{
+    auth::{
+        jwt::sha256_hex,
+        roles::{AdminRole, RequireRole},
+    },
+    errors::AppError,
+    state::AppState,
+};
+
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Operator access: the static `ADMIN_TOKEN` (header `x-admin-token`) or a Bearer
+/// access token of a user with the `admin` role.
+#[derive(Debug, Clone)]
+pub enum AdminAuth {
+    /// Presented `ADMIN_TOKEN`.
+    Operator,
+    /// User with the `admin` role.
+    User(ObjectId),
+}
+
+impl AdminAuth {
+    /// `actor` of audit events.
+    pub fn actor(&self) -> String {
+        match self {
+            Self::Operator => "admin".to_string(),
+            Self::User(user_id) => user_id.to_hex(),
+        }
+    }
+}
+
+impl FromRequestParts<Arc<AppState>> for AdminAuth {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(presented) = parts.headers.get(ADMIN_TOKEN_HEADER) else {
+            let RequireRole(claims, _) =
+                RequireRole::<AdminRole>::from_request_parts(parts, state).await?;
+            return Ok(Self::User(claims.first_party_user_id()?));
+        };
+
+        let expected = state
+            .cfg
+            .admin_token
+            .as_deref()
+            .ok_or(AppError::Unauthorized)?;
+        let presented = presented.to_str().map_err(|_| AppError::Unauthorized)?;
+
+        // compare digests, not the secrets themselves
+        if sha256_hex(presented) != sha256_hex(expected) {
+            return Err(AppError::Unauthorized);
+        }
+        Ok(Self::Operator)
+    }
+}
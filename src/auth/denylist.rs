@@ -0,0 +1,169 @@
+//! Revoked access tokens that have not expired yet.
+//!
+//! Entries are written to `revoked_tokens` and kept in memory, so checking a
+//! token costs no query. Revocations made by this instance apply at once, those
+//! of other instances after the next reload (`TOKEN_DENYLIST_RELOAD_SECONDS`).
+
+use chrono::{Duration as ChronoDuration, Utc};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::{auth::jwt::Claims, errors::AppError, store::RevokedTokenStore};
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    token_version: Option<i64>,
+    expires_at: BsonDateTime,
+}
+
+pub struct Denylist {
+    store: Arc<dyn RevokedTokenStore>,
+    entries: RwLock<HashMap<String, Entry>>,
+}
+
+fn poisoned() -> AppError {
+    AppError::Internal("denylist lock poisoned".into())
+}
+
+fn jti_key(jti: &str) -> String {
+    format!("jti:{jti}")
+}
+
+fn sid_key(sid: &str) -> String {
+    format!("sid:{sid}")
+}
+
+fn user_key(user_id: &str) -> String {
+    format!("user:{user_id}")
+}
+
+impl Denylist {
+    pub async fn load(store: Arc<dyn RevokedTokenStore>) -> Result<Self, AppError> {
+        let denylist = Self {
+            store,
+            entries: RwLock::new(HashMap::new()),
+        };
+        denylist.reload().await?;
+        Ok(denylist)
+    }
+
+    pub async fn reload(&self) -> Result<(), AppError> {
+        let docs = self.store.list_active(BsonDateTime::now()).await?;
+        let entries = docs
+            .into_iter()
+            .map(|d| {
+                let entry = Entry {
+                    token_version: d.token_version,
+                    expires_at: d.expires_at,
+                };
+                (d.key, entry)
+            })
+            .collect();
+        *self.entries.write().map_err(|_| poisoned())? = entries;
+        Ok(())
+    }
+
+    /// Periodically picks up revocations made by other instances.
+    pub fn spawn_reloader(self: &Arc<Self>, every: Duration) {
+        let denylist = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let Some(denylist) = denylist.upgrade() else {
+                    break;
+                };
+                if let Err(e) = denylist.reload().await {
+                    tracing::warn!(error = %e, "token denylist reload failed");
+                }
+            }
+        });
+    }
+
+    async fn add(
+        &self,
+        key: String,
+        token_version: Option<i64>,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        let expires_at =
+            BsonDateTime::from_chrono(Utc::now() + ChronoDuration::seconds(ttl_seconds.max(1)));
+        self.store.revoke(&key, token_version, expires_at).await?;
+
+        let mut entries = self.entries.write().map_err(|_| poisoned())?;
+        let entry = entries.entry(key).or_insert(Entry {
+            token_version,
+            expires_at,
+        });
+        entry.token_version = entry.token_version.max(token_version);
+        entry.expires_at = entry.expires_at.max(expires_at);
+        Ok(())
+    }
+
+    /// Rejects one token until its `exp`.
+    pub async fn revoke_token(&self, claims: &Claims) -> Result<(), AppError> {
+        let Some(jti) = claims.jti.as_deref() else {
+            return Ok(());
+        };
+        let ttl = claims.exp as i64 - Utc::now().timestamp();
+        if ttl <= 0 {
+            return Ok(());
+        }
+        self.add(jti_key(jti), None, ttl).await
+    }
+
+    /// Rejects every access token of a session (refresh token family), for `ttl_seconds`
+    /// (the access token lifetime).
+    pub async fn revoke_session(
+        &self,
+        family_id: ObjectId,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        self.add(sid_key(&family_id.to_hex()), None, ttl_seconds)
+            .await
+    }
+
+    /// Rejects access tokens of the user issued before `token_version`.
+    pub async fn revoke_user(
+        &self,
+        user_id: ObjectId,
+        token_version: i64,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        self.add(
+            user_key(&user_id.to_hex()),
+            Some(token_version),
+            ttl_seconds,
+        )
+        .await
+    }
+
+    pub fn is_revoked(&self, claims: &Claims) -> Result<bool, AppError> {
+        let entries = self.entries.read().map_err(|_| poisoned())?;
+        let now = BsonDateTime::now();
+        let active = |key: String| entries.get(&key).filter(|e| e.expires_at > now).copied();
+
+        if let Some(jti) = claims.jti.as_deref()
+            && active(jti_key(jti)).is_some()
+        {
+            return Ok(true);
+        }
+        if let Some(sid) = claims.sid.as_deref()
+            && active(sid_key(sid)).is_some()
+        {
+            return Ok(true);
+        }
+        // tokens without `ver` predate versioning and count as version 0
+        if let Some(min) = active(user_key(&claims.sub)).and_then(|e| e.token_version)
+            && claims.ver.unwrap_or(0) < min
+        {
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
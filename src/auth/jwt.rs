@@ -4,16 +4,18 @@ use axum_extra::{
     TypedHeader,
 };
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::{decode, decode_header, encode, TokenData};
+use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::sync::LazyLock;
+use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::errors::AppError;
-
-static JWT_SECRET: LazyLock<String> =
-    LazyLock::new(|| std::env::var("JWT_SECRET").expect("JWT_SECRET must be set"));
+use crate::{
+    auth::{keyring::Keyring, keys::JwtKey, scopes::ScopedPrincipal},
+    errors::AppError,
+    state::AppState,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
@@ -21,23 +23,57 @@ pub struct Claims {
     pub exp: usize,
     pub iat: usize,
 
-    pub typ: String,         // "access" | "refresh"
-    pub jti: Option<String>, // refresh only
-}
+    pub typ: String, // "access" | "refresh"
+    pub jti: Option<String>,
+
+    /// Space-delimited scopes (access only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+
+    /// OAuth client the token was issued to (access only, absent for first-party).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+
+    /// User's `token_version` at issue time (user access tokens).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ver: Option<i64>,
+
+    /// Session (refresh token family) the access token belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+
+    /// User's roles and permissions (first-party access tokens).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
 
-#[derive(Clone)]
-pub struct Keys {
-    pub encoding: EncodingKey,
-    pub decoding: DecodingKey,
+    /// Active organization of the session and the user's role in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_role: Option<String>,
 }
 
-impl Keys {
-    pub fn global() -> Self {
-        let secret = JWT_SECRET.as_bytes();
-        Self {
-            encoding: EncodingKey::from_secret(secret),
-            decoding: DecodingKey::from_secret(secret),
+impl Claims {
+    /// User id of an access token; refresh (and other) tokens are rejected.
+    pub fn access_user_id(&self) -> Result<ObjectId, AppError> {
+        if self.typ != "access" {
+            return Err(AppError::Unauthorized);
         }
+        ObjectId::parse_str(&self.sub).map_err(|_| AppError::Unauthorized)
+    }
+
+    /// Like `access_user_id`, but refuses tokens issued to OAuth clients: account
+    /// security settings (second factors, passkeys) are for the user's own sessions.
+    pub fn first_party_user_id(&self) -> Result<ObjectId, AppError> {
+        let user_id = self.access_user_id()?;
+        if self.client_id.is_some() {
+            return Err(AppError::Forbidden(
+                "not available to OAuth client tokens".into(),
+            ));
+        }
+        Ok(user_id)
     }
 }
 
@@ -47,14 +83,22 @@ pub fn sha256_hex(s: &str) -> String {
     hex::encode(h.finalize())
 }
 
-pub fn new_access_claims(user_id_hex: String, ttl_seconds: i64) -> Claims {
+pub fn new_access_claims(user_id_hex: String, ttl_seconds: i64, scopes: &[String]) -> Claims {
     let now = Utc::now();
     Claims {
         sub: user_id_hex,
         iat: now.timestamp() as usize,
         exp: (now + Duration::seconds(ttl_seconds)).timestamp() as usize,
         typ: "access".into(),
-        jti: None,
+        jti: Some(Uuid::new_v4().to_string()),
+        scope: (!scopes.is_empty()).then(|| scopes.join(" ")),
+        client_id: None,
+        ver: None,
+        sid: None,
+        roles: Vec::new(),
+        permissions: Vec::new(),
+        org_id: None,
+        org_role: None,
     }
 }
 
@@ -69,37 +113,58 @@ pub fn new_refresh_claims(user_id_hex: String, ttl_seconds: i64) -> (Claims, Str
             exp: (now + Duration::seconds(ttl_seconds)).timestamp() as usize,
             typ: "refresh".into(),
             jti: Some(jti.clone()),
+            scope: None,
+            client_id: None,
+            ver: None,
+            sid: None,
+            roles: Vec::new(),
+            permissions: Vec::new(),
+            org_id: None,
+            org_role: None,
         },
         jti,
     )
 }
 
-pub fn make_token(claims: &Claims) -> Result<String, AppError> {
-    let keys = Keys::global();
-    encode(&Header::default(), claims, &keys.encoding).map_err(|_| AppError::Jwt)
+pub fn make_token<T: Serialize>(key: &JwtKey, claims: &T) -> Result<String, AppError> {
+    encode(&key.header(), claims, &key.encoding).map_err(|_| AppError::Jwt)
 }
 
-pub fn decode_token(token: &str) -> Result<TokenData<Claims>, AppError> {
-    let keys = Keys::global();
-    decode::<Claims>(token, &keys.decoding, &Validation::default()).map_err(|_| AppError::Jwt)
+/// Verifies with the keyring key named by the `kid` header.
+pub async fn decode_token(keyring: &Keyring, token: &str) -> Result<TokenData<Claims>, AppError> {
+    let header = decode_header(token).map_err(|_| AppError::Jwt)?;
+    let key = keyring.verifying_key(header.kid.as_deref()).await?;
+    decode::<Claims>(token, &key.decoding, &key.validation()).map_err(|_| AppError::Jwt)
 }
 
 #[derive(Debug, Clone)]
 pub struct AuthClaims(pub Claims);
 
-impl<S> FromRequestParts<S> for AuthClaims
-where
-    S: Send + Sync,
-{
+impl ScopedPrincipal for AuthClaims {
+    fn has_scope(&self, scope: &str) -> bool {
+        self.0
+            .scope
+            .as_deref()
+            .is_some_and(|s| s.split_whitespace().any(|x| x == scope))
+    }
+}
+
+impl FromRequestParts<Arc<AppState>> for AuthClaims {
     type Rejection = AppError;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
             .map_err(|_| AppError::Unauthorized)?;
 
-        let data = decode_token(bearer.token())?;
+        let data = decode_token(&state.keyring, bearer.token()).await?;
+        if state.denylist.is_revoked(&data.claims)? {
+            return Err(AppError::Unauthorized);
+        }
         Ok(Self(data.claims))
     }
 }
@@ -0,0 +1,248 @@
+//! JWT keyring: one active signing key plus retired keys that still verify.
+//!
+//! Keys live in `signing_keys` (material encrypted with the same AES-GCM key as
+//! API keys), so every instance sees a rotation. An empty keyring is seeded from
+//! `JWT_SECRET` / `JWT_PRIVATE_KEY_PATH`. After rotation the previous key keeps
+//! verifying for the longest token TTL, then it is pruned.
+
+use chrono::Utc;
+use jsonwebtoken::{jwk::JwkSet, Algorithm};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api_key::crypto::{decrypt_secret, encrypt_secret},
+    auth::keys::{config_material, generate_material, is_hmac, JwtKey, LEGACY_KID},
+    config::Config,
+    errors::AppError,
+    models::signing_key::SigningKeyDoc,
+    store::SigningKeyStore,
+};
+
+/// Unknown `kid`s trigger a reload at most this often.
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+struct KeyringState {
+    active: Arc<JwtKey>,
+    by_kid: HashMap<String, Arc<JwtKey>>,
+    loaded_at: Instant,
+}
+
+pub struct Keyring {
+    store: Arc<dyn SigningKeyStore>,
+    /// How long a retired key keeps verifying (longest token TTL).
+    verify_window: chrono::Duration,
+    state: RwLock<KeyringState>,
+}
+
+fn poisoned() -> AppError {
+    AppError::Internal("keyring lock poisoned".into())
+}
+
+fn parse_alg(alg: &str) -> Result<Algorithm, AppError> {
+    Algorithm::from_str(alg).map_err(|_| AppError::Validation(format!("unknown alg: {alg}")))
+}
+
+fn to_doc(key: &JwtKey, material: &str) -> Result<SigningKeyDoc, AppError> {
+    let (ct, nonce) = encrypt_secret(material)?;
+    Ok(SigningKeyDoc {
+        id: ObjectId::new(),
+        kid: key.kid.clone(),
+        alg: format!("{:?}", key.alg),
+        key_ciphertext: ct,
+        key_nonce: nonce,
+        active: false,
+        created_at: BsonDateTime::now(),
+        retired_at: None,
+    })
+}
+
+fn build_state(
+    docs: &[SigningKeyDoc],
+    verify_window: chrono::Duration,
+) -> Result<KeyringState, AppError> {
+    let cutoff = BsonDateTime::from_millis((Utc::now() - verify_window).timestamp_millis());
+
+    let mut by_kid = HashMap::new();
+    let mut active: Option<(&SigningKeyDoc, Arc<JwtKey>)> = None;
+
+    for doc in docs {
+        if !doc.active && doc.retired_at.is_some_and(|r| r < cutoff) {
+            continue;
+        }
+
+        let material = decrypt_secret(&doc.key_ciphertext, &doc.key_nonce)?;
+        let key = Arc::new(JwtKey::from_material(
+            parse_alg(&doc.alg)?,
+            &material,
+            Some(&doc.kid),
+        )?);
+        by_kid.insert(doc.kid.clone(), key.clone());
+
+        // concurrent rotations may leave two active keys: the newest wins
+        if doc.active
+            && active
+                .as_ref()
+                .is_none_or(|(a, _)| a.created_at <= doc.created_at)
+        {
+            active = Some((doc, key));
+        }
+    }
+
+    let (_, active) =
+        active.ok_or_else(|| AppError::Internal("keyring has no active key".into()))?;
+
+    Ok(KeyringState {
+        active,
+        by_kid,
+        loaded_at: Instant::now(),
+    })
+}
+
+impl Keyring {
+    pub async fn load(store: Arc<dyn SigningKeyStore>, cfg: &Config) -> Result<Self, AppError> {
+        let verify_window =
+            chrono::Duration::seconds(cfg.jwt_access_ttl_seconds.max(cfg.jwt_refresh_ttl_seconds));
+
+        let mut docs = store.list().await?;
+        if !docs.iter().any(|d| d.active) {
+            let material = config_material(cfg)?;
+            let kid = is_hmac(cfg.jwt_alg).then_some(LEGACY_KID);
+            let key = JwtKey::from_material(cfg.jwt_alg, &material, kid)?;
+
+            let doc = to_doc(&key, &material)?;
+            store.insert(&doc).await?;
+            store.activate(doc.id, BsonDateTime::now()).await?;
+            tracing::info!(kid = %key.kid, alg = ?key.alg, "seeded JWT keyring from config");
+
+            docs = store.list().await?;
+        }
+
+        let state = build_state(&docs, verify_window)?;
+        Ok(Self {
+            store,
+            verify_window,
+            state: RwLock::new(state),
+        })
+    }
+
+    pub async fn reload(&self) -> Result<(), AppError> {
+        let docs = self.store.list().await?;
+        let state = build_state(&docs, self.verify_window)?;
+        *self.state.write().map_err(|_| poisoned())? = state;
+        Ok(())
+    }
+
+    /// Key for signing new tokens.
+    pub fn signing_key(&self) -> Result<Arc<JwtKey>, AppError> {
+        Ok(self.state.read().map_err(|_| poisoned())?.active.clone())
+    }
+
+    /// Key for verifying a token with header `kid` (reloads once on a miss, in
+    /// case another instance has just rotated).
+    pub async fn verifying_key(&self, kid: Option<&str>) -> Result<Arc<JwtKey>, AppError> {
+        let kid = kid.unwrap_or(LEGACY_KID);
+
+        let stale = {
+            let state = self.state.read().map_err(|_| poisoned())?;
+            if let Some(key) = state.by_kid.get(kid) {
+                return Ok(key.clone());
+            }
+            state.loaded_at.elapsed() >= MIN_RELOAD_INTERVAL
+        };
+
+        if stale {
+            self.reload().await?;
+            let state = self.state.read().map_err(|_| poisoned())?;
+            if let Some(key) = state.by_kid.get(kid) {
+                return Ok(key.clone());
+            }
+        }
+        Err(AppError::Jwt)
+    }
+
+    /// Public keys of every key that still verifies.
+    pub fn jwks(&self) -> Result<JwkSet, AppError> {
+        let state = self.state.read().map_err(|_| poisoned())?;
+        let mut keys: Vec<_> = state
+            .by_kid
+            .values()
+            .filter_map(|k| k.jwk.clone())
+            .collect();
+        keys.sort_by(|a, b| a.common.key_id.cmp(&b.common.key_id));
+        Ok(JwkSet { keys })
+    }
+
+    pub async fn list(&self) -> Result<Vec<SigningKeyDoc>, AppError> {
+        self.store.list().await
+    }
+
+    /// Makes a new key active; the previous one is retired but keeps verifying.
+    /// `alg` defaults to the current key's algorithm; without `private_key_pem`
+    /// fresh material is generated.
+    pub async fn rotate(
+        &self,
+        alg: Option<Algorithm>,
+        private_key_pem: Option<&str>,
+    ) -> Result<SigningKeyDoc, AppError> {
+        let alg = match alg {
+            Some(alg) => alg,
+            None => self.signing_key()?.alg,
+        };
+        let material = match private_key_pem {
+            Some(pem) if !is_hmac(alg) => pem.to_string(),
+            Some(_) => {
+                return Err(AppError::Validation(
+                    "private_key_pem is only for asymmetric algorithms".into(),
+                ));
+            }
+            // RSA keygen takes hundreds of ms: keep it off the runtime workers
+            None => tokio::task::spawn_blocking(move || generate_material(alg))
+                .await
+                .map_err(|e| AppError::Internal(format!("key generation task: {e}")))??,
+        };
+
+        let key = JwtKey::from_material(alg, &material, None)?;
+        if self.store.list().await?.iter().any(|d| d.kid == key.kid) {
+            return Err(AppError::Conflict("key is already in the keyring".into()));
+        }
+
+        let mut doc = to_doc(&key, &material)?;
+        let now = BsonDateTime::now();
+        self.store.insert(&doc).await?;
+        self.store.activate(doc.id, now).await?;
+
+        let cutoff =
+            BsonDateTime::from_millis((Utc::now() - self.verify_window).timestamp_millis());
+        self.store.delete_retired_before(cutoff).await?;
+
+        self.reload().await?;
+        tracing::info!(kid = %key.kid, alg = ?key.alg, "rotated JWT signing key");
+
+        doc.active = true;
+        Ok(doc)
+    }
+
+    /// Periodically picks up rotations made by other instances.
+    pub fn spawn_reloader(self: &Arc<Self>, every: Duration) {
+        let keyring = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let Some(keyring) = keyring.upgrade() else {
+                    break;
+                };
+                if let Err(e) = keyring.reload().await {
+                    tracing::warn!(error = %e, "JWT keyring reload failed");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,202 @@
+//! JWT signing key: HMAC secret or an asymmetric private key (PKCS#8 PEM).
+//!
+//! For RS*/PS*/ES*/EdDSA the public half is exported as a JWK and published at
+//! `/.well-known/jwks.json`, so resource servers can verify tokens locally.
+//!
+//! "Material" is how a key is stored in the keyring: base64 secret for HMAC,
+//! PEM for everything else.
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, ThumbprintHash,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+
+use uuid::Uuid;
+
+use crate::{config::Config, errors::AppError};
+
+/// `kid` of the HMAC key seeded from `JWT_SECRET`; tokens without `kid` (issued
+/// before the keyring existed) are verified with it.
+pub const LEGACY_KID: &str = "default";
+
+pub struct JwtKey {
+    pub alg: Algorithm,
+    /// `kid` header; RFC 7638 thumbprint for asymmetric keys.
+    pub kid: String,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+    /// Public key (asymmetric only).
+    pub jwk: Option<Jwk>,
+}
+
+fn key_err(what: &str) -> impl FnOnce(jsonwebtoken::errors::Error) -> AppError + '_ {
+    move |e| AppError::Internal(format!("{what}: {e}"))
+}
+
+pub fn is_hmac(alg: Algorithm) -> bool {
+    matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+}
+
+fn public_jwk(alg: Algorithm, encoding: &EncodingKey) -> Result<Jwk, AppError> {
+    let mut jwk = match alg {
+        // jsonwebtoken can't derive the public JWK of an Ed25519 key itself
+        Algorithm::EdDSA => {
+            use ed25519_dalek::pkcs8::DecodePrivateKey;
+
+            let sk = ed25519_dalek::SigningKey::from_pkcs8_der(encoding.inner())
+                .map_err(|e| AppError::Internal(format!("bad Ed25519 key: {e}")))?;
+            Jwk {
+                common: CommonParameters {
+                    key_algorithm: Some(KeyAlgorithm::EdDSA),
+                    ..Default::default()
+                },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(sk.verifying_key().as_bytes()),
+                }),
+            }
+        }
+        _ => Jwk::from_encoding_key(encoding, alg).map_err(key_err("jwk"))?,
+    };
+
+    jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+    jwk.common.key_id = Some(jwk.thumbprint(ThumbprintHash::SHA256));
+    Ok(jwk)
+}
+
+impl JwtKey {
+    pub fn hmac(kid: String, alg: Algorithm, secret: &[u8]) -> Self {
+        Self {
+            alg,
+            kid,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    /// Private key in PEM (PKCS#8; PKCS#1 is also accepted for RSA).
+    pub fn from_pem(alg: Algorithm, pem: &[u8]) -> Result<Self, AppError> {
+        let encoding = match alg {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => EncodingKey::from_rsa_pem(pem),
+            Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(pem),
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(pem),
+            _ => {
+                return Err(AppError::Validation(format!(
+                    "{alg:?} is not an asymmetric algorithm"
+                )));
+            }
+        }
+        .map_err(|e| AppError::Validation(format!("bad JWT private key: {e}")))?;
+
+        let jwk = public_jwk(alg, &encoding)?;
+        let decoding = DecodingKey::from_jwk(&jwk).map_err(key_err("jwk"))?;
+
+        Ok(Self {
+            alg,
+            kid: jwk.common.key_id.clone().unwrap_or_default(),
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+        })
+    }
+
+    /// Rebuilds a key from keyring material. Without `kid`, asymmetric keys use
+    /// their thumbprint and HMAC keys get a random one.
+    pub fn from_material(
+        alg: Algorithm,
+        material: &str,
+        kid: Option<&str>,
+    ) -> Result<Self, AppError> {
+        let mut key = if is_hmac(alg) {
+            let secret = STANDARD
+                .decode(material)
+                .map_err(|_| AppError::Internal("HMAC key material is not base64".into()))?;
+            Self::hmac(Uuid::new_v4().simple().to_string(), alg, &secret)
+        } else {
+            Self::from_pem(alg, material.as_bytes())?
+        };
+
+        if let Some(kid) = kid {
+            key.kid = kid.to_string();
+            if let Some(jwk) = key.jwk.as_mut() {
+                jwk.common.key_id = Some(kid.to_string());
+            }
+        }
+        Ok(key)
+    }
+
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.alg);
+        header.kid = Some(self.kid.clone());
+        header
+    }
+
+    pub fn validation(&self) -> Validation {
+        Validation::new(self.alg)
+    }
+}
+
+/// Key material configured via `JWT_SECRET` / `JWT_PRIVATE_KEY_PATH`; seeds an
+/// empty keyring.
+pub fn config_material(cfg: &Config) -> Result<String, AppError> {
+    if is_hmac(cfg.jwt_alg) {
+        let secret = cfg
+            .jwt_secret
+            .as_deref()
+            .ok_or_else(|| AppError::Internal("JWT_SECRET is required".into()))?;
+        return Ok(STANDARD.encode(secret.as_bytes()));
+    }
+
+    let path = cfg.jwt_private_key_path.as_deref().ok_or_else(|| {
+        AppError::Internal("JWT_PRIVATE_KEY_PATH is required for asymmetric JWT_ALG".into())
+    })?;
+    std::fs::read_to_string(path).map_err(|e| AppError::Internal(format!("read {path}: {e}")))
+}
+
+/// Fresh random key material for rotation.
+pub fn generate_material(alg: Algorithm) -> Result<String, AppError> {
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey};
+    use rand::{rngs::OsRng, RngCore};
+
+    let pem = match alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            let mut secret = [0u8; 64];
+            OsRng.fill_bytes(&mut secret);
+            return Ok(STANDARD.encode(secret));
+        }
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => rsa::RsaPrivateKey::new(&mut OsRng, 2048)
+            .map_err(|e| AppError::Internal(format!("rsa keygen: {e}")))?
+            .to_pkcs8_pem(LineEnding::LF),
+        Algorithm::ES256 => p256::SecretKey::random(&mut OsRng).to_pkcs8_pem(LineEnding::LF),
+        Algorithm::EdDSA => {
+            ed25519_dalek::SigningKey::generate(&mut OsRng).to_pkcs8_pem(LineEnding::LF)
+        }
+        _ => {
+            return Err(AppError::Validation(format!(
+                "cannot generate {alg:?} keys, provide private_key_pem"
+            )));
+        }
+    }
+    .map_err(|e| AppError::Internal(format!("pkcs8 encode: {e}")))?;
+
+    Ok(pem.to_string())
+}
@@ -1,3 +1,12 @@
+pub mod admin;
+pub mod denylist;
 pub mod jwt;
+pub mod keyring;
+pub mod keys;
+pub mod oidc;
+pub mod roles;
+pub mod scopes;
 pub mod tokens;
+pub mod totp;
+pub mod webauthn;
 pub use jwt::AuthClaims;
@@ -0,0 +1,71 @@
+//! OpenID Connect ID tokens (OIDC Core §2), signed with the keyring like access tokens.
+
+use chrono::{Duration, Utc};
+use mongodb::bson::DateTime as BsonDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{
+        jwt::make_token,
+        scopes::{SCOPE_EMAIL, SCOPE_PROFILE},
+    },
+    errors::AppError,
+    models::user::UserPublic,
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String, // client_id
+    pub exp: usize,
+    pub iat: usize,
+    pub auth_time: usize,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+
+    /// `email` scope.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// `profile` scope.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Claims released for the granted scopes: `(email, name)`.
+pub fn scoped_claims(user: &UserPublic, scopes: &[String]) -> (Option<String>, Option<String>) {
+    let has = |s: &str| scopes.iter().any(|x| x == s);
+    (
+        has(SCOPE_EMAIL).then(|| user.email.clone()),
+        has(SCOPE_PROFILE).then(|| user.name.clone()),
+    )
+}
+
+pub fn make_id_token(
+    state: &AppState,
+    user: &UserPublic,
+    client_id: &str,
+    scopes: &[String],
+    nonce: Option<String>,
+    auth_time: BsonDateTime,
+) -> Result<String, AppError> {
+    let now = Utc::now();
+    let (email, name) = scoped_claims(user, scopes);
+
+    let claims = IdTokenClaims {
+        iss: state.cfg.issuer.clone(),
+        sub: user.id.clone(),
+        aud: client_id.to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::seconds(state.cfg.jwt_access_ttl_seconds)).timestamp() as usize,
+        auth_time: (auth_time.timestamp_millis() / 1000) as usize,
+        nonce,
+        email,
+        name,
+    };
+
+    let signing_key = state.keyring.signing_key()?;
+    make_token(&signing_key, &claims)
+}
@@ -0,0 +1,116 @@
+//! Role-based access control.
+//!
+//! Roles and permissions are stored on the user and embedded in first-party access
+//! tokens (`roles`, `permissions` claims), so other services can authorize from the
+//! token (or /auth/introspect) without calling back. A handler declares what it
+//! needs by extracting `RequireRole<R>` or `RequirePermission<P>`, where the marker
+//! type names the role or permission; a missing one is rejected with 403.
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use std::marker::PhantomData;
+
+use crate::{
+    auth::jwt::{AuthClaims, Claims},
+    errors::AppError,
+};
+
+pub const ROLE_ADMIN: &str = "admin";
+
+const MAX_NAME_LEN: usize = 64;
+
+/// Role required by a route.
+pub trait RequiredRole {
+    const ROLE: &'static str;
+}
+
+/// Permission required by a route.
+pub trait RequiredPermission {
+    const PERMISSION: &'static str;
+}
+
+/// Operators of this service (`/admin/*`).
+pub struct AdminRole;
+
+impl RequiredRole for AdminRole {
+    const ROLE: &'static str = ROLE_ADMIN;
+}
+
+impl Claims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+/// Bearer access token whose user has role `R`.
+pub struct RequireRole<R>(pub Claims, pub PhantomData<R>);
+
+impl<R, S> FromRequestParts<S> for RequireRole<R>
+where
+    AuthClaims: FromRequestParts<S, Rejection = AppError>,
+    R: RequiredRole,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthClaims(claims) = AuthClaims::from_request_parts(parts, state).await?;
+        claims.access_user_id()?;
+
+        if claims.has_role(R::ROLE) {
+            Ok(Self(claims, PhantomData))
+        } else {
+            Err(AppError::Forbidden(format!("role {} required", R::ROLE)))
+        }
+    }
+}
+
+/// Bearer access token whose user has permission `P`.
+pub struct RequirePermission<P>(pub Claims, pub PhantomData<P>);
+
+impl<P, S> FromRequestParts<S> for RequirePermission<P>
+where
+    AuthClaims: FromRequestParts<S, Rejection = AppError>,
+    P: RequiredPermission,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthClaims(claims) = AuthClaims::from_request_parts(parts, state).await?;
+        claims.access_user_id()?;
+
+        if claims.has_permission(P::PERMISSION) {
+            Ok(Self(claims, PhantomData))
+        } else {
+            Err(AppError::Forbidden(format!(
+                "permission {} required",
+                P::PERMISSION
+            )))
+        }
+    }
+}
+
+/// Trims, validates and dedups role or permission names (`[a-z0-9_.:-]`, ≤ 64).
+pub fn normalize_names(names: Vec<String>, what: &str) -> Result<Vec<String>, AppError> {
+    let mut out: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        let name = name.trim().to_ascii_lowercase();
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b':' | b'-'));
+        if !valid {
+            return Err(AppError::Validation(format!("invalid {what}: {name:?}")));
+        }
+        if !out.contains(&name) {
+            out.push(name);
+        }
+    }
+    out.sort();
+    Ok(out)
+}
@@ -0,0 +1,76 @@
+//! Per-route scope requirements.
+//!
+//! A handler declares what it needs by extracting `RequireScopes<P, R>`: `P` is the
+//! principal extractor (`ApiKeyPrincipal` or `AuthClaims`), `R` a marker type listing
+//! the scopes. Missing scopes are rejected with 403 `insufficient_scope`.
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use std::marker::PhantomData;
+
+use crate::errors::AppError;
+
+pub const SCOPE_API: &str = "api";
+pub const SCOPE_API_KEYS: &str = "api_keys";
+
+// OpenID Connect
+pub const SCOPE_OPENID: &str = "openid";
+pub const SCOPE_EMAIL: &str = "email";
+pub const SCOPE_PROFILE: &str = "profile";
+
+/// Scope set required by a route.
+pub trait RequiredScopes {
+    const SCOPES: &'static [&'static str];
+}
+
+/// An authenticated principal that carries scopes (API key or access token).
+pub trait ScopedPrincipal {
+    fn has_scope(&self, scope: &str) -> bool;
+}
+
+/// `/api/*`: calls made with an API key.
+pub struct ApiScope;
+
+impl RequiredScopes for ApiScope {
+    const SCOPES: &'static [&'static str] = &[SCOPE_API];
+}
+
+/// Management of the user's own API keys.
+pub struct ApiKeysScope;
+
+impl RequiredScopes for ApiKeysScope {
+    const SCOPES: &'static [&'static str] = &[SCOPE_API_KEYS];
+}
+
+/// OIDC UserInfo.
+pub struct OpenIdScope;
+
+impl RequiredScopes for OpenIdScope {
+    const SCOPES: &'static [&'static str] = &[SCOPE_OPENID];
+}
+
+/// Extracts principal `P` and checks that it has every scope of `R`.
+pub struct RequireScopes<P, R>(pub P, pub PhantomData<R>);
+
+impl<P, R, S> FromRequestParts<S> for RequireScopes<P, R>
+where
+    P: FromRequestParts<S, Rejection = AppError> + ScopedPrincipal,
+    R: RequiredScopes,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = P::from_request_parts(parts, state).await?;
+
+        if R::SCOPES.iter().all(|s| principal.has_scope(s)) {
+            Ok(Self(principal, PhantomData))
+        } else {
+            Err(AppError::InsufficientScope(R::SCOPES.join(" ")))
+        }
+    }
+}
+
+/// Space-delimited `scope` claim (RFC 6749 §3.3) → list.
+pub fn split_scope(scope: &str) -> Vec<String> {
+    scope.split_whitespace().map(str::to_string).collect()
+}
@@ -1,11 +1,22 @@
 use crate::{
     auth::jwt::{make_token, new_access_claims, new_refresh_claims, sha256_hex},
     errors::AppError,
-    models::refresh_token::RefreshTokenDoc,
+    models::{
+        refresh_token::RefreshTokenDoc,
+        user_token::{UserTokenDoc, UserTokenPurpose},
+    },
+    rate_limit::ClientIp,
     state::AppState,
 };
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{Duration, Utc};
 use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use rand::RngCore;
+use std::{convert::Infallible, net::IpAddr, sync::Arc};
 
 #[derive(Debug, Clone)]
 pub struct IssuedTokens {
@@ -15,33 +26,125 @@ pub struct IssuedTokens {
     pub refresh_doc_id: ObjectId,
 }
 
+/// Who the tokens are for. `Default` is a first-party login (`JWT_ACCESS_SCOPES`).
+#[derive(Debug, Clone, Default)]
+pub struct TokenGrant {
+    pub client_id: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    /// Family and start of the session continued by a rotation; `None` starts a new one.
+    pub family_id: Option<ObjectId>,
+    pub session_started_at: Option<BsonDateTime>,
+    /// Active organization (first-party sessions); dropped if the user left it.
+    pub org_id: Option<ObjectId>,
+}
+
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Device a refresh token is issued to, shown in the session list.
+#[derive(Debug, Clone, Default)]
+pub struct ClientDevice {
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+impl FromRequestParts<Arc<AppState>> for ClientDevice {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state).await?;
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.chars().take(MAX_USER_AGENT_LEN).collect());
+        Ok(Self { user_agent, ip })
+    }
+}
+
 pub async fn issue_tokens_and_store_refresh(
     state: &AppState,
     user_id: ObjectId,
+    device: &ClientDevice,
 ) -> Result<IssuedTokens, AppError> {
-    let access_claims = new_access_claims(user_id.to_hex(), state.cfg.jwt_access_ttl_seconds);
+    issue_tokens_for_grant(state, user_id, &TokenGrant::default(), device).await
+}
+
+/// Like `issue_tokens_and_store_refresh`, but the grant is remembered on the refresh
+/// token so that rotation keeps the same client and scopes.
+pub async fn issue_tokens_for_grant(
+    state: &AppState,
+    user_id: ObjectId,
+    grant: &TokenGrant,
+    device: &ClientDevice,
+) -> Result<IssuedTokens, AppError> {
+    let scopes = grant
+        .scopes
+        .as_deref()
+        .unwrap_or(&state.cfg.jwt_access_scopes);
+    let user = state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    if user.disabled {
+        return Err(AppError::Forbidden("account disabled".into()));
+    }
+
+    let refresh_doc_id = ObjectId::new();
+    let family_id = grant.family_id.unwrap_or(refresh_doc_id);
+
+    let mut access_claims =
+        new_access_claims(user_id.to_hex(), state.cfg.jwt_access_ttl_seconds, scopes);
+    access_claims.client_id = grant.client_id.clone();
+    access_claims.ver = Some(user.token_version);
+    access_claims.sid = Some(family_id.to_hex());
+    // roles authorize this service's own APIs, not what OAuth clients may do
+    let mut org_id = None;
+    if grant.client_id.is_none() {
+        access_claims.roles = user.roles;
+        access_claims.permissions = user.permissions;
+
+        if let Some(id) = grant.org_id
+            && let Some(membership) = state.memberships.find(id, user_id).await?
+        {
+            access_claims.org_id = Some(id.to_hex());
+            access_claims.org_role = Some(membership.role.as_str().to_string());
+            org_id = Some(id);
+        }
+    }
     let (refresh_claims, refresh_jti) =
         new_refresh_claims(user_id.to_hex(), state.cfg.jwt_refresh_ttl_seconds);
 
-    let access_token = make_token(&access_claims)?;
-    let refresh_token = make_token(&refresh_claims)?;
+    let signing_key = state.keyring.signing_key()?;
+    let access_token = make_token(&signing_key, &access_claims)?;
+    let refresh_token = make_token(&signing_key, &refresh_claims)?;
 
     let expires_at_millis =
         (Utc::now() + Duration::seconds(state.cfg.jwt_refresh_ttl_seconds)).timestamp_millis();
 
-    let refresh_doc_id = ObjectId::new();
+    let now = BsonDateTime::now();
     let rt = RefreshTokenDoc {
         id: refresh_doc_id,
         user_id,
         jti: refresh_jti,
         token_hash: sha256_hex(&refresh_token),
-        created_at: BsonDateTime::now(),
+        created_at: now,
         expires_at: BsonDateTime::from_millis(expires_at_millis),
         revoked_at: None,
         replaced_by: None,
+        client_id: grant.client_id.clone(),
+        scopes: grant.scopes.clone(),
+        user_agent: device.user_agent.clone(),
+        ip: device.ip.map(|ip| ip.to_string()),
+        session_started_at: Some(grant.session_started_at.unwrap_or(now)),
+        family_id: Some(family_id),
+        org_id,
     };
 
-    state.refresh_tokens.insert_one(rt).await?;
+    state.refresh_tokens.insert(&rt).await?;
 
     Ok(IssuedTokens {
         access_token,
@@ -50,3 +153,65 @@ pub async fn issue_tokens_and_store_refresh(
         refresh_doc_id,
     })
 }
+
+/// Access token of an OAuth client acting on its own behalf (`sub` = client_id).
+/// No refresh token: the client simply authenticates again.
+pub fn issue_client_access_token(
+    state: &AppState,
+    client_id: &str,
+    scopes: &[String],
+) -> Result<String, AppError> {
+    let mut claims = new_access_claims(
+        client_id.to_string(),
+        state.cfg.oauth_client_token_ttl_seconds,
+        scopes,
+    );
+    claims.client_id = Some(client_id.to_string());
+
+    let signing_key = state.keyring.signing_key()?;
+    make_token(&signing_key, &claims)
+}
+
+/// 32 random bytes, base64url (authorization codes, client secrets, mailed tokens).
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Creates a single-use token for `purpose` and returns its plaintext (only the
+/// hash is stored).
+pub async fn issue_user_token(
+    state: &AppState,
+    user_id: ObjectId,
+    purpose: UserTokenPurpose,
+    ttl_seconds: i64,
+) -> Result<String, AppError> {
+    let token = random_token();
+    let now = Utc::now();
+
+    let doc = UserTokenDoc {
+        id: ObjectId::new(),
+        token_hash: sha256_hex(&token),
+        user_id,
+        purpose,
+        created_at: BsonDateTime::from_chrono(now),
+        expires_at: BsonDateTime::from_chrono(now + Duration::seconds(ttl_seconds)),
+    };
+    state.user_tokens.insert(&doc).await?;
+
+    Ok(token)
+}
+
+/// Consumes a mailed token; unknown, expired and already used tokens are rejected.
+pub async fn consume_user_token(
+    state: &AppState,
+    token: &str,
+    purpose: UserTokenPurpose,
+) -> Result<UserTokenDoc, AppError> {
+    state
+        .user_tokens
+        .take_by_hash(&sha256_hex(token), purpose, BsonDateTime::now())
+        .await?
+        .ok_or_else(|| AppError::Validation("invalid or expired token".into()))
+}
@@ -0,0 +1,93 @@
+//! TOTP (RFC 6238: SHA-1, 6 digits, 30 s) and recovery codes.
+
+use rand::Rng;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{auth::jwt::sha256_hex, errors::AppError};
+
+const STEP_SECONDS: u64 = 30;
+/// Accepted clock drift, in steps each way.
+const SKEW_STEPS: u64 = 1;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+// no 0/o, 1/l/i: codes are typed by hand
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// New random 160-bit secret, base32.
+pub fn new_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::rngs::OsRng.fill(&mut bytes);
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+fn totp(secret: &str, issuer: &str, account: &str) -> Result<TOTP, AppError> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|_| AppError::Internal("totp secret is not valid base32".into()))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        SKEW_STEPS as u8,
+        STEP_SECONDS,
+        bytes,
+        Some(issuer.to_string()),
+        account.to_string(),
+    )
+    .map_err(|e| AppError::Internal(format!("totp: {e}")))
+}
+
+/// `otpauth://` URL for authenticator apps (usually shown as a QR code).
+pub fn otpauth_url(secret: &str, issuer: &str, account: &str) -> Result<String, AppError> {
+    Ok(totp(secret, issuer, account)?.get_url())
+}
+
+/// Time step the code belongs to (within the allowed drift), `None` if it does not match.
+pub fn matching_step(secret: &str, code: &str, now: u64) -> Result<Option<i64>, AppError> {
+    let code = code.trim();
+    if code.len() != 6 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(None);
+    }
+
+    let totp = totp(secret, "-", "-")?;
+    let current = now / STEP_SECONDS;
+
+    let step = (current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS).find(|step| {
+        let expected = totp.generate(step * STEP_SECONDS);
+        // constant time: the comparison must not leak matching prefixes
+        expected
+            .bytes()
+            .zip(code.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    });
+
+    Ok(step.map(|s| s as i64))
+}
+
+/// Plain codes (shown once) and their hashes (stored).
+pub fn new_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let mut rng = rand::rngs::OsRng;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|c| recovery_code_hash(c)).collect();
+
+    (codes, hashes)
+}
+
+/// Case, spaces and dashes do not matter.
+pub fn recovery_code_hash(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    sha256_hex(&normalized)
+}
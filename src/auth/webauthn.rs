@@ -0,0 +1,237 @@
+//! WebAuthn (passkey) checks shared by the registration and login ceremonies:
+//! client data, authenticator data and COSE public keys.
+//!
+//! Attestation statements are not verified: options ask for `attestation: "none"`,
+//! so the credential is trusted as the user's own, not as a certified device.
+
+use ciborium::value::{Integer, Value};
+use ed25519_dalek::Verifier as _; // `signature::Verifier`, shared by all three key types
+use rsa::{pkcs1v15, BigUint, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::errors::AppError;
+
+pub const ALG_ES256: i64 = -7;
+pub const ALG_EDDSA: i64 = -8;
+pub const ALG_RS256: i64 = -257;
+/// In order of preference (`pubKeyCredParams`).
+pub const SUPPORTED_ALGS: [i64; 3] = [ALG_ES256, ALG_EDDSA, ALG_RS256];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+
+pub(crate) fn invalid(msg: &str) -> AppError {
+    AppError::Validation(format!("invalid credential: {msg}"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub typ: String,
+    /// base64url, as issued by us
+    pub challenge: String,
+    pub origin: String,
+}
+
+/// Parses `clientDataJSON` and checks ceremony type and origin.
+pub fn parse_client_data(
+    json: &[u8],
+    expected_type: &str,
+    origins: &[String],
+) -> Result<ClientData, AppError> {
+    let data: ClientData =
+        serde_json::from_slice(json).map_err(|_| invalid("malformed clientDataJSON"))?;
+
+    if data.typ != expected_type {
+        return Err(invalid("unexpected ceremony type"));
+    }
+    if !origins.iter().any(|o| o == &data.origin) {
+        return Err(invalid("origin not allowed"));
+    }
+    Ok(data)
+}
+
+pub struct AttestedCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE_Key, CBOR-encoded
+    pub public_key: Vec<u8>,
+}
+
+pub struct AuthenticatorData {
+    pub rp_id_hash: [u8; 32],
+    pub flags: u8,
+    pub sign_count: u32,
+    /// Present in registration responses only.
+    pub credential: Option<AttestedCredential>,
+}
+
+impl AuthenticatorData {
+    pub fn parse(data: &[u8]) -> Result<Self, AppError> {
+        if data.len() < 37 {
+            return Err(invalid("authenticator data too short"));
+        }
+
+        let mut rp_id_hash = [0u8; 32];
+        rp_id_hash.copy_from_slice(&data[..32]);
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let credential = if flags & FLAG_ATTESTED_DATA != 0 {
+            // aaguid(16) | credentialIdLength(2) | credentialId | credentialPublicKey
+            let rest = &data[37..];
+            if rest.len() < 18 {
+                return Err(invalid("attested credential data too short"));
+            }
+            let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let rest = &rest[18..];
+            if rest.len() < id_len {
+                return Err(invalid("attested credential data too short"));
+            }
+            let (credential_id, mut key_bytes) = rest.split_at(id_len);
+
+            // the key is followed by optional extensions: take exactly one CBOR item
+            let total = key_bytes.len();
+            let _: Value = ciborium::from_reader(&mut key_bytes)
+                .map_err(|_| invalid("malformed credential public key"))?;
+            let public_key = rest[id_len..id_len + (total - key_bytes.len())].to_vec();
+
+            Some(AttestedCredential {
+                credential_id: credential_id.to_vec(),
+                public_key,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash,
+            flags,
+            sign_count,
+            credential,
+        })
+    }
+
+    /// Right relying party, user present and verified (passkeys replace the password).
+    pub fn check(&self, rp_id: &str) -> Result<(), AppError> {
+        if self.rp_id_hash[..] != Sha256::digest(rp_id.as_bytes())[..] {
+            return Err(invalid("rp id mismatch"));
+        }
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(invalid("user not present"));
+        }
+        if self.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(invalid("user not verified"));
+        }
+        Ok(())
+    }
+}
+
+/// `authData` of a CBOR attestation object (`fmt` / `attStmt` are ignored, see module docs).
+pub fn attestation_auth_data(attestation_object: &[u8]) -> Result<Vec<u8>, AppError> {
+    let value: Value = ciborium::from_reader(attestation_object)
+        .map_err(|_| invalid("malformed attestation object"))?;
+
+    value
+        .as_map()
+        .and_then(|m| {
+            m.iter()
+                .find(|(k, _)| k.as_text() == Some("authData"))
+                .and_then(|(_, v)| v.as_bytes())
+        })
+        .cloned()
+        .ok_or_else(|| invalid("attestation object without authData"))
+}
+
+enum CoseKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(pkcs1v15::VerifyingKey<Sha256>),
+}
+
+fn cose_param(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| k.as_integer() == Some(Integer::from(label)))
+        .map(|(_, v)| v)
+}
+
+fn cose_int(map: &[(Value, Value)], label: i64) -> Option<i64> {
+    cose_param(map, label)
+        .and_then(Value::as_integer)
+        .and_then(|i| i64::try_from(i).ok())
+}
+
+fn cose_bytes(map: &[(Value, Value)], label: i64) -> Result<&[u8], AppError> {
+    cose_param(map, label)
+        .and_then(Value::as_bytes)
+        .map(Vec::as_slice)
+        .ok_or_else(|| invalid("incomplete public key"))
+}
+
+fn parse_cose_key(bytes: &[u8]) -> Result<(i64, CoseKey), AppError> {
+    let value: Value = ciborium::from_reader(bytes).map_err(|_| invalid("malformed public key"))?;
+    let map = value
+        .as_map()
+        .ok_or_else(|| invalid("malformed public key"))?;
+
+    // 1: kty, 3: alg, -1: crv (EC2/OKP) or n (RSA), -2: x or e, -3: y
+    let kty = cose_int(map, 1);
+    let alg = cose_int(map, 3).ok_or_else(|| invalid("public key without alg"))?;
+
+    let key = match (alg, kty) {
+        (ALG_ES256, Some(2)) if cose_int(map, -1) == Some(1) => {
+            let mut point = vec![0x04];
+            point.extend_from_slice(cose_bytes(map, -2)?);
+            point.extend_from_slice(cose_bytes(map, -3)?);
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+                .map_err(|_| invalid("bad P-256 key"))?;
+            CoseKey::Es256(key)
+        }
+        (ALG_EDDSA, Some(1)) if cose_int(map, -1) == Some(6) => {
+            let x: [u8; 32] = cose_bytes(map, -2)?
+                .try_into()
+                .map_err(|_| invalid("bad Ed25519 key"))?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&x)
+                .map_err(|_| invalid("bad Ed25519 key"))?;
+            CoseKey::EdDsa(key)
+        }
+        (ALG_RS256, Some(3)) => {
+            let n = BigUint::from_bytes_be(cose_bytes(map, -1)?);
+            let e = BigUint::from_bytes_be(cose_bytes(map, -2)?);
+            let key = RsaPublicKey::new(n, e).map_err(|_| invalid("bad RSA key"))?;
+            CoseKey::Rs256(pkcs1v15::VerifyingKey::new(key))
+        }
+        _ => return Err(invalid("unsupported public key algorithm")),
+    };
+
+    Ok((alg, key))
+}
+
+/// Validates a COSE public key, returns its algorithm.
+pub fn cose_key_alg(bytes: &[u8]) -> Result<i64, AppError> {
+    parse_cose_key(bytes).map(|(alg, _)| alg)
+}
+
+/// Checks an assertion signature over `authenticatorData || sha256(clientDataJSON)`.
+pub fn verify_assertion(
+    cose_key: &[u8],
+    auth_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> Result<bool, AppError> {
+    let (_, key) = parse_cose_key(cose_key)?;
+
+    let mut message = auth_data.to_vec();
+    message.extend_from_slice(&Sha256::digest(client_data_json));
+
+    let ok = match key {
+        CoseKey::Es256(key) => p256::ecdsa::Signature::from_der(signature)
+            .is_ok_and(|sig| key.verify(&message, &sig).is_ok()),
+        CoseKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+            .is_ok_and(|sig| key.verify(&message, &sig).is_ok()),
+        CoseKey::Rs256(key) => pkcs1v15::Signature::try_from(signature)
+            .is_ok_and(|sig| key.verify(&message, &sig).is_ok()),
+    };
+    Ok(ok)
+}
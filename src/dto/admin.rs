@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::{
+    api_key::{ApiKeyDoc, ApiKeyPublic, QuotaAlgorithm},
+    oauth_client::OAuthClientPublic,
+    user::UserPublic,
+};
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RotateJwtKeyRequest {
+    /// e.g. "RS256"; defaults to the algorithm of the current key.
+    pub alg: Option<String>,
+    /// Bring your own key (PKCS#8 PEM) instead of generating one.
+    pub private_key_pem: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateOAuthClientRequest {
+    pub name: String,
+    /// Absolute https URIs (plain http only for localhost), matched exactly.
+    /// May be empty for confidential clients that only use `client_credentials`.
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    /// Scopes the client may request.
+    pub scopes: Vec<String>,
+    /// Issue a client secret (backend services).
+    #[serde(default)]
+    pub confidential: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateOAuthClientResponse {
+    pub client: OAuthClientPublic,
+    /// Plain secret of a confidential client (показываем только один раз).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UnlockLoginRequest {
+    /// Clears the account's failed login counter and lock.
+    pub email: Option<String>,
+    /// Clears the client's failed login counter and lock.
+    pub ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetRolesRequest {
+    /// Replaces the user's roles, e.g. `["admin"]`.
+    pub roles: Vec<String>,
+    /// Replaces the user's permissions, e.g. `["billing:read"]`.
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminUserQuery {
+    /// Case-insensitive substring of the email or name.
+    pub q: Option<String>,
+    pub disabled: Option<bool>,
+    pub role: Option<String>,
+    /// `next_before` of the previous page.
+    pub before: Option<String>,
+    /// Page size, 1..=200 (default 50).
+    pub limit: Option<i64>,
+}
+
+/// Users newest first; pass `next_before` as `before` for the next page.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserPage {
+    pub users: Vec<UserPublic>,
+    /// `None` on the last page.
+    pub next_before: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminApiKeyQuery {
+    /// Owner of personal keys.
+    pub user_id: Option<String>,
+    pub org_id: Option<String>,
+    /// User who created the keys, personal or organization ones.
+    pub created_by: Option<String>,
+    pub active: Option<bool>,
+    /// `next_before` of the previous page.
+    pub before: Option<String>,
+    /// Page size, 1..=200 (default 50).
+    pub limit: Option<i64>,
+}
+
+/// An API key with its owner and usage counters.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AdminApiKeyPublic {
+    #[serde(flatten)]
+    pub key: ApiKeyPublic,
+    /// Owner of a personal key (`org_id` is set for organization keys).
+    pub user_id: Option<String>,
+    pub created_by: Option<String>,
+    /// Requests in the current minute window.
+    pub requests_used_minute: i32,
+    /// Requests on `usage_day` (UTC, yyyymmdd).
+    pub requests_used_today: i64,
+    pub usage_day: i32,
+}
+
+impl From<ApiKeyDoc> for AdminApiKeyPublic {
+    fn from(k: ApiKeyDoc) -> Self {
+        Self {
+            user_id: k.owner.user_id().map(|id| id.to_hex()),
+            created_by: k.created_by.map(|id| id.to_hex()),
+            requests_used_minute: k.requests_used_minute,
+            requests_used_today: k.requests_used_today,
+            usage_day: k.usage_day,
+            key: ApiKeyPublic::from(k),
+        }
+    }
+}
+
+/// Keys newest first; pass `next_before` as `before` for the next page.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyPage {
+    pub keys: Vec<AdminApiKeyPublic>,
+    /// `None` on the last page.
+    pub next_before: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct AdminUpdateApiKeyRequest {
+    pub scopes: Option<Vec<String>>,
+    /// Custom limits (may exceed the plan until the owner's plan changes).
+    pub requests_per_minute: Option<i32>,
+    pub requests_per_day: Option<i64>,
+    pub quota_algorithm: Option<QuotaAlgorithm>,
+    /// Drop custom limits and follow the plan again.
+    #[serde(default)]
+    pub plan_limits: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpsertPlanRequest {
+    pub requests_per_minute: i32,
+    pub requests_per_day: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetPlanRequest {
+    /// e.g. `"pro"`.
+    pub plan: String,
+}
@@ -0,0 +1,80 @@
+use crate::models::{
+    api_key::{ApiKeyPublic, QuotaAlgorithm},
+    api_key_usage::{UsageGranularity, UsagePoint},
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Distinguishes a missing field (`None`) from an explicit `null` (`Some(None)`).
+fn deserialize_some<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(d).map(Some)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Option<Vec<String>>,
+    pub expires_at: Option<String>, // RFC 3339
+    pub requests_per_minute: Option<i32>,
+    pub requests_per_day: Option<i64>,
+    /// Defaults to `fixed_window`.
+    pub quota_algorithm: Option<QuotaAlgorithm>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateApiKeyResponse {
+    pub key: ApiKeyPublic,
+    pub api_key: String, // plain (показываем только один раз)
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdateApiKeyRequest {
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    /// RFC 3339; `null` removes the expiry.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at: Option<Option<String>>,
+    pub requests_per_minute: Option<i32>,
+    pub requests_per_day: Option<i64>,
+    pub quota_algorithm: Option<QuotaAlgorithm>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    /// RFC 3339 or `YYYY-MM-DD` (UTC), inclusive; defaults to 30 days (24 hours for
+    /// hourly usage) before `to`.
+    pub from: Option<String>,
+    /// RFC 3339 or `YYYY-MM-DD` (UTC), exclusive; defaults to now.
+    pub to: Option<String>,
+    /// `day` (default) or `hour`.
+    #[param(inline)]
+    pub granularity: Option<UsageGranularity>,
+    /// `json` (default) or `csv`.
+    #[param(inline)]
+    pub format: Option<UsageFormat>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageReport {
+    pub key_id: String,
+    pub granularity: UsageGranularity,
+    pub from: String,
+    pub to: String,
+    /// Sum of `points`.
+    pub total: i64,
+    /// Periods with requests, oldest first (periods without requests are omitted).
+    pub points: Vec<UsagePoint>,
+}
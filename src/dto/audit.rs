@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::audit_event::{AuditAction, AuditEventPublic, AuditOutcome};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditPageQuery {
+    /// `next_before` of the previous page.
+    pub before: Option<String>,
+    /// Page size, 1..=200 (default 50).
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminAuditQuery {
+    pub user_id: Option<String>,
+    pub actor: Option<String>,
+    #[param(inline)]
+    pub action: Option<AuditAction>,
+    #[param(inline)]
+    pub outcome: Option<AuditOutcome>,
+    /// RFC 3339, inclusive.
+    pub from: Option<String>,
+    /// RFC 3339, exclusive.
+    pub to: Option<String>,
+    /// `next_before` of the previous page.
+    pub before: Option<String>,
+    /// Page size, 1..=200 (default 50).
+    pub limit: Option<i64>,
+}
+
+/// Events newest first; pass `next_before` as `before` for the next page.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditPage {
+    pub events: Vec<AuditEventPublic>,
+    /// `None` on the last page.
+    pub next_before: Option<String>,
+}
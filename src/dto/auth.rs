@@ -1,6 +1,6 @@
 use crate::models::user::UserPublic;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct RegisterRequest {
@@ -13,22 +13,41 @@ pub struct RegisterRequest {
 pub struct RegisterResponse {
     pub user: UserPublic,
     pub api_key: String,
-    pub access_token: String,
-    pub refresh_token: String,
-    pub token_type: String,
+    // absent while REQUIRE_VERIFIED_EMAIL is on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
 }
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct IntrospectRequest {
     pub token: String,
 }
 
-#[derive(Debug, Serialize, ToSchema)]
+/// `Default` is the inactive response (RFC 7662: no other fields).
+#[derive(Debug, Default, Serialize, ToSchema)]
 pub struct IntrospectResponse {
     pub active: bool,
-    pub sub: Option<String>, // user_id
+    pub sub: Option<String>, // user_id (org_id for organization API keys)
     pub token_type: Option<String>,
     pub scopes: Option<Vec<String>>,
+    pub client_id: Option<String>, // OAuth client the token was issued to
+    pub roles: Option<Vec<String>>, // first-party access tokens
+    pub permissions: Option<Vec<String>>,
+    /// Organization the token acts for (owner of an API key, active org of an
+    /// access token) and the user's current role in it.
+    pub org_id: Option<String>,
+    pub org_role: Option<String>,
 }
+
+impl IntrospectResponse {
+    pub fn inactive() -> Self {
+        Self::default()
+    }
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub email: String,
@@ -37,9 +56,16 @@ pub struct LoginRequest {
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
-    pub access_token: String,
-    pub refresh_token: String,
-    pub token_type: String,
+    // absent while a second factor is pending
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    /// Set when TOTP is on: no tokens yet, finish with `/auth/login/mfa`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mfa_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -58,3 +84,57 @@ pub struct RefreshResponse {
 pub struct RotateApiKeyResponse {
     pub api_key: String,
 }
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    /// TOTP code or a recovery code.
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    /// TOTP code (or a recovery code, where noted).
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpSetupResponse {
+    /// base32, for manual entry
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    /// Shown only once, each code works once.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MfaStatusResponse {
+    pub totp_enabled: bool,
+    pub recovery_codes_remaining: usize,
+}
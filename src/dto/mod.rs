@@ -1 +1,7 @@
+pub mod admin;
+pub mod api_key;
+pub mod audit;
 pub mod auth;
+pub mod oauth;
+pub mod org;
+pub mod passkey;
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Authorization request (RFC 6749 §4.1.1 + RFC 7636 §4.3).
+#[derive(Debug, Clone, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorizeRequest {
+    /// Only "code" is supported.
+    pub response_type: Option<String>,
+    pub client_id: String,
+    /// Must exactly match one of the client's registered URIs.
+    pub redirect_uri: String,
+    /// Space-delimited; defaults to every scope the client is allowed.
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    /// BASE64URL(SHA256(code_verifier)).
+    pub code_challenge: Option<String>,
+    /// Only "S256" is supported.
+    pub code_challenge_method: Option<String>,
+    /// OIDC: echoed in the ID token.
+    pub nonce: Option<String>,
+}
+
+/// Login form posted back to `/oauth/authorize`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AuthorizeForm {
+    #[serde(flatten)]
+    pub request: AuthorizeRequest,
+    pub email: String,
+    pub password: String,
+    /// TOTP or recovery code, for users with TOTP on.
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// Token request (RFC 6749 §4.1.3, §6), form-encoded.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TokenRequest {
+    /// "authorization_code" | "refresh_token" | "client_credentials"
+    pub grant_type: String,
+    /// Client authentication via the body (`client_secret_post`);
+    /// HTTP Basic (`client_secret_basic`) is accepted as well.
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+
+    // authorization_code
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+
+    // refresh_token
+    pub refresh_token: Option<String>,
+
+    // client_credentials: space-delimited, defaults to every scope the client is allowed
+    pub scope: Option<String>,
+}
+
+/// Token response (RFC 6749 §5.1).
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Granted scopes, space-delimited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// OIDC ID token, when `openid` was granted (authorization code only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+/// OIDC UserInfo response; `email` / `name` depend on the token's scopes.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// OpenID Provider Metadata (OIDC Discovery §3).
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub introspection_endpoint: String,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub scopes_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+}
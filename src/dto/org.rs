@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::membership::OrgRole;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+}
+
+/// An organization as seen by one of its members.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrganizationMembershipPublic {
+    pub id: String,
+    pub name: String,
+    /// The caller's role.
+    pub role: OrgRole,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MemberPublic {
+    pub user_id: String,
+    pub email: String,
+    pub name: String,
+    pub role: OrgRole,
+    pub joined_at: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddMemberRequest {
+    /// Email of an existing user.
+    pub email: String,
+    /// Defaults to `member`.
+    pub role: Option<OrgRole>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMemberRequest {
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SwitchOrganizationRequest {
+    pub refresh_token: String,
+    /// Organization to act in; `null` switches back to personal.
+    pub org_id: Option<String>,
+}
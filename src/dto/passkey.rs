@@ -0,0 +1,116 @@
+//! WebAuthn ceremony payloads in the JSON form of WebAuthn Level 3
+//! (`PublicKeyCredential.parseCreationOptionsFromJSON()` / `credential.toJSON()`):
+//! binary fields are base64url strings.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUserEntity {
+    /// user handle: base64url of the user id
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialParameter {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub require_resident_key: bool,
+    pub user_verification: String,
+}
+
+/// `PublicKeyCredentialCreationOptionsJSON`
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRegistrationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: PasskeyUserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameter>,
+    /// milliseconds
+    pub timeout: u64,
+    pub attestation: String,
+    pub authenticator_selection: AuthenticatorSelection,
+    /// Passkeys the user already has.
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+/// `PublicKeyCredentialRequestOptionsJSON` (discoverable credentials: no allow list)
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyLoginOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: u64,
+    pub user_verification: String,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// `RegistrationResponseJSON`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RegistrationCredential {
+    /// credential id, base64url
+    pub id: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasskeyRegisterFinishRequest {
+    /// Label shown in the passkey list.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default)]
+    pub user_handle: Option<String>,
+}
+
+/// `AuthenticationResponseJSON`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AssertionCredential {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub response: AssertionResponse,
+}
@@ -1,9 +1,11 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde_json::json;
+
+use crate::api_key::rate_limit::RateLimit;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -14,6 +16,10 @@ pub enum AppError {
     #[error("Unauthorized")]
     Unauthorized,
 
+    /// Authenticated, but not allowed (e.g. email not verified).
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Conflict: {0}")]
     Conflict(String),
 
@@ -28,8 +34,25 @@ pub enum AppError {
 
     #[error("Internal error: {0}")]
     Internal(String),
+    /// API key quota exhausted; the key's limits for the `RateLimit-*` headers.
     #[error("Too many requests")]
-    TooManyRequests,
+    TooManyRequests(RateLimit),
+
+    /// Too many failed logins from this client; seconds until it may retry.
+    #[error("Too many login attempts, retry after {0}s")]
+    Throttled(u64),
+
+    /// Account temporarily locked after failed logins; seconds until unlock.
+    #[error("Account locked for {0}s")]
+    Locked(u64),
+
+    /// Required scopes (space-delimited) the credential lacks.
+    #[error("Insufficient scope: {0}")]
+    InsufficientScope(String),
+
+    /// RFC 6749 §5.2 error code + description (`/oauth/*`).
+    #[error("OAuth error: {0}: {1}")]
+    OAuth(&'static str, String),
 }
 
 impl From<mongodb::error::Error> for AppError {
@@ -43,14 +66,50 @@ impl IntoResponse for AppError {
         let (status, msg) = match &self {
             AppError::Validation(s) => (StatusCode::BAD_REQUEST, s.as_str()),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            AppError::Forbidden(s) => (StatusCode::FORBIDDEN, s.as_str()),
             AppError::Conflict(s) => (StatusCode::CONFLICT, s.as_str()),
             AppError::NotFound => (StatusCode::NOT_FOUND, "not found"),
             AppError::Db(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database error"),
             AppError::Jwt => (StatusCode::BAD_REQUEST, "invalid token"),
-            AppError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, "too many requests"),
+            AppError::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, "too many requests"),
+            AppError::Throttled(_) => (StatusCode::TOO_MANY_REQUESTS, "too many login attempts"),
+            AppError::Locked(_) => (StatusCode::LOCKED, "account temporarily locked"),
             AppError::Internal(s) => (StatusCode::INTERNAL_SERVER_ERROR, s.as_str()),
+            AppError::InsufficientScope(_) => (StatusCode::FORBIDDEN, "insufficient_scope"),
+            AppError::OAuth(code, description) => {
+                let status = if *code == "invalid_client" {
+                    StatusCode::UNAUTHORIZED
+                } else {
+                    StatusCode::BAD_REQUEST
+                };
+                return (
+                    status,
+                    [(header::CACHE_CONTROL, "no-store")],
+                    Json(json!({ "error": code, "error_description": description })),
+                )
+                    .into_response();
+            }
         };
 
-        (status, Json(json!({ "error": msg }))).into_response()
+        let mut resp = (status, Json(json!({ "error": msg }))).into_response();
+
+        // RFC 6750 §3.1
+        if let AppError::InsufficientScope(scope) = &self {
+            let challenge = format!("Bearer error=\"insufficient_scope\", scope=\"{scope}\"");
+            if let Ok(v) = HeaderValue::from_str(&challenge) {
+                resp.headers_mut().insert(header::WWW_AUTHENTICATE, v);
+            }
+        }
+
+        if let AppError::TooManyRequests(limit) = &self {
+            limit.apply(resp.headers_mut());
+        }
+
+        if let AppError::Throttled(secs) | AppError::Locked(secs) = &self {
+            resp.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(*secs));
+        }
+
+        resp
     }
 }
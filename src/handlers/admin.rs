@@ -0,0 +1,544 @@
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Response,
+    Json,
+};
+use jsonwebtoken::Algorithm;
+
+use crate::{
+    auth::{admin::AdminAuth, tokens::ClientDevice},
+    dto::admin::{
+        AdminApiKeyPublic, AdminApiKeyQuery, AdminUpdateApiKeyRequest, AdminUserQuery, ApiKeyPage,
+        CreateOAuthClientRequest, CreateOAuthClientResponse, RotateJwtKeyRequest, SetPlanRequest,
+        SetRolesRequest, UnlockLoginRequest, UpsertPlanRequest, UserPage,
+    },
+    dto::api_key::{UsageQuery, UsageReport},
+    dto::audit::{AdminAuditQuery, AuditPage},
+    errors::AppError,
+    handlers::api_keys::usage_response,
+    models::{
+        login_attempt::LoginAttemptPublic, oauth_client::OAuthClientPublic,
+        organization::OrganizationPublic, plan::PlanPublic, signing_key::SigningKeyPublic,
+        user::UserPublic,
+    },
+    services::{admin_service, audit_service, lockout_service, oauth_service, plan_service},
+    state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/jwt-keys",
+    responses(
+        (status = 200, description = "JWT keyring (no key material)", body = Vec<SigningKeyPublic>),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_jwt_keys(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+) -> Result<Json<Vec<SigningKeyPublic>>, AppError> {
+    let keys = state.keyring.list().await?;
+    Ok(Json(keys.into_iter().map(SigningKeyPublic::from).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/jwt-keys/rotate",
+    request_body = RotateJwtKeyRequest,
+    responses(
+        (status = 200, description = "New active key; the previous one keeps verifying", body = SigningKeyPublic),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn rotate_jwt_key(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Json(req): Json<RotateJwtKeyRequest>,
+) -> Result<Json<SigningKeyPublic>, AppError> {
+    let alg = req
+        .alg
+        .as_deref()
+        .map(|a| {
+            Algorithm::from_str(a).map_err(|_| AppError::Validation(format!("unknown alg: {a}")))
+        })
+        .transpose()?;
+
+    let doc = state
+        .keyring
+        .rotate(alg, req.private_key_pem.as_deref())
+        .await?;
+    Ok(Json(SigningKeyPublic::from(doc)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/oauth/clients",
+    request_body = CreateOAuthClientRequest,
+    responses(
+        (status = 200, description = "Client registered, secret shown once", body = CreateOAuthClientResponse),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn create_oauth_client(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Json(req): Json<CreateOAuthClientRequest>,
+) -> Result<Json<CreateOAuthClientResponse>, AppError> {
+    Ok(Json(oauth_service::create_client(&state, req).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/oauth/clients",
+    responses(
+        (status = 200, description = "Registered OAuth clients", body = Vec<OAuthClientPublic>),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_oauth_clients(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+) -> Result<Json<Vec<OAuthClientPublic>>, AppError> {
+    Ok(Json(oauth_service::list_clients(&state).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/lockouts",
+    responses(
+        (status = 200, description = "Accounts and clients locked right now", body = Vec<LoginAttemptPublic>),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_lockouts(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+) -> Result<Json<Vec<LoginAttemptPublic>>, AppError> {
+    Ok(Json(lockout_service::list_locked(&state).await?))
+}
+
+/// Lifts a lock and resets the failed login counter of an account and/or a client IP.
+#[utoipa::path(
+    post,
+    path = "/lockouts/unlock",
+    request_body = UnlockLoginRequest,
+    responses(
+        (status = 200, description = "Unlocked", body = serde_json::Value),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn unlock_login(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Json(req): Json<UnlockLoginRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let cleared = lockout_service::unlock(&state, req).await?;
+    Ok(Json(
+        serde_json::json!({ "status": "ok", "cleared": cleared }),
+    ))
+}
+
+/// Audit log of all accounts; filters combine with AND.
+#[utoipa::path(
+    get,
+    path = "/audit",
+    params(AdminAuditQuery),
+    responses(
+        (status = 200, description = "Events, newest first", body = AuditPage),
+        (status = 400, description = "Invalid filter"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_audit_events(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Query(query): Query<AdminAuditQuery>,
+) -> Result<Json<AuditPage>, AppError> {
+    Ok(Json(audit_service::query(&state, query).await?))
+}
+
+/// Replaces a user's roles and permissions. Their current access tokens are revoked;
+/// sessions get tokens with the new roles on the next refresh.
+#[utoipa::path(
+    put,
+    path = "/users/{id}/roles",
+    params(("id" = String, Path, description = "User id")),
+    request_body = SetRolesRequest,
+    responses(
+        (status = 200, description = "Updated user", body = UserPublic),
+        (status = 400, description = "Invalid role or permission name"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Bearer token without the admin role"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn set_user_roles(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<SetRolesRequest>,
+) -> Result<Json<UserPublic>, AppError> {
+    Ok(Json(
+        admin_service::set_roles(&state, &admin, &id, req, &device).await?,
+    ))
+}
+
+/// Users, newest first; filters combine with AND.
+#[utoipa::path(
+    get,
+    path = "/users",
+    params(AdminUserQuery),
+    responses(
+        (status = 200, description = "Users", body = UserPage),
+        (status = 400, description = "Invalid filter"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_users(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Query(query): Query<AdminUserQuery>,
+) -> Result<Json<UserPage>, AppError> {
+    Ok(Json(admin_service::list_users(&state, query).await?))
+}
+
+/// Disables the account: sign-in, refresh and its API keys stop working, sessions end.
+#[utoipa::path(
+    post,
+    path = "/users/{id}/disable",
+    params(("id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Updated user", body = UserPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn disable_user(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<UserPublic>, AppError> {
+    Ok(Json(
+        admin_service::disable_user(&state, &admin, &id, &device).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{id}/enable",
+    params(("id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Updated user", body = UserPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn enable_user(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<UserPublic>, AppError> {
+    Ok(Json(
+        admin_service::enable_user(&state, &admin, &id, &device).await?,
+    ))
+}
+
+/// Deletes the account with its sessions, personal API keys, memberships and second
+/// factors; organization keys it created stay with their organization.
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    params(("id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Last owner of an organization")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    admin_service::delete_user(&state, &admin, &id, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Ends every session of the user.
+#[utoipa::path(
+    post,
+    path = "/users/{id}/logout",
+    params(("id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Number of ended sessions", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn logout_user(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let revoked = admin_service::force_logout(&state, &admin, &id, &device).await?;
+    Ok(Json(
+        serde_json::json!({ "status": "ok", "sessions_revoked": revoked }),
+    ))
+}
+
+/// API keys of all users and organizations, newest first; filters combine with AND.
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    params(AdminApiKeyQuery),
+    responses(
+        (status = 200, description = "API keys with usage", body = ApiKeyPage),
+        (status = 400, description = "Invalid filter"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_api_keys(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Query(query): Query<AdminApiKeyQuery>,
+) -> Result<Json<ApiKeyPage>, AppError> {
+    Ok(Json(admin_service::list_api_keys(&state, query).await?))
+}
+
+/// Changes a key's scopes and quotas.
+#[utoipa::path(
+    patch,
+    path = "/api-keys/{id}",
+    params(("id" = String, Path, description = "API key id")),
+    request_body = AdminUpdateApiKeyRequest,
+    responses(
+        (status = 200, description = "Updated key", body = AdminApiKeyPublic),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn update_api_key(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<AdminUpdateApiKeyRequest>,
+) -> Result<Json<AdminApiKeyPublic>, AppError> {
+    Ok(Json(
+        admin_service::update_api_key(&state, &admin, &id, req, &device).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys/{id}/revoke",
+    params(("id" = String, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "Deactivated key", body = AdminApiKeyPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn revoke_api_key(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<AdminApiKeyPublic>, AppError> {
+    Ok(Json(
+        admin_service::revoke_api_key(&state, &admin, &id, &device).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys/{id}/reset-usage",
+    params(("id" = String, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "Key with zeroed counters", body = AdminApiKeyPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn reset_api_key_usage(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<AdminApiKeyPublic>, AppError> {
+    Ok(Json(
+        admin_service::reset_api_key_usage(&state, &admin, &id, &device).await?,
+    ))
+}
+
+/// Plans from PLANS with the stored ones applied on top.
+#[utoipa::path(
+    get,
+    path = "/plans",
+    responses(
+        (status = 200, description = "Plans", body = Vec<PlanPublic>),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn list_plans(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+) -> Result<Json<Vec<PlanPublic>>, AppError> {
+    let plans = plan_service::list(&state).await?;
+    Ok(Json(plans.into_iter().map(PlanPublic::from).collect()))
+}
+
+/// Creates or redefines a plan; keys on it get the new limits immediately.
+#[utoipa::path(
+    put,
+    path = "/plans/{name}",
+    params(("name" = String, Path, description = "Plan name")),
+    request_body = UpsertPlanRequest,
+    responses(
+        (status = 200, description = "Plan", body = PlanPublic),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn upsert_plan(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(name): Path<String>,
+    Json(req): Json<UpsertPlanRequest>,
+) -> Result<Json<PlanPublic>, AppError> {
+    Ok(Json(
+        plan_service::upsert(&state, &admin, &name, req, &device).await?,
+    ))
+}
+
+/// Moves the user's personal API keys to another plan.
+#[utoipa::path(
+    put,
+    path = "/users/{id}/plan",
+    params(("id" = String, Path, description = "User id")),
+    request_body = SetPlanRequest,
+    responses(
+        (status = 200, description = "Updated user", body = UserPublic),
+        (status = 400, description = "Unknown plan"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn set_user_plan(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<SetPlanRequest>,
+) -> Result<Json<UserPublic>, AppError> {
+    Ok(Json(
+        plan_service::set_user_plan(&state, &admin, &id, req, &device).await?,
+    ))
+}
+
+/// Moves the organization's API keys to another plan.
+#[utoipa::path(
+    put,
+    path = "/orgs/{id}/plan",
+    params(("id" = String, Path, description = "Organization id")),
+    request_body = SetPlanRequest,
+    responses(
+        (status = 200, description = "Updated organization", body = OrganizationPublic),
+        (status = 400, description = "Unknown plan"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn set_org_plan(
+    State(state): State<Arc<AppState>>,
+    admin: AdminAuth,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<SetPlanRequest>,
+) -> Result<Json<OrganizationPublic>, AppError> {
+    Ok(Json(
+        plan_service::set_org_plan(&state, &admin, &id, req, &device).await?,
+    ))
+}
+
+/// Usage of any key, for billing; also works for deactivated keys.
+#[utoipa::path(
+    get,
+    path = "/api-keys/{id}/usage",
+    params(("id" = String, Path, description = "API key id"), UsageQuery),
+    responses(
+        (status = 200, description = "Usage", content(
+            (UsageReport = "application/json"),
+            (String = "text/csv")
+        )),
+        (status = 400, description = "Invalid range"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "admin",
+    security(("adminToken" = []), ("bearerAuth" = [])),
+)]
+pub async fn get_api_key_usage(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminAuth,
+    Path(id): Path<String>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Response, AppError> {
+    let report = admin_service::api_key_usage(&state, &id, &query).await?;
+    Ok(usage_response(report, query.format))
+}
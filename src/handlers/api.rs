@@ -1,17 +1,33 @@
 use axum::Json;
 
-use crate::api_key::extractor::ApiKeyUser;
+use crate::{
+    api_key::extractor::ApiKeyPrincipal,
+    auth::scopes::{ApiScope, RequireScopes},
+    models::api_key::ApiKeyAccount,
+};
+
 #[utoipa::path(
     get,
     path = "/ping",
-    responses((status = 200, description = "OK")),
+    responses(
+        (status = 200, description = "OK"),
+        (status = 403, description = "Key lacks the `api` scope"),
+        (status = 429, description = "Key quota exhausted; see `RateLimit-*` and `Retry-After`")
+    ),
     tag = "api",
-      security(("apiKeyAuth" = [])),
+      security(("apiKeyAuth" = ["api"])),
 )]
-pub async fn ping(ApiKeyUser(user): ApiKeyUser) -> Json<serde_json::Value> {
+pub async fn ping(
+    RequireScopes(ApiKeyPrincipal(account, _), _): RequireScopes<ApiKeyPrincipal, ApiScope>,
+) -> Json<serde_json::Value> {
+    let (user_id, email, org_id) = match account {
+        ApiKeyAccount::User(user) => (Some(user.id.to_hex()), Some(user.email), None),
+        ApiKeyAccount::Org(org) => (None, None, Some(org.id.to_hex())),
+    };
     Json(serde_json::json!({
         "ok": true,
-        "user_id": user.id.to_hex(),
-        "email": user.email
+        "user_id": user_id,
+        "email": email,
+        "org_id": org_id
     }))
 }
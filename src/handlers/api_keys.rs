@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    auth::{
+        jwt::AuthClaims,
+        scopes::{ApiKeysScope, RequireScopes},
+        tokens::ClientDevice,
+    },
+    dto::api_key::{
+        CreateApiKeyRequest, CreateApiKeyResponse, UpdateApiKeyRequest, UsageFormat, UsageQuery,
+        UsageReport,
+    },
+    errors::AppError,
+    models::api_key::{ApiKeyOwner, ApiKeyPublic},
+    services::{api_key_service, usage_service},
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 200, description = "Key created, plaintext shown once", body = CreateApiKeyResponse),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreateApiKeyResponse>, AppError> {
+    let user_id = claims.access_user_id()?;
+    let created = api_key_service::create(
+        state.as_ref(),
+        ApiKeyOwner::User(user_id),
+        user_id,
+        req,
+        &device,
+    )
+    .await?;
+
+    Ok(Json(CreateApiKeyResponse {
+        key: ApiKeyPublic::from(created.key),
+        api_key: created.api_key,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    responses(
+        (status = 200, description = "User's API keys", body = Vec<ApiKeyPublic>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn list_api_keys(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+) -> Result<Json<Vec<ApiKeyPublic>>, AppError> {
+    let user_id = claims.access_user_id()?;
+    Ok(Json(
+        api_key_service::list(state.as_ref(), ApiKeyOwner::User(user_id)).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-keys/{id}",
+    params(("id" = String, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "API key", body = ApiKeyPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn get_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiKeyPublic>, AppError> {
+    let user_id = claims.access_user_id()?;
+    Ok(Json(
+        api_key_service::get(state.as_ref(), ApiKeyOwner::User(user_id), &id).await?,
+    ))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api-keys/{id}",
+    params(("id" = String, Path, description = "API key id")),
+    request_body = UpdateApiKeyRequest,
+    responses(
+        (status = 200, description = "Updated API key", body = ApiKeyPublic),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn update_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateApiKeyRequest>,
+) -> Result<Json<ApiKeyPublic>, AppError> {
+    let user_id = claims.access_user_id()?;
+    Ok(Json(
+        api_key_service::update(
+            state.as_ref(),
+            ApiKeyOwner::User(user_id),
+            user_id,
+            &id,
+            req,
+            &device,
+        )
+        .await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys/{id}/deactivate",
+    params(("id" = String, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "Deactivated API key", body = ApiKeyPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn deactivate_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<ApiKeyPublic>, AppError> {
+    let user_id = claims.access_user_id()?;
+    Ok(Json(
+        api_key_service::deactivate(
+            state.as_ref(),
+            ApiKeyOwner::User(user_id),
+            user_id,
+            &id,
+            &device,
+        )
+        .await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api-keys/{id}",
+    params(("id" = String, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn delete_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.access_user_id()?;
+    api_key_service::delete(
+        state.as_ref(),
+        ApiKeyOwner::User(user_id),
+        user_id,
+        &id,
+        &device,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// JSON report, or a CSV attachment for `format=csv`.
+pub(crate) fn usage_response(report: UsageReport, format: Option<UsageFormat>) -> Response {
+    match format.unwrap_or_default() {
+        UsageFormat::Json => Json(report).into_response(),
+        UsageFormat::Csv => {
+            let filename = format!(
+                "attachment; filename=\"usage-{}-{}.csv\"",
+                report.key_id,
+                report.granularity.as_str()
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                    (header::CONTENT_DISPOSITION, filename),
+                ],
+                usage_service::to_csv(&report),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Requests served with the key per day or hour, from the usage rollups.
+#[utoipa::path(
+    get,
+    path = "/api-keys/{id}/usage",
+    params(("id" = String, Path, description = "API key id"), UsageQuery),
+    responses(
+        (status = 200, description = "Usage", content(
+            (UsageReport = "application/json"),
+            (String = "text/csv")
+        )),
+        (status = 400, description = "Invalid range"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "api-keys",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn get_api_key_usage(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    Path(id): Path<String>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Response, AppError> {
+    let user_id = claims.access_user_id()?;
+    let report =
+        usage_service::for_owner(state.as_ref(), ApiKeyOwner::User(user_id), &id, &query).await?;
+    Ok(usage_response(report, query.format))
+}
@@ -1,16 +1,33 @@
 use crate::{
-    auth::jwt::AuthClaims,
+    auth::{
+        jwt::{decode_token, AuthClaims},
+        scopes::{ApiKeysScope, RequireScopes},
+        tokens::ClientDevice,
+    },
+    dto::audit::{AuditPage, AuditPageQuery},
     dto::auth::{
-        LoginRequest, LoginResponse, RefreshRequest, RefreshResponse, RegisterRequest,
-        RegisterResponse, RotateApiKeyResponse,
+        ForgotPasswordRequest, LoginRequest, LoginResponse, RefreshRequest, RefreshResponse,
+        RegisterRequest, RegisterResponse, ResendVerificationRequest, ResetPasswordRequest,
+        RotateApiKeyResponse, VerifyEmailRequest,
     },
+    dto::org::SwitchOrganizationRequest,
     errors::AppError,
-    models::api_key::ApiKeyPublic,
-    services::auth_service,
+    models::{refresh_token::SessionPublic, user::UserPublic},
+    services::{
+        audit_service,
+        auth_service::{self, LoginOutput},
+        email_service, org_service, password_service,
+    },
     state::AppState,
 };
-use axum::{extract::State, Json};
-use mongodb::bson::oid::ObjectId;
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
 use std::sync::Arc; // ← КЛЮЧЕВОЙ ИМПОРТ для Cursor.try_collect()
 
 #[utoipa::path(
@@ -26,16 +43,25 @@ use std::sync::Arc; // ← КЛЮЧЕВОЙ ИМПОРТ для Cursor.try_colle
 )]
 pub async fn register(
     State(state): State<Arc<AppState>>,
+    device: ClientDevice,
     Json(req): Json<RegisterRequest>,
 ) -> Result<Json<RegisterResponse>, AppError> {
-    let out = auth_service::register(state.as_ref(), req).await?;
+    let out = auth_service::register(state.as_ref(), req, &device).await?;
+    let (access_token, refresh_token, token_type) = match out.tokens {
+        Some(t) => (
+            Some(t.access_token),
+            Some(t.refresh_token),
+            Some(t.token_type),
+        ),
+        None => (None, None, None),
+    };
 
     Ok(Json(RegisterResponse {
         user: out.user,
         api_key: out.api_key,
-        access_token: out.tokens.access_token,
-        refresh_token: out.tokens.refresh_token,
-        token_type: out.tokens.token_type,
+        access_token,
+        refresh_token,
+        token_type,
     }))
 }
 
@@ -44,22 +70,35 @@ pub async fn register(
     path = "/login",
     request_body = LoginRequest,
     responses(
-        (status = 200, description = "Logged in", body = LoginResponse),
-        (status = 401, description = "Unauthorized")
+        (status = 200, description = "Logged in, or `mfa_token` if TOTP is on", body = LoginResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Email not verified (REQUIRE_VERIFIED_EMAIL)"),
+        (status = 423, description = "Account temporarily locked (Retry-After)"),
+        (status = 429, description = "Too many failed attempts from this client (Retry-After)")
     ),
     tag = "auth"
 )]
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    device: ClientDevice,
     Json(req): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, AppError> {
-    let tokens = auth_service::login(state.as_ref(), req).await?;
+    let out = match auth_service::login(state.as_ref(), req, &device).await? {
+        LoginOutput::Tokens(tokens) => LoginResponse {
+            access_token: Some(tokens.access_token),
+            refresh_token: Some(tokens.refresh_token),
+            token_type: Some(tokens.token_type),
+            mfa_token: None,
+        },
+        LoginOutput::MfaRequired(mfa_token) => LoginResponse {
+            access_token: None,
+            refresh_token: None,
+            token_type: None,
+            mfa_token: Some(mfa_token),
+        },
+    };
 
-    Ok(Json(LoginResponse {
-        access_token: tokens.access_token,
-        refresh_token: tokens.refresh_token,
-        token_type: tokens.token_type,
-    }))
+    Ok(Json(out))
 }
 
 #[utoipa::path(
@@ -75,11 +114,7 @@ pub async fn me(
     State(state): State<Arc<AppState>>,
     AuthClaims(claims): AuthClaims,
 ) -> Result<Json<crate::models::user::UserPublic>, AppError> {
-    if claims.typ != "access" {
-        return Err(AppError::Unauthorized);
-    }
-
-    let user_id = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)?;
+    let user_id = claims.access_user_id()?;
     let me = auth_service::me(state.as_ref(), user_id).await?;
     Ok(Json(me))
 }
@@ -97,9 +132,10 @@ pub async fn me(
 )]
 pub async fn refresh(
     State(state): State<Arc<AppState>>,
+    device: ClientDevice,
     Json(req): Json<RefreshRequest>,
 ) -> Result<Json<RefreshResponse>, AppError> {
-    let tokens = auth_service::refresh(state.as_ref(), req).await?;
+    let tokens = auth_service::refresh(state.as_ref(), req, &device).await?;
 
     Ok(Json(RefreshResponse {
         access_token: tokens.access_token,
@@ -108,6 +144,34 @@ pub async fn refresh(
     }))
 }
 
+/// Refresh that also switches the session's active organization (`org_id`,
+/// `org_role` claims); `org_id: null` switches back to personal.
+#[utoipa::path(
+    post,
+    path = "/switch-org",
+    request_body = SwitchOrganizationRequest,
+    responses(
+        (status = 200, description = "Tokens for the organization", body = RefreshResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not a member of the organization")
+    ),
+    tag = "auth"
+)]
+pub async fn switch_org(
+    State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    Json(req): Json<SwitchOrganizationRequest>,
+) -> Result<Json<RefreshResponse>, AppError> {
+    let tokens = org_service::switch(state.as_ref(), req, &device).await?;
+
+    Ok(Json(RefreshResponse {
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        token_type: tokens.token_type,
+    }))
+}
+
+/// Revokes the refresh token and its session; a Bearer access token, if sent, is revoked too.
 #[utoipa::path(
     post,
     path = "/logout",
@@ -115,35 +179,228 @@ pub async fn refresh(
     responses(
         (status = 200, description = "Logged out", body = serde_json::Value)
     ),
-    tag = "auth"
+    tag = "auth",
+    security((), ("bearerAuth" = [])),
 )]
 pub async fn logout(
     State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
     Json(req): Json<RefreshRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    auth_service::logout(state.as_ref(), req).await?;
+    // the access token the client is holding stops working as well
+    if let Some(TypedHeader(Authorization(bearer))) = bearer
+        && let Ok(data) = decode_token(&state.keyring, bearer.token()).await
+        && data.claims.typ == "access"
+    {
+        state.denylist.revoke_token(&data.claims).await?;
+    }
+
+    auth_service::logout(state.as_ref(), req, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sessions",
+    responses(
+        (status = 200, description = "Active sessions, most recently used first", body = Vec<SessionPublic>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token of an OAuth client")
+    ),
+    tag = "auth",
+    security(("bearerAuth" = [])),
+)]
+pub async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+) -> Result<Json<Vec<SessionPublic>>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        auth_service::list_sessions(state.as_ref(), user_id).await?,
+    ))
+}
+
+/// Signs a device out: its refresh token stops working (the access token lives until `exp`).
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "Revoked", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "auth",
+    security(("bearerAuth" = [])),
+)]
+pub async fn revoke_session(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    auth_service::revoke_session(state.as_ref(), user_id, &id, &device).await?;
     Ok(Json(serde_json::json!({ "status": "ok" })))
 }
 
+#[utoipa::path(
+    post,
+    path = "/logout-all",
+    responses(
+        (status = 200, description = "All sessions revoked", body = serde_json::Value),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "auth",
+    security(("bearerAuth" = [])),
+)]
+pub async fn logout_all(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    let revoked = auth_service::logout_all(state.as_ref(), user_id, &device).await?;
+    Ok(Json(
+        serde_json::json!({ "status": "ok", "revoked": revoked }),
+    ))
+}
+
 #[utoipa::path(
     post,
     path = "/api-key/rotate",
     responses(
         (status = 200, description = "API key returned", body = RotateApiKeyResponse),
-        (status = 401, description = "Unauthorized")
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope")
     ),
-    tag = "auth"
+    tag = "auth",
+    security(("bearerAuth" = ["api_keys"])),
 )]
 pub async fn rotate_api_key(
     State(state): State<Arc<AppState>>,
-    AuthClaims(claims): AuthClaims,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
 ) -> Result<Json<RotateApiKeyResponse>, AppError> {
-    if claims.typ != "access" {
-        return Err(AppError::Unauthorized);
-    }
-
-    let user_id = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)?;
-    let api_key = auth_service::reveal_api_key(state.as_ref(), user_id).await?;
+    let user_id = claims.access_user_id()?;
+    let api_key = auth_service::reveal_api_key(state.as_ref(), user_id, &device).await?;
 
     Ok(Json(RotateApiKeyResponse { api_key }))
 }
+
+#[utoipa::path(
+    post,
+    path = "/email/verify",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "Email verified", body = UserPublic),
+        (status = 400, description = "Invalid or expired token")
+    ),
+    tag = "auth"
+)]
+pub async fn verify_email(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<VerifyEmailRequest>,
+) -> Result<Json<UserPublic>, AppError> {
+    let user = email_service::confirm(state.as_ref(), &req.token).await?;
+    Ok(Json(user))
+}
+
+/// Target of the mailed link.
+#[utoipa::path(
+    get,
+    path = "/email/verify",
+    params(VerifyEmailRequest),
+    responses(
+        (status = 200, description = "Email verified", body = UserPublic),
+        (status = 400, description = "Invalid or expired token")
+    ),
+    tag = "auth"
+)]
+pub async fn verify_email_link(
+    State(state): State<Arc<AppState>>,
+    Query(req): Query<VerifyEmailRequest>,
+) -> Result<Json<UserPublic>, AppError> {
+    let user = email_service::confirm(state.as_ref(), &req.token).await?;
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    post,
+    path = "/email/resend",
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 200, description = "Sent if the address belongs to an unverified user", body = serde_json::Value)
+    ),
+    tag = "auth"
+)]
+pub async fn resend_verification(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ResendVerificationRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    email_service::resend(state.as_ref(), req).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/password/forgot",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 200, description = "Reset email sent if the address is registered", body = serde_json::Value)
+    ),
+    tag = "auth"
+)]
+pub async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ForgotPasswordRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    password_service::forgot(state.as_ref(), req).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Sets a new password; all refresh tokens of the user are revoked.
+#[utoipa::path(
+    post,
+    path = "/password/reset",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 200, description = "Password changed", body = serde_json::Value),
+        (status = 400, description = "Invalid or expired token")
+    ),
+    tag = "auth"
+)]
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    Json(req): Json<ResetPasswordRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    password_service::reset(state.as_ref(), req, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Security events of the current user's account (logins, failed logins, sessions, keys).
+#[utoipa::path(
+    get,
+    path = "/audit",
+    params(AuditPageQuery),
+    responses(
+        (status = 200, description = "Events, newest first", body = AuditPage),
+        (status = 400, description = "Invalid cursor"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token of an OAuth client")
+    ),
+    tag = "auth",
+    security(("bearerAuth" = [])),
+)]
+pub async fn list_audit_events(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    Query(query): Query<AuditPageQuery>,
+) -> Result<Json<AuditPage>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        audit_service::list_for_user(state.as_ref(), user_id, query).await?,
+    ))
+}
@@ -2,13 +2,31 @@ use std::sync::Arc;
 
 use axum::extract::State;
 use axum::Json;
-use mongodb::bson::{doc, DateTime as BsonDateTime};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
 
-use crate::auth::jwt::{decode_token, sha256_hex};
+use crate::auth::jwt::{decode_token, sha256_hex, Claims};
+use crate::auth::scopes::split_scope;
 use crate::dto::auth::{IntrospectRequest, IntrospectResponse};
 use crate::errors::AppError;
+use crate::models::api_key::ApiKeyOwner;
+use crate::models::membership::MembershipDoc;
 use crate::state::AppState;
 
+/// Membership behind the token's `org_id` claim, checked live so that a removed
+/// member's tokens stop being scoped to the organization before they expire.
+async fn active_membership(
+    state: &AppState,
+    claims: &Claims,
+) -> Result<Option<MembershipDoc>, AppError> {
+    let (Some(org_id), Ok(user_id)) = (claims.org_id.as_deref(), claims.access_user_id()) else {
+        return Ok(None);
+    };
+    let Ok(org_id) = ObjectId::parse_str(org_id) else {
+        return Ok(None);
+    };
+    state.memberships.find(org_id, user_id).await
+}
+
 #[utoipa::path(
     post,
     path = "/introspect",
@@ -23,100 +41,85 @@ pub async fn introspect(
     let token = req.token.trim();
 
     if token.is_empty() {
-        return Ok(Json(IntrospectResponse {
-            active: false,
-            sub: None,
-            token_type: None,
-            scopes: None,
-        }));
+        return Ok(Json(IntrospectResponse::inactive()));
     }
 
     // 1) JWT path (looks like header.payload.signature)
-    if token.matches('.').count() == 2 {
-        if let Ok(data) = decode_token(token) {
-            let claims = data.claims;
-
-            // refresh: must exist in DB and not be revoked/expired
-            if claims.typ == "refresh" {
-                let token_hash = sha256_hex(token);
-
-                let db_rt = state
-                    .refresh_tokens
-                    .find_one(doc! { "token_hash": &token_hash })
-                    .await?;
-
-                let active = db_rt.as_ref().is_some_and(|rt| {
-                    rt.revoked_at.is_none() && rt.expires_at > BsonDateTime::now()
-                });
-
-                if active {
-                    return Ok(Json(IntrospectResponse {
-                        active: true,
-                        sub: Some(claims.sub),
-                        token_type: Some("refresh".to_string()),
-                        scopes: None,
-                    }));
-                }
-
-                // RFC7662 style: for inactive token return active=false (лучше без лишних полей)
-                return Ok(Json(IntrospectResponse {
-                    active: false,
-                    sub: None,
-                    token_type: None,
-                    scopes: None,
-                }));
-            }
+    if token.matches('.').count() == 2
+        && let Ok(data) = decode_token(&state.keyring, token).await
+    {
+        let claims = data.claims;
+
+        // refresh: must exist in DB and not be revoked/expired
+        if claims.typ == "refresh" {
+            let token_hash = sha256_hex(token);
+
+            let db_rt = state.refresh_tokens.find_by_hash(&token_hash).await?;
+
+            let active = db_rt
+                .as_ref()
+                .is_some_and(|rt| rt.revoked_at.is_none() && rt.expires_at > BsonDateTime::now());
 
-            // access: signature+exp already verified by decode_token()
-            if claims.typ == "access" {
+            if active {
                 return Ok(Json(IntrospectResponse {
                     active: true,
                     sub: Some(claims.sub),
-                    token_type: Some("access".to_string()),
-                    scopes: None,
+                    token_type: Some("refresh".to_string()),
+                    client_id: db_rt.and_then(|rt| rt.client_id),
+                    ..Default::default()
                 }));
             }
 
-            // unknown typ but valid JWT
+            // RFC7662 style: for inactive token return active=false (лучше без лишних полей)
+            return Ok(Json(IntrospectResponse::inactive()));
+        }
+
+        // access: signature+exp already verified by decode_token(), then the denylist
+        if claims.typ == "access" && !state.denylist.is_revoked(&claims)? {
+            let membership = active_membership(&state, &claims).await?;
             return Ok(Json(IntrospectResponse {
                 active: true,
                 sub: Some(claims.sub),
-                token_type: Some(claims.typ),
-                scopes: None,
+                token_type: Some("access".to_string()),
+                scopes: claims.scope.as_deref().map(split_scope),
+                client_id: claims.client_id,
+                roles: (!claims.roles.is_empty()).then_some(claims.roles),
+                permissions: (!claims.permissions.is_empty()).then_some(claims.permissions),
+                org_id: membership.as_ref().map(|m| m.org_id.to_hex()),
+                org_role: membership.map(|m| m.role.as_str().to_string()),
             }));
         }
+
+        if claims.typ == "access" {
+            return Ok(Json(IntrospectResponse::inactive()));
+        }
+
+        // unknown typ but valid JWT
+        return Ok(Json(IntrospectResponse {
+            active: true,
+            sub: Some(claims.sub),
+            token_type: Some(claims.typ),
+            ..Default::default()
+        }));
     }
 
     // 2) API key path (plaintext api key)
     let key_hash = sha256_hex(token);
 
-    let key = state
-        .api_keys
-        .find_one(doc! {
-            "key_hash": &key_hash,
-            "active": true,
-            "$or": [
-                { "expires_at": mongodb::bson::Bson::Null },
-                { "expires_at": { "$exists": false } },
-                { "expires_at": { "$gt": BsonDateTime::now() } }
-            ]
-        })
-        .await?;
+    let key = state.api_keys.find_active_by_hash(&key_hash).await?;
 
     if let Some(key) = key {
         return Ok(Json(IntrospectResponse {
             active: true,
-            sub: Some(key.user_id.to_hex()),
+            sub: Some(match key.owner {
+                ApiKeyOwner::User(id) | ApiKeyOwner::Org(id) => id.to_hex(),
+            }),
             token_type: Some("api_key".to_string()),
             scopes: Some(key.scopes),
+            org_id: key.owner.org_id().map(|id| id.to_hex()),
+            ..Default::default()
         }));
     }
 
-    Ok(Json(IntrospectResponse {
-        active: false,
-        sub: None,
-        token_type: None,
-        scopes: None,
-    }))
+    Ok(Json(IntrospectResponse::inactive()))
 }
-
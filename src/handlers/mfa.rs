@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+
+use crate::{
+    auth::{jwt::AuthClaims, tokens::ClientDevice},
+    dto::auth::{
+        LoginResponse, MfaLoginRequest, MfaStatusResponse, RecoveryCodesResponse, TotpCodeRequest,
+        TotpSetupResponse,
+    },
+    errors::AppError,
+    services::mfa_service,
+    state::AppState,
+};
+
+/// Second login step: `mfa_token` from `/auth/login` plus a TOTP or recovery code.
+#[utoipa::path(
+    post,
+    path = "/login/mfa",
+    request_body = MfaLoginRequest,
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 400, description = "Invalid or expired mfa_token"),
+        (status = 401, description = "Invalid code (log in again)"),
+        (status = 423, description = "Account temporarily locked"),
+        (status = 429, description = "Too many failed attempts from this client")
+    ),
+    tag = "mfa"
+)]
+pub async fn login_mfa(
+    State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    Json(req): Json<MfaLoginRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let tokens = mfa_service::complete_login(state.as_ref(), req, &device).await?;
+
+    Ok(Json(LoginResponse {
+        access_token: Some(tokens.access_token),
+        refresh_token: Some(tokens.refresh_token),
+        token_type: Some(tokens.token_type),
+        mfa_token: None,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/mfa",
+    responses(
+        (status = 200, description = "Second factor state", body = MfaStatusResponse),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "mfa",
+    security(("bearerAuth" = [])),
+)]
+pub async fn mfa_status(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+) -> Result<Json<MfaStatusResponse>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(mfa_service::status(state.as_ref(), user_id).await?))
+}
+
+/// Starts enrollment: add the secret to an authenticator app, then confirm with `/mfa/totp/enable`.
+#[utoipa::path(
+    post,
+    path = "/mfa/totp/setup",
+    responses(
+        (status = 200, description = "New secret", body = TotpSetupResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 409, description = "TOTP already enabled")
+    ),
+    tag = "mfa",
+    security(("bearerAuth" = [])),
+)]
+pub async fn totp_setup(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+) -> Result<Json<TotpSetupResponse>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(mfa_service::setup(state.as_ref(), user_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/mfa/totp/enable",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "TOTP enabled, recovery codes returned once", body = RecoveryCodesResponse),
+        (status = 400, description = "Invalid code"),
+        (status = 401, description = "Unauthorized"),
+        (status = 409, description = "No pending setup, or TOTP already enabled")
+    ),
+    tag = "mfa",
+    security(("bearerAuth" = [])),
+)]
+pub async fn totp_enable(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        mfa_service::enable(state.as_ref(), user_id, &req.code, &device).await?,
+    ))
+}
+
+/// Accepts a TOTP or a recovery code.
+#[utoipa::path(
+    post,
+    path = "/mfa/totp/disable",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "TOTP disabled", body = serde_json::Value),
+        (status = 400, description = "Invalid code"),
+        (status = 401, description = "Unauthorized"),
+        (status = 409, description = "TOTP not enabled")
+    ),
+    tag = "mfa",
+    security(("bearerAuth" = [])),
+)]
+pub async fn totp_disable(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    mfa_service::disable(state.as_ref(), user_id, &req.code, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Replaces all recovery codes; accepts a TOTP code.
+#[utoipa::path(
+    post,
+    path = "/mfa/recovery-codes",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "New recovery codes, returned once", body = RecoveryCodesResponse),
+        (status = 400, description = "Invalid code"),
+        (status = 401, description = "Unauthorized"),
+        (status = 409, description = "TOTP not enabled")
+    ),
+    tag = "mfa",
+    security(("bearerAuth" = [])),
+)]
+pub async fn regenerate_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    let codes =
+        mfa_service::regenerate_recovery_codes(state.as_ref(), user_id, &req.code, &device).await?;
+    Ok(Json(codes))
+}
@@ -1,3 +1,10 @@
+pub mod admin;
 pub mod api;
+pub mod api_keys;
 pub mod auth;
 pub mod introspect;
+pub mod mfa;
+pub mod oauth;
+pub mod orgs;
+pub mod passkeys;
+pub mod well_known;
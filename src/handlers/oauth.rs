@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+
+use axum_extra::{
+    headers::{authorization::Basic, Authorization},
+    TypedHeader,
+};
+use percent_encoding::percent_decode_str;
+
+use crate::{
+    auth::{
+        jwt::AuthClaims,
+        scopes::{split_scope, OpenIdScope, RequireScopes},
+        tokens::ClientDevice,
+    },
+    dto::oauth::{AuthorizeForm, AuthorizeRequest, TokenRequest, TokenResponse, UserInfoResponse},
+    errors::AppError,
+    models::audit_event::{AuditAction, AuditOutcome},
+    models::oauth_client::OAuthClientDoc,
+    services::{
+        audit_service::AuditEntry, auth_service, lockout_service, mfa_service, oauth_service,
+    },
+    state::AppState,
+};
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Minimal login page; the authorization request travels in hidden fields.
+fn login_page(client: &OAuthClientDoc, req: &AuthorizeRequest, error: Option<&str>) -> Response {
+    let fields = [
+        ("response_type", req.response_type.as_deref()),
+        ("client_id", Some(req.client_id.as_str())),
+        ("redirect_uri", Some(req.redirect_uri.as_str())),
+        ("scope", req.scope.as_deref()),
+        ("state", req.state.as_deref()),
+        ("code_challenge", req.code_challenge.as_deref()),
+        (
+            "code_challenge_method",
+            req.code_challenge_method.as_deref(),
+        ),
+        ("nonce", req.nonce.as_deref()),
+    ];
+    let hidden: String = fields
+        .iter()
+        .filter_map(|(name, value)| {
+            value.map(|v| {
+                format!(
+                    r#"<input type="hidden" name="{name}" value="{}">"#,
+                    escape_html(v)
+                )
+            })
+        })
+        .collect();
+    let error = error
+        .map(|e| format!(r#"<p class="error">{}</p>"#, escape_html(e)))
+        .unwrap_or_default();
+
+    let body = format!(
+        r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>Sign in</title></head>
+<body>
+<h1>Sign in to {client}</h1>
+{error}
+<form method="post" action="authorize">
+{hidden}
+<label>Email <input type="email" name="email" required autofocus></label>
+<label>Password <input type="password" name="password" required></label>
+<label>Authentication code (if enabled) <input type="text" name="code" autocomplete="one-time-code"></label>
+<button type="submit">Sign in</button>
+</form>
+</body>
+</html>"#,
+        client = escape_html(&client.name),
+    );
+
+    // no framing: the page collects credentials
+    ([(header::X_FRAME_OPTIONS, "DENY")], Html(body)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/authorize",
+    params(AuthorizeRequest),
+    responses(
+        (status = 200, description = "Login page", content_type = "text/html"),
+        (status = 303, description = "Redirect to redirect_uri with error"),
+        (status = 400, description = "Unknown client or redirect_uri")
+    ),
+    tag = "oauth"
+)]
+pub async fn authorize(
+    State(state): State<Arc<AppState>>,
+    Query(req): Query<AuthorizeRequest>,
+) -> Result<Response, AppError> {
+    let client = oauth_service::resolve_client(&state, &req.client_id, &req.redirect_uri).await?;
+
+    if let Err(e) = oauth_service::validate_authorize(&client, &req) {
+        let location = oauth_service::error_redirect(&req, e)?;
+        return Ok(Redirect::to(&location).into_response());
+    }
+
+    Ok(login_page(&client, &req, None))
+}
+
+#[utoipa::path(
+    post,
+    path = "/authorize",
+    request_body(content = AuthorizeForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirect to redirect_uri with code (or error)"),
+        (status = 400, description = "Unknown client or redirect_uri"),
+        (status = 401, description = "Login page with an error", content_type = "text/html")
+    ),
+    tag = "oauth"
+)]
+pub async fn authorize_login(
+    State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    Form(form): Form<AuthorizeForm>,
+) -> Result<Response, AppError> {
+    let req = &form.request;
+    let client = oauth_service::resolve_client(&state, &req.client_id, &req.redirect_uri).await?;
+
+    let scopes = match oauth_service::validate_authorize(&client, req) {
+        Ok(scopes) => scopes,
+        Err(e) => {
+            let location = oauth_service::error_redirect(req, e)?;
+            return Ok(Redirect::to(&location).into_response());
+        }
+    };
+
+    let user = match auth_service::authenticate(&state, &form.email, &form.password, &device).await
+    {
+        Ok(user) => user,
+        Err(AppError::Unauthorized | AppError::Validation(_)) => {
+            let page = login_page(&client, req, Some("Invalid email or password"));
+            return Ok((StatusCode::UNAUTHORIZED, page).into_response());
+        }
+        Err(AppError::Forbidden(msg)) => {
+            let page = login_page(&client, req, Some(&msg));
+            return Ok((StatusCode::FORBIDDEN, page).into_response());
+        }
+        Err(e @ (AppError::Locked(_) | AppError::Throttled(_))) => {
+            let status = e.into_response().status();
+            let page = login_page(
+                &client,
+                req,
+                Some("Too many failed attempts, try again later"),
+            );
+            return Ok((status, page).into_response());
+        }
+        Err(e) => return Err(e),
+    };
+
+    match mfa_service::check_login_code(&state, user.id, form.code.as_deref()).await {
+        Ok(()) => lockout_service::record_success(&state, &user.email).await?,
+        Err(AppError::Unauthorized) => {
+            AuditEntry::new(AuditAction::Login, AuditOutcome::Failure, &device)
+                .subject(user.id)
+                .target(&user.email)
+                .detail("invalid second factor")
+                .record(&state)
+                .await;
+            lockout_service::record_failure(&state, &user.email, &device).await?;
+            let page = login_page(&client, req, Some("Invalid or missing authentication code"));
+            return Ok((StatusCode::UNAUTHORIZED, page).into_response());
+        }
+        Err(e) => return Err(e),
+    }
+
+    let location = oauth_service::issue_code(&state, &client, req, user.id, scopes).await?;
+    auth_service::record_login(
+        &state,
+        user.id,
+        &format!("oauth:{}", client.client_id),
+        &device,
+    )
+    .await;
+    Ok(Redirect::to(&location).into_response())
+}
+
+/// RFC 6749 §2.3.1: client_id and client_secret are form-urlencoded before they
+/// go into HTTP Basic.
+fn form_decode(value: &str) -> Result<String, AppError> {
+    percent_decode_str(&value.replace('+', " "))
+        .decode_utf8()
+        .map(|s| s.into_owned())
+        .map_err(|_| AppError::OAuth("invalid_client", "malformed client credentials".into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/token",
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Tokens (access only for client_credentials)", body = TokenResponse),
+        (status = 400, description = "OAuth error (invalid_request, invalid_grant, ...)"),
+        (status = 401, description = "invalid_client")
+    ),
+    tag = "oauth"
+)]
+pub async fn token(
+    State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(mut req): Form<TokenRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    // client_secret_basic; only one authentication method per request (RFC 6749 §2.3)
+    if let Some(TypedHeader(Authorization(basic))) = basic {
+        if req.client_secret.is_some() {
+            return Err(AppError::OAuth(
+                "invalid_request",
+                "use either HTTP Basic or client_secret, not both".into(),
+            ));
+        }
+        req.client_id = Some(form_decode(basic.username())?);
+        req.client_secret = Some(form_decode(basic.password())?);
+    }
+
+    let out = oauth_service::token(&state, req, &device).await?;
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(out)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/userinfo",
+    responses(
+        (status = 200, description = "OIDC claims of the token's user", body = UserInfoResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `openid` scope")
+    ),
+    tag = "oauth",
+    security(("bearerAuth" = ["openid"])),
+)]
+pub async fn userinfo(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, OpenIdScope>,
+) -> Result<Json<UserInfoResponse>, AppError> {
+    let user_id = claims.access_user_id()?;
+    let scopes = claims.scope.as_deref().map(split_scope).unwrap_or_default();
+
+    Ok(Json(
+        oauth_service::userinfo(&state, user_id, &scopes).await?,
+    ))
+}
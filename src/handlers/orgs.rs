@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Response,
+    Json,
+};
+
+use crate::{
+    auth::{
+        jwt::AuthClaims,
+        scopes::{ApiKeysScope, RequireScopes},
+        tokens::ClientDevice,
+    },
+    dto::{
+        api_key::{CreateApiKeyRequest, CreateApiKeyResponse, UsageQuery, UsageReport},
+        org::{
+            AddMemberRequest, CreateOrganizationRequest, MemberPublic,
+            OrganizationMembershipPublic, UpdateMemberRequest,
+        },
+    },
+    errors::AppError,
+    handlers::api_keys::usage_response,
+    models::api_key::ApiKeyPublic,
+    services::org_service,
+    state::AppState,
+};
+
+/// Creates an organization; the caller becomes its owner.
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreateOrganizationRequest,
+    responses(
+        (status = 200, description = "Organization created", body = OrganizationMembershipPublic),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token of an OAuth client")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn create_org(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    Json(req): Json<CreateOrganizationRequest>,
+) -> Result<Json<OrganizationMembershipPublic>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        org_service::create(state.as_ref(), user_id, req).await?,
+    ))
+}
+
+/// Organizations the caller belongs to.
+#[utoipa::path(
+    get,
+    path = "",
+    responses(
+        (status = 200, description = "Organizations with the caller's role", body = Vec<OrganizationMembershipPublic>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token of an OAuth client")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn list_orgs(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+) -> Result<Json<Vec<OrganizationMembershipPublic>>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(org_service::list_mine(state.as_ref(), user_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}",
+    params(("id" = String, Path, description = "Organization id")),
+    responses(
+        (status = 200, description = "Organization", body = OrganizationMembershipPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found or not a member")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn get_org(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    Path(id): Path<String>,
+) -> Result<Json<OrganizationMembershipPublic>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(org_service::get(state.as_ref(), user_id, &id).await?))
+}
+
+/// Deletes the organization, its memberships and API keys (owners only).
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    params(("id" = String, Path, description = "Organization id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Not an owner"),
+        (status = 404, description = "Not found or not a member")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn delete_org(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    org_service::delete(state.as_ref(), user_id, &id, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/members",
+    params(("id" = String, Path, description = "Organization id")),
+    responses(
+        (status = 200, description = "Members", body = Vec<MemberPublic>),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found or not a member")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<MemberPublic>>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        org_service::list_members(state.as_ref(), user_id, &id).await?,
+    ))
+}
+
+/// Adds an existing user by email (owners and admins; only owners add owners).
+#[utoipa::path(
+    post,
+    path = "/{id}/members",
+    params(("id" = String, Path, description = "Organization id")),
+    request_body = AddMemberRequest,
+    responses(
+        (status = 200, description = "Member added", body = MemberPublic),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Insufficient organization role"),
+        (status = 404, description = "Organization or user not found"),
+        (status = 409, description = "Already a member")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn add_member(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<AddMemberRequest>,
+) -> Result<Json<MemberPublic>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        org_service::add_member(state.as_ref(), user_id, &id, req, &device).await?,
+    ))
+}
+
+/// Changes a member's role (owners and admins; owners only for the owner role).
+#[utoipa::path(
+    patch,
+    path = "/{id}/members/{user_id}",
+    params(
+        ("id" = String, Path, description = "Organization id"),
+        ("user_id" = String, Path, description = "Member's user id")
+    ),
+    request_body = UpdateMemberRequest,
+    responses(
+        (status = 200, description = "Updated", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Insufficient organization role"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Would leave the organization without an owner")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn update_member(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Path((id, member_id)): Path<(String, String)>,
+    Json(req): Json<UpdateMemberRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    org_service::update_member(state.as_ref(), user_id, &id, &member_id, req, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Removes a member; any member may remove themselves (leave).
+#[utoipa::path(
+    delete,
+    path = "/{id}/members/{user_id}",
+    params(
+        ("id" = String, Path, description = "Organization id"),
+        ("user_id" = String, Path, description = "Member's user id")
+    ),
+    responses(
+        (status = 200, description = "Removed", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Insufficient organization role"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Would leave the organization without an owner")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = [])),
+)]
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Path((id, member_id)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    org_service::remove_member(state.as_ref(), user_id, &id, &member_id, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Creates an organization-owned API key (owners and admins).
+#[utoipa::path(
+    post,
+    path = "/{id}/api-keys",
+    params(("id" = String, Path, description = "Organization id")),
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 200, description = "Key created, plaintext shown once", body = CreateApiKeyResponse),
+        (status = 400, description = "Validation error"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Insufficient scope or organization role"),
+        (status = 404, description = "Not found or not a member")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn create_org_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
+    Path(id): Path<String>,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreateApiKeyResponse>, AppError> {
+    let user_id = claims.access_user_id()?;
+    let created = org_service::create_api_key(state.as_ref(), user_id, &id, req, &device).await?;
+
+    Ok(Json(CreateApiKeyResponse {
+        key: ApiKeyPublic::from(created.key),
+        api_key: created.api_key,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/api-keys",
+    params(("id" = String, Path, description = "Organization id")),
+    responses(
+        (status = 200, description = "Organization's API keys", body = Vec<ApiKeyPublic>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found or not a member")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn list_org_api_keys(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<ApiKeyPublic>>, AppError> {
+    let user_id = claims.access_user_id()?;
+    Ok(Json(
+        org_service::list_api_keys(state.as_ref(), user_id, &id).await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{id}/api-keys/{key_id}",
+    params(
+        ("id" = String, Path, description = "Organization id"),
+        ("key_id" = String, Path, description = "API key id")
+    ),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Insufficient scope or organization role"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn delete_org_api_key(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    device: ClientDevice,
+    Path((id, key_id)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.access_user_id()?;
+    org_service::delete_api_key(state.as_ref(), user_id, &id, &key_id, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/{id}/api-keys/{key_id}/usage",
+    params(
+        ("id" = String, Path, description = "Organization id"),
+        ("key_id" = String, Path, description = "API key id"),
+        UsageQuery
+    ),
+    responses(
+        (status = 200, description = "Usage", content(
+            (UsageReport = "application/json"),
+            (String = "text/csv")
+        )),
+        (status = 400, description = "Invalid range"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Token lacks the `api_keys` scope"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "orgs",
+    security(("bearerAuth" = ["api_keys"])),
+)]
+pub async fn get_org_api_key_usage(
+    State(state): State<Arc<AppState>>,
+    RequireScopes(AuthClaims(claims), _): RequireScopes<AuthClaims, ApiKeysScope>,
+    Path((id, key_id)): Path<(String, String)>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Response, AppError> {
+    let user_id = claims.access_user_id()?;
+    let report = org_service::api_key_usage(state.as_ref(), user_id, &id, &key_id, &query).await?;
+    Ok(usage_response(report, query.format))
+}
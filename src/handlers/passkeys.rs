@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    auth::{jwt::AuthClaims, tokens::ClientDevice},
+    dto::{
+        auth::LoginResponse,
+        passkey::{
+            AssertionCredential, PasskeyLoginOptions, PasskeyRegisterFinishRequest,
+            PasskeyRegistrationOptions,
+        },
+    },
+    errors::AppError,
+    models::passkey::PasskeyPublic,
+    services::passkey_service,
+    state::AppState,
+};
+
+/// Options for `navigator.credentials.create()`.
+#[utoipa::path(
+    post,
+    path = "/passkeys/register/start",
+    responses(
+        (status = 200, description = "Creation options", body = PasskeyRegistrationOptions),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "passkeys",
+    security(("bearerAuth" = [])),
+)]
+pub async fn register_start(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+) -> Result<Json<PasskeyRegistrationOptions>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(
+        passkey_service::register_start(state.as_ref(), user_id).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkeys/register/finish",
+    request_body = PasskeyRegisterFinishRequest,
+    responses(
+        (status = 200, description = "Passkey registered", body = PasskeyPublic),
+        (status = 400, description = "Invalid credential or challenge"),
+        (status = 401, description = "Unauthorized"),
+        (status = 409, description = "Passkey already registered")
+    ),
+    tag = "passkeys",
+    security(("bearerAuth" = [])),
+)]
+pub async fn register_finish(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Json(req): Json<PasskeyRegisterFinishRequest>,
+) -> Result<Json<PasskeyPublic>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    let passkey = passkey_service::register_finish(state.as_ref(), user_id, req, &device).await?;
+    Ok(Json(passkey))
+}
+
+#[utoipa::path(
+    get,
+    path = "/passkeys",
+    responses(
+        (status = 200, description = "Passkeys of the user", body = [PasskeyPublic]),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "passkeys",
+    security(("bearerAuth" = [])),
+)]
+pub async fn list_passkeys(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+) -> Result<Json<Vec<PasskeyPublic>>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    Ok(Json(passkey_service::list(state.as_ref(), user_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/passkeys/{id}",
+    params(("id" = String, Path, description = "Passkey id")),
+    responses(
+        (status = 200, description = "Deleted", body = serde_json::Value),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Not found")
+    ),
+    tag = "passkeys",
+    security(("bearerAuth" = [])),
+)]
+pub async fn delete_passkey(
+    State(state): State<Arc<AppState>>,
+    AuthClaims(claims): AuthClaims,
+    device: ClientDevice,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = claims.first_party_user_id()?;
+    passkey_service::delete(state.as_ref(), user_id, &id, &device).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Options for `navigator.credentials.get()` (discoverable passkeys, no email needed).
+#[utoipa::path(
+    post,
+    path = "/passkeys/login/start",
+    responses((status = 200, description = "Request options", body = PasskeyLoginOptions)),
+    tag = "passkeys"
+)]
+pub async fn login_start(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<PasskeyLoginOptions>, AppError> {
+    Ok(Json(passkey_service::login_start(state.as_ref()).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkeys/login/finish",
+    request_body = AssertionCredential,
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 400, description = "Invalid credential or challenge"),
+        (status = 401, description = "Unknown passkey or bad signature"),
+        (status = 403, description = "Email not verified (REQUIRE_VERIFIED_EMAIL)")
+    ),
+    tag = "passkeys"
+)]
+pub async fn login_finish(
+    State(state): State<Arc<AppState>>,
+    device: ClientDevice,
+    Json(cred): Json<AssertionCredential>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let tokens = passkey_service::login_finish(state.as_ref(), cred, &device).await?;
+
+    Ok(Json(LoginResponse {
+        access_token: Some(tokens.access_token),
+        refresh_token: Some(tokens.refresh_token),
+        token_type: Some(tokens.token_type),
+        mfa_token: None,
+    }))
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::{
+    dto::oauth::OpenIdConfiguration, errors::AppError, services::oauth_service, state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses((status = 200, description = "Public keys for verifying access tokens (RFC 7517)", body = serde_json::Value)),
+    tag = "well-known"
+)]
+pub async fn jwks(State(state): State<Arc<AppState>>) -> Result<Json<JwkSet>, AppError> {
+    Ok(Json(state.keyring.jwks()?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/openid-configuration",
+    responses((status = 200, description = "OpenID Provider Metadata", body = OpenIdConfiguration)),
+    tag = "well-known"
+)]
+pub async fn openid_configuration(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<OpenIdConfiguration>, AppError> {
+    Ok(Json(oauth_service::openid_configuration(&state)?))
+}
@@ -0,0 +1,15 @@
+// src/lib.rs
+pub mod api_key;
+pub mod auth;
+pub mod config;
+pub mod dto;
+pub mod errors;
+pub mod handlers;
+pub mod mail;
+pub mod models;
+pub mod password;
+pub mod rate_limit;
+pub mod routes;
+pub mod services;
+pub mod state;
+pub mod store;
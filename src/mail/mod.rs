@@ -0,0 +1,65 @@
+//! Outgoing mail behind the `Mailer` trait.
+//!
+//! `MAIL_BACKEND=smtp` sends through `SMTP_URL`; `outbox` (default) writes every
+//! message as an `.eml` file to `MAIL_OUTBOX_DIR` for local dev and tests.
+
+pub mod outbox;
+pub mod smtp;
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    Message,
+};
+use std::sync::Arc;
+
+use crate::{
+    config::{Config, MailBackend},
+    errors::AppError,
+};
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    /// Plain text.
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), AppError>;
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, AppError> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|_| AppError::Validation(format!("invalid email address: {}", email.to)))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject.clone())
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|e| AppError::Internal(format!("build email: {e}")))
+}
+
+pub fn from_config(cfg: &Config) -> Result<Arc<dyn Mailer>, AppError> {
+    let from: Mailbox = cfg
+        .mail_from
+        .parse()
+        .map_err(|_| AppError::Internal(format!("invalid MAIL_FROM: {}", cfg.mail_from)))?;
+
+    Ok(match cfg.mail_backend {
+        MailBackend::Smtp => {
+            let url = cfg
+                .smtp_url
+                .as_deref()
+                .ok_or_else(|| AppError::Internal("SMTP_URL is required for smtp".into()))?;
+            Arc::new(smtp::SmtpMailer::new(url, from)?)
+        }
+        MailBackend::Outbox => Arc::new(outbox::OutboxMailer::new(&cfg.mail_outbox_dir, from)),
+    })
+}
@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::message::Mailbox;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    mail::{build_message, Email, Mailer},
+};
+
+/// Writes messages to `<dir>/<timestamp>-<uuid>.eml` instead of sending them.
+pub struct OutboxMailer {
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl OutboxMailer {
+    pub fn new(dir: &str, from: Mailbox) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            from,
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let message = build_message(&self.from, email)?;
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| AppError::Internal(format!("outbox: {e}")))?;
+
+        let name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+            Uuid::new_v4().simple()
+        );
+        let path = self.dir.join(name);
+        tokio::fs::write(&path, message.formatted())
+            .await
+            .map_err(|e| AppError::Internal(format!("outbox: {e}")))?;
+
+        tracing::info!(to = %email.to, path = %path.display(), "email written to outbox");
+        Ok(())
+    }
+}
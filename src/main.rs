@@ -1,19 +1,6 @@
 // src/main.rs
-mod api_key;
-mod auth;
-mod config;
-mod dto;
-mod errors;
-mod handlers;
-mod models;
-mod password;
-mod rate_limit;
-mod routes;
-mod services;
-mod state;
-
-use crate::{config::Config, routes::app_router, state::AppState};
-use std::sync::Arc;
+use auth_service::{config::Config, routes::app_router, services::plan_service, state::AppState};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -32,8 +19,26 @@ async fn main() {
 
     let cfg = Config::from_env();
     let state = Arc::new(AppState::new(cfg).await.expect("init state"));
+    let synced = plan_service::sync_keys(&state)
+        .await
+        .expect("sync API key limits with plans");
+    if synced > 0 {
+        tracing::info!(keys = synced, "API key limits updated from plans");
+    }
+    state.keyring.spawn_reloader(Duration::from_secs(
+        state.cfg.jwt_keys_reload_seconds.max(1),
+    ));
+    state.denylist.spawn_reloader(Duration::from_secs(
+        state.cfg.token_denylist_reload_seconds.max(1),
+    ));
+    state
+        .usage
+        .spawn_flusher(Duration::from_secs(state.cfg.usage_flush_seconds.max(1)));
+    state
+        .quota
+        .spawn_flusher(Duration::from_secs(state.cfg.quota_cache_seconds.max(1)));
 
-    let app = app_router(state)
+    let app = app_router(state.clone())
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http());
 
@@ -42,5 +47,51 @@ async fn main() {
             .await
             .unwrap();
 
-    axum::serve(listener, app).await.unwrap();
+    // peer address for per-IP login limits (`ClientIp`)
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
+
+    // requests counted since the last flush and unused leases
+    if let Err(e) = state.usage.flush().await {
+        tracing::warn!(error = %e, "API key usage flush on shutdown failed");
+    }
+    if let Err(e) = state.quota.release_all().await {
+        tracing::warn!(error = %e, "API key quota release on shutdown failed");
+    }
+}
+
+/// Ctrl-C, or SIGTERM on unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!(error = %e, "failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutting down");
 }
@@ -2,12 +2,20 @@ use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::models::{bson_to_rfc3339, organization::OrganizationDoc, user::UserDoc};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKeyDoc {
     #[serde(rename = "_id")]
     pub id: ObjectId,
 
-    pub user_id: ObjectId,
+    /// Account the key belongs to and acts for.
+    #[serde(flatten)]
+    pub owner: ApiKeyOwner,
+    /// User who created the key (metadata only); `None` for keys created before it
+    /// was recorded.
+    #[serde(default)]
+    pub created_by: Option<ObjectId>,
 
     pub name: String,
 
@@ -19,30 +27,177 @@ pub struct ApiKeyDoc {
     // state
     pub active: bool,
     pub expires_at: Option<BsonDateTime>,
+    /// Bumped whenever the key or its owner changes, so instances holding a cached
+    /// copy reload the owner with their next lease.
+    #[serde(default)]
+    pub generation: i64,
 
-    // throttling/quota
+    // throttling/quota: copied from the owner's plan unless `custom_limits`
+    #[serde(default)]
+    pub plan: Option<String>,
     pub requests_per_minute: i32,
     pub requests_per_day: i64,
+    /// Limits set for this key; plan changes only lower them to the plan's.
+    #[serde(default)]
+    pub custom_limits: bool,
+    /// How `requests_per_minute` is enforced; the day quota is always per UTC day.
+    #[serde(default)]
+    pub quota_algorithm: QuotaAlgorithm,
 
     // usage counters (UTC)
     pub minute_bucket: i64,        // unix_minute
     pub requests_used_minute: i32, // within minute_bucket
+    #[serde(default)]
+    pub requests_prev_minute: i32, // within minute_bucket - 1 (sliding window)
     pub usage_day: i32,            // yyyymmdd (UTC)
     pub requests_used_today: i64,  // within usage_day
 
+    // token bucket: capacity requests_per_minute, refilled at requests_per_minute/60 per
+    // second; `None` means full
+    #[serde(default)]
+    pub bucket_tokens: Option<f64>,
+    #[serde(default)]
+    pub bucket_updated_at: Option<BsonDateTime>,
+
     pub scopes: Vec<String>,
 
     pub created_at: BsonDateTime,
     pub last_used_at: BsonDateTime,
 }
 
+/// Per-minute limit enforcement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaAlgorithm {
+    /// Counter per UTC minute: up to 2x the limit around a minute boundary.
+    #[default]
+    FixedWindow,
+    /// Current minute plus the previous one weighted by how much of it still
+    /// overlaps the last 60 seconds.
+    SlidingWindow,
+    /// Bursts up to the limit, then a steady `requests_per_minute / 60` per second.
+    TokenBucket,
+}
+
+impl QuotaAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::FixedWindow => "fixed_window",
+            Self::SlidingWindow => "sliding_window",
+            Self::TokenBucket => "token_bucket",
+        }
+    }
+}
+
+/// Share of the previous minute still inside the 60 seconds ending at `now`.
+pub fn previous_minute_weight(now: BsonDateTime, minute: i64) -> f64 {
+    let elapsed = (now.timestamp_millis() - minute * 60_000).clamp(0, 60_000);
+    (60_000 - elapsed) as f64 / 60_000.0
+}
+
+/// Who a key belongs to: a user or an organization. Stored as `user_id` or
+/// `org_id` (keys from before owners kept their creator in `user_id` next to
+/// `org_id`; the organization wins).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "StoredOwner", into = "StoredOwner")]
+pub enum ApiKeyOwner {
+    User(ObjectId),
+    Org(ObjectId),
+}
+
+impl ApiKeyOwner {
+    pub fn user_id(self) -> Option<ObjectId> {
+        match self {
+            Self::User(id) => Some(id),
+            Self::Org(_) => None,
+        }
+    }
+
+    pub fn org_id(self) -> Option<ObjectId> {
+        match self {
+            Self::User(_) => None,
+            Self::Org(id) => Some(id),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredOwner {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    org_id: Option<ObjectId>,
+}
+
+impl From<ApiKeyOwner> for StoredOwner {
+    fn from(owner: ApiKeyOwner) -> Self {
+        Self {
+            user_id: owner.user_id(),
+            org_id: owner.org_id(),
+        }
+    }
+}
+
+impl TryFrom<StoredOwner> for ApiKeyOwner {
+    type Error = &'static str;
+
+    fn try_from(s: StoredOwner) -> Result<Self, Self::Error> {
+        match (s.org_id, s.user_id) {
+            (Some(org_id), _) => Ok(Self::Org(org_id)),
+            (None, Some(user_id)) => Ok(Self::User(user_id)),
+            (None, None) => Err("api key without user_id or org_id"),
+        }
+    }
+}
+
+/// The account behind an `ApiKeyOwner`: who requests with the key act as.
+#[derive(Debug, Clone)]
+pub enum ApiKeyAccount {
+    User(UserDoc),
+    Org(OrganizationDoc),
+}
+
+impl ApiKeyAccount {
+    pub fn owner(&self) -> ApiKeyOwner {
+        match self {
+            Self::User(user) => ApiKeyOwner::User(user.id),
+            Self::Org(org) => ApiKeyOwner::Org(org.id),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ApiKeyPublic {
     pub id: String,
     pub name: String,
+    /// Owning organization (`None` for personal keys).
+    pub org_id: Option<String>,
     pub active: bool,
     pub scopes: Vec<String>,
     pub expires_at: Option<String>,
+    pub plan: Option<String>,
     pub requests_per_minute: i32,
     pub requests_per_day: i64,
+    pub quota_algorithm: QuotaAlgorithm,
+    pub created_at: String,
+    pub last_used_at: String,
+}
+
+impl From<ApiKeyDoc> for ApiKeyPublic {
+    fn from(k: ApiKeyDoc) -> Self {
+        Self {
+            id: k.id.to_hex(),
+            name: k.name,
+            org_id: k.owner.org_id().map(|id| id.to_hex()),
+            active: k.active,
+            scopes: k.scopes,
+            expires_at: k.expires_at.map(bson_to_rfc3339),
+            plan: k.plan,
+            requests_per_minute: k.requests_per_minute,
+            requests_per_day: k.requests_per_day,
+            quota_algorithm: k.quota_algorithm,
+            created_at: bson_to_rfc3339(k.created_at),
+            last_used_at: bson_to_rfc3339(k.last_used_at),
+        }
+    }
 }
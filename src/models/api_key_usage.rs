@@ -0,0 +1,72 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::{api_key::ApiKeyOwner, bson_to_rfc3339};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGranularity {
+    Hour,
+    Day,
+}
+
+impl UsageGranularity {
+    /// Stored value (for filters).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    pub fn seconds(self) -> i64 {
+        match self {
+            Self::Hour => 3600,
+            Self::Day => 86_400,
+        }
+    }
+
+    /// Start (UTC) of the period containing `unix_seconds`.
+    pub fn period_start(self, unix_seconds: i64) -> i64 {
+        unix_seconds - unix_seconds.rem_euclid(self.seconds())
+    }
+}
+
+/// Requests served with one key in one hour or UTC day (`api_key_usage` collection).
+/// Kept after the key is deleted, for billing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyUsageDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub key_id: ObjectId,
+    /// Owner of the key, as `user_id` or `org_id`.
+    #[serde(flatten)]
+    pub owner: ApiKeyOwner,
+
+    pub granularity: UsageGranularity,
+    pub period_start: BsonDateTime,
+    pub requests: i64,
+    /// Ids of the last increments added, so a retried write is not counted twice.
+    #[serde(default)]
+    pub applied: Vec<ObjectId>,
+}
+
+/// How many increment ids a rollup remembers; retries come within a flush or two.
+pub const APPLIED_INCREMENTS_KEPT: usize = 100;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UsagePoint {
+    pub period_start: String,
+    pub requests: i64,
+}
+
+impl From<ApiKeyUsageDoc> for UsagePoint {
+    fn from(u: ApiKeyUsageDoc) -> Self {
+        Self {
+            period_start: bson_to_rfc3339(u.period_start),
+            requests: u.requests,
+        }
+    }
+}
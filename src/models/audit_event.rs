@@ -0,0 +1,166 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::bson_to_rfc3339;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// Password, second factor, passkey or OAuth login page (`detail` says which).
+    Login,
+    /// A login key (account or IP) got locked by brute-force protection.
+    LoginLockout,
+    Logout,
+    LogoutAll,
+    SessionRevoke,
+    /// A rotated-out refresh token was presented again.
+    RefreshTokenReuse,
+    PasswordReset,
+    ApiKeyReveal,
+    ApiKeyRotate,
+    /// A key was created or deleted by its owner; `target` is the key id.
+    ApiKeyCreate,
+    ApiKeyDelete,
+    /// Same for organization keys; `detail` names the organization.
+    OrgApiKeyCreate,
+    OrgApiKeyDelete,
+    OrgApiKeyUpdate,
+    OrgApiKeyRevoke,
+    /// Second factor changes by the account owner.
+    TotpEnable,
+    TotpDisable,
+    RecoveryCodesRegenerate,
+    /// `target` is the passkey id.
+    PasskeyAdd,
+    PasskeyRemove,
+    /// Roles or permissions of a user were replaced by an admin.
+    RoleChange,
+    /// Organization membership changes; `target` is the organization id.
+    OrgMemberAdd,
+    OrgMemberUpdate,
+    OrgMemberRemove,
+    OrgDelete,
+    /// Account administration (`actor` is the admin).
+    UserDisable,
+    UserEnable,
+    UserDelete,
+    /// An admin or the owner changed scopes, quotas or the state of a key; `target`
+    /// is the key id.
+    ApiKeyUpdate,
+    ApiKeyRevoke,
+    ApiKeyUsageReset,
+    /// A plan was defined or assigned; `target` is the plan name.
+    PlanChange,
+}
+
+impl AuditAction {
+    /// Stored value (for filters).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::LoginLockout => "login_lockout",
+            Self::Logout => "logout",
+            Self::LogoutAll => "logout_all",
+            Self::SessionRevoke => "session_revoke",
+            Self::RefreshTokenReuse => "refresh_token_reuse",
+            Self::PasswordReset => "password_reset",
+            Self::ApiKeyReveal => "api_key_reveal",
+            Self::ApiKeyRotate => "api_key_rotate",
+            Self::ApiKeyCreate => "api_key_create",
+            Self::ApiKeyDelete => "api_key_delete",
+            Self::OrgApiKeyCreate => "org_api_key_create",
+            Self::OrgApiKeyDelete => "org_api_key_delete",
+            Self::OrgApiKeyUpdate => "org_api_key_update",
+            Self::OrgApiKeyRevoke => "org_api_key_revoke",
+            Self::TotpEnable => "totp_enable",
+            Self::TotpDisable => "totp_disable",
+            Self::RecoveryCodesRegenerate => "recovery_codes_regenerate",
+            Self::PasskeyAdd => "passkey_add",
+            Self::PasskeyRemove => "passkey_remove",
+            Self::RoleChange => "role_change",
+            Self::OrgMemberAdd => "org_member_add",
+            Self::OrgMemberUpdate => "org_member_update",
+            Self::OrgMemberRemove => "org_member_remove",
+            Self::OrgDelete => "org_delete",
+            Self::UserDisable => "user_disable",
+            Self::UserEnable => "user_enable",
+            Self::UserDelete => "user_delete",
+            Self::ApiKeyUpdate => "api_key_update",
+            Self::ApiKeyRevoke => "api_key_revoke",
+            Self::ApiKeyUsageReset => "api_key_usage_reset",
+            Self::PlanChange => "plan_change",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    /// Stored value (for filters).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// Security-relevant event (`audit_events` collection), append-only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEventDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    /// Account the event belongs to (shown to that user); `None` if unknown.
+    pub user_id: Option<ObjectId>,
+    /// Who acted: a user id, `admin`, or `None` (anonymous).
+    pub actor: Option<String>,
+
+    pub action: AuditAction,
+    /// What was acted on: an email, a session or key id, a lockout key.
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    pub detail: Option<String>,
+
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+
+    pub created_at: BsonDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditEventPublic {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub actor: Option<String>,
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+}
+
+impl From<AuditEventDoc> for AuditEventPublic {
+    fn from(e: AuditEventDoc) -> Self {
+        Self {
+            id: e.id.to_hex(),
+            user_id: e.user_id.map(|id| id.to_hex()),
+            actor: e.actor,
+            action: e.action,
+            target: e.target,
+            outcome: e.outcome,
+            detail: e.detail,
+            ip: e.ip,
+            user_agent: e.user_agent,
+            created_at: bson_to_rfc3339(e.created_at),
+        }
+    }
+}
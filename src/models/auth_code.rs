@@ -0,0 +1,28 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Single-use authorization code (`oauth_codes` collection), only its hash is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthCodeDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub code_hash: String,
+
+    pub client_id: String,
+    pub user_id: ObjectId,
+    /// Must be repeated verbatim in the token request.
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+
+    /// PKCE: BASE64URL(SHA256(code_verifier)), method is always S256.
+    pub code_challenge: String,
+    /// OIDC `nonce` from the authorization request.
+    #[serde(default)]
+    pub nonce: Option<String>,
+
+    /// When the user entered their credentials.
+    pub auth_time: BsonDateTime,
+    pub created_at: BsonDateTime,
+    pub expires_at: BsonDateTime,
+}
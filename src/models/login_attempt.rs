@@ -0,0 +1,42 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::bson_to_rfc3339;
+
+/// Failed login counter (`login_attempts` collection) for one key:
+/// `account:<email>` or `ip:<address>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginAttemptDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub key: String,
+
+    /// Consecutive failures within the window.
+    pub failures: i32,
+    pub last_failure_at: BsonDateTime,
+    pub locked_until: Option<BsonDateTime>,
+
+    /// The record (and with it the counter) is forgotten after this.
+    pub expires_at: BsonDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LoginAttemptPublic {
+    pub key: String,
+    pub failures: i32,
+    pub last_failure_at: String,
+    pub locked_until: Option<String>,
+}
+
+impl From<LoginAttemptDoc> for LoginAttemptPublic {
+    fn from(a: LoginAttemptDoc) -> Self {
+        Self {
+            key: a.key,
+            failures: a.failures,
+            last_failure_at: bson_to_rfc3339(a.last_failure_at),
+            locked_until: a.locked_until.map(bson_to_rfc3339),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Role of a user within one organization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    /// Everything, including deleting the organization and managing owners.
+    Owner,
+    /// Manages members (except owners) and organization API keys.
+    Admin,
+    /// Sees the organization, its members and keys.
+    Member,
+}
+
+impl OrgRole {
+    /// Stored value (for filters) and the `org_role` claim.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Admin => "admin",
+            Self::Member => "member",
+        }
+    }
+
+    /// May add/remove members and manage organization API keys.
+    pub fn can_manage(self) -> bool {
+        matches!(self, Self::Owner | Self::Admin)
+    }
+}
+
+/// A user's membership in an organization (`memberships` collection),
+/// unique per (`org_id`, `user_id`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MembershipDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub org_id: ObjectId,
+    pub user_id: ObjectId,
+    pub role: OrgRole,
+
+    pub created_at: BsonDateTime,
+}
@@ -1,3 +1,29 @@
 pub mod api_key;
+pub mod api_key_usage;
+pub mod audit_event;
+pub mod auth_code;
+pub mod login_attempt;
+pub mod membership;
+pub mod oauth_client;
+pub mod organization;
+pub mod passkey;
+pub mod plan;
 pub mod refresh_token;
+pub mod revoked_token;
+pub mod signing_key;
+pub mod totp_factor;
 pub mod user;
+pub mod user_token;
+pub mod webauthn_challenge;
+
+use mongodb::bson::DateTime as BsonDateTime;
+
+pub fn bson_to_rfc3339(dt: BsonDateTime) -> String {
+    // bson::DateTime хранит миллисекунды от epoch; можно перевести в chrono
+    let ms = dt.timestamp_millis();
+    let secs = ms / 1000;
+    let nsec = ((ms % 1000) * 1_000_000) as u32;
+    let chrono_dt = chrono::DateTime::<chrono::Utc>::from_timestamp(secs, nsec)
+        .unwrap_or_else(|| chrono::DateTime::<chrono::Utc>::from_timestamp(0, 0).unwrap());
+    chrono_dt.to_rfc3339()
+}
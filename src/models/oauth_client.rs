@@ -0,0 +1,53 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::bson_to_rfc3339;
+
+/// Registered OAuth 2.0 client (`oauth_clients` collection).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthClientDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub client_id: String,
+    pub name: String,
+
+    /// Exact-match allowlist for `redirect_uri`.
+    pub redirect_uris: Vec<String>,
+    /// Upper bound for scopes a client may request.
+    pub scopes: Vec<String>,
+
+    /// sha256 of the secret; `None` for public clients (SPA, native).
+    #[serde(default)]
+    pub client_secret_hash: Option<String>,
+
+    pub active: bool,
+    pub created_at: BsonDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OAuthClientPublic {
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    /// Has a secret; only confidential clients may use `client_credentials`.
+    pub confidential: bool,
+    pub active: bool,
+    pub created_at: String,
+}
+
+impl From<OAuthClientDoc> for OAuthClientPublic {
+    fn from(c: OAuthClientDoc) -> Self {
+        Self {
+            client_id: c.client_id,
+            name: c.name,
+            redirect_uris: c.redirect_uris,
+            scopes: c.scopes,
+            confidential: c.client_secret_hash.is_some(),
+            active: c.active,
+            created_at: bson_to_rfc3339(c.created_at),
+        }
+    }
+}
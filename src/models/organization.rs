@@ -0,0 +1,39 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::bson_to_rfc3339;
+
+/// Customer organization (`organizations` collection); members are in `memberships`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub name: String,
+    pub created_by: ObjectId,
+    pub created_at: BsonDateTime,
+
+    /// Plan of the organization's API keys; `None` is DEFAULT_PLAN.
+    #[serde(default)]
+    pub plan: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrganizationPublic {
+    pub id: String,
+    pub name: String,
+    pub plan: Option<String>,
+    pub created_at: String,
+}
+
+impl From<OrganizationDoc> for OrganizationPublic {
+    fn from(o: OrganizationDoc) -> Self {
+        Self {
+            id: o.id.to_hex(),
+            name: o.name,
+            plan: o.plan,
+            created_at: bson_to_rfc3339(o.created_at),
+        }
+    }
+}
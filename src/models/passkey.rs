@@ -0,0 +1,46 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::bson_to_rfc3339;
+
+/// WebAuthn credential of a user (`passkeys` collection).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub user_id: ObjectId,
+    pub name: String,
+
+    pub credential_id: String, // base64url, as sent by the browser
+    pub public_key: Vec<u8>,   // COSE_Key (CBOR)
+    pub alg: i64,              // COSE algorithm: -7 ES256 | -8 EdDSA | -257 RS256
+
+    /// Authenticator signature counter, for clone detection (0 = not supported).
+    pub sign_count: i64,
+
+    pub created_at: BsonDateTime,
+    pub last_used_at: Option<BsonDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PasskeyPublic {
+    pub id: String,
+    pub name: String,
+    pub alg: i64,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+impl From<PasskeyDoc> for PasskeyPublic {
+    fn from(p: PasskeyDoc) -> Self {
+        Self {
+            id: p.id.to_hex(),
+            name: p.name,
+            alg: p.alg,
+            created_at: bson_to_rfc3339(p.created_at),
+            last_used_at: p.last_used_at.map(bson_to_rfc3339),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Named API key quota (`plans` collection, on top of the `PLANS` config).
+/// Keys copy the limits of their owner's plan, see `plan_service`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanDoc {
+    #[serde(rename = "_id")]
+    pub name: String,
+
+    pub requests_per_minute: i32,
+    pub requests_per_day: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlanPublic {
+    pub name: String,
+    pub requests_per_minute: i32,
+    pub requests_per_day: i64,
+}
+
+impl From<PlanDoc> for PlanPublic {
+    fn from(p: PlanDoc) -> Self {
+        Self {
+            name: p.name,
+            requests_per_minute: p.requests_per_minute,
+            requests_per_day: p.requests_per_day,
+        }
+    }
+}
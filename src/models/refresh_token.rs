@@ -1,6 +1,11 @@
 use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
+use crate::models::bson_to_rfc3339;
+
+/// One refresh token. Rotation chains them via `replaced_by`; the non-revoked head
+/// of a chain is a session.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RefreshTokenDoc {
     #[serde(rename = "_id")]
@@ -16,4 +21,55 @@ pub struct RefreshTokenDoc {
 
     pub revoked_at: Option<BsonDateTime>,
     pub replaced_by: Option<ObjectId>,
+
+    /// OAuth client the token was issued to (`None` for first-party login).
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Scopes granted to that client; `None` means `JWT_ACCESS_SCOPES`.
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+
+    /// Device the token was issued to (login or the refresh that rotated it).
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// When the chain started (first login); `None` for tokens issued before sessions.
+    #[serde(default)]
+    pub session_started_at: Option<BsonDateTime>,
+    /// Rotation chain the token belongs to: id of its first token, copied on every
+    /// rotation. `None` for tokens issued before families.
+    #[serde(default)]
+    pub family_id: Option<ObjectId>,
+    /// Active organization of the session (`org_id` claim of its access tokens).
+    #[serde(default)]
+    pub org_id: Option<ObjectId>,
+}
+
+/// Active session = current refresh token of a login chain.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionPublic {
+    /// Id of the rotation chain, stable across refreshes (the token id for
+    /// sessions from before families).
+    pub id: String,
+    pub client_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    pub expires_at: String,
+}
+
+impl From<RefreshTokenDoc> for SessionPublic {
+    fn from(t: RefreshTokenDoc) -> Self {
+        Self {
+            id: t.family_id.unwrap_or(t.id).to_hex(),
+            client_id: t.client_id,
+            user_agent: t.user_agent,
+            ip: t.ip,
+            created_at: bson_to_rfc3339(t.session_started_at.unwrap_or(t.created_at)),
+            last_used_at: bson_to_rfc3339(t.created_at),
+            expires_at: bson_to_rfc3339(t.expires_at),
+        }
+    }
 }
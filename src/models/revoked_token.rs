@@ -0,0 +1,21 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Denylist entry for access tokens that have not expired yet (`revoked_tokens`).
+///
+/// `key` is `jti:<jti>` (one token), `sid:<family_id>` (every token of a session) or
+/// `user:<user_id>` (tokens of the user older than `token_version`). Entries live
+/// as long as the tokens they reject.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokedTokenDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub key: String,
+    /// `user:` entries: tokens with a lower `ver` claim are rejected.
+    #[serde(default)]
+    pub token_version: Option<i64>,
+
+    pub created_at: BsonDateTime,
+    pub expires_at: BsonDateTime,
+}
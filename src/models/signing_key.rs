@@ -0,0 +1,47 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::bson_to_rfc3339;
+
+/// JWT signing key of the keyring (`signing_keys` collection).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKeyDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub kid: String,
+    pub alg: String, // "HS256" | "RS256" | "ES256" | "EdDSA" | ...
+
+    // encrypt(PKCS#8 PEM) for asymmetric, encrypt(base64 secret) for HMAC
+    pub key_ciphertext: Vec<u8>,
+    pub key_nonce: [u8; 12],
+
+    /// Signs new tokens; at most one key is active.
+    pub active: bool,
+
+    pub created_at: BsonDateTime,
+    /// Set on rotation; the key keeps verifying for the token lifetime after that.
+    pub retired_at: Option<BsonDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SigningKeyPublic {
+    pub kid: String,
+    pub alg: String,
+    pub active: bool,
+    pub created_at: String,
+    pub retired_at: Option<String>,
+}
+
+impl From<SigningKeyDoc> for SigningKeyPublic {
+    fn from(k: SigningKeyDoc) -> Self {
+        Self {
+            kid: k.kid,
+            alg: k.alg,
+            active: k.active,
+            created_at: bson_to_rfc3339(k.created_at),
+            retired_at: k.retired_at.map(bson_to_rfc3339),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+
+/// TOTP second factor of a user (`totp_factors` collection), at most one per user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpFactorDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub user_id: ObjectId,
+
+    // encrypt(base32 secret)
+    pub secret_ciphertext: Vec<u8>,
+    pub secret_nonce: [u8; 12],
+
+    /// `false` while enrollment is pending (no code confirmed yet).
+    pub enabled: bool,
+
+    /// sha256 of the unused recovery codes.
+    pub recovery_code_hashes: Vec<String>,
+
+    /// Last accepted time step: a code is accepted only once.
+    pub last_used_step: i64,
+
+    pub created_at: BsonDateTime,
+    pub enabled_at: Option<BsonDateTime>,
+}
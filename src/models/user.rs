@@ -2,6 +2,8 @@ use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::models::bson_to_rfc3339;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserDoc {
     #[serde(rename = "_id")]
@@ -13,7 +15,28 @@ pub struct UserDoc {
     pub password_hash: String,
     pub created_at: BsonDateTime,
 
+    #[serde(default)]
+    pub email_verified: bool,
+
     pub default_api_key_id: Option<ObjectId>,
+
+    /// Embedded as `ver` in access tokens; bumping it revokes every issued token.
+    #[serde(default)]
+    pub token_version: i64,
+
+    /// Embedded in first-party access tokens (`roles`, `permissions` claims).
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+
+    /// Set by an admin: no logins, token refreshes or API key calls.
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// Plan of the user's personal API keys; `None` is DEFAULT_PLAN.
+    #[serde(default)]
+    pub plan: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -21,6 +44,11 @@ pub struct UserPublic {
     pub id: String,
     pub email: String,
     pub name: String,
+    pub email_verified: bool,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    pub disabled: bool,
+    pub plan: Option<String>,
     pub created_at: String,
 }
 
@@ -30,16 +58,12 @@ impl From<UserDoc> for UserPublic {
             id: u.id.to_hex(),
             email: u.email,
             name: u.name,
+            email_verified: u.email_verified,
+            roles: u.roles,
+            permissions: u.permissions,
+            disabled: u.disabled,
+            plan: u.plan,
             created_at: bson_to_rfc3339(u.created_at),
         }
     }
 }
-fn bson_to_rfc3339(dt: BsonDateTime) -> String {
-    // bson::DateTime хранит миллисекунды от epoch; можно перевести в chrono
-    let ms = dt.timestamp_millis();
-    let secs = ms / 1000;
-    let nsec = ((ms % 1000) * 1_000_000) as u32;
-    let chrono_dt = chrono::DateTime::<chrono::Utc>::from_timestamp(secs, nsec)
-        .unwrap_or_else(|| chrono::DateTime::<chrono::Utc>::from_timestamp(0, 0).unwrap());
-    chrono_dt.to_rfc3339()
-}
@@ -0,0 +1,36 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserTokenPurpose {
+    EmailVerification,
+    PasswordReset,
+    /// Second login step (TOTP), see `mfa_service`.
+    MfaChallenge,
+}
+
+impl UserTokenPurpose {
+    /// Stored value (for filters).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::EmailVerification => "email_verification",
+            Self::PasswordReset => "password_reset",
+            Self::MfaChallenge => "mfa_challenge",
+        }
+    }
+}
+
+/// Single-use token mailed to a user (`user_tokens` collection), only its hash is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserTokenDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub token_hash: String,
+    pub user_id: ObjectId,
+    pub purpose: UserTokenPurpose,
+
+    pub created_at: BsonDateTime,
+    pub expires_at: BsonDateTime,
+}
@@ -0,0 +1,34 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebAuthnCeremony {
+    Registration,
+    Authentication,
+}
+
+impl WebAuthnCeremony {
+    /// Stored value (for filters).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Registration => "registration",
+            Self::Authentication => "authentication",
+        }
+    }
+}
+
+/// Pending WebAuthn ceremony (`webauthn_challenges` collection), only the challenge hash is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebAuthnChallengeDoc {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub challenge_hash: String,
+    pub ceremony: WebAuthnCeremony,
+    /// Registration only: the signed-in user adding a passkey.
+    pub user_id: Option<ObjectId>,
+
+    pub created_at: BsonDateTime,
+    pub expires_at: BsonDateTime,
+}
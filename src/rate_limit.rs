@@ -1,6 +1,16 @@
-use axum::http::{Request, StatusCode};
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, Request, StatusCode},
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 use tower_governor::{key_extractor::KeyExtractor, GovernorError};
 
+use crate::state::AppState;
+
 pub const API_KEY_HEADER: &str = "x-api-key";
 
 #[derive(Clone, Debug)]
@@ -23,3 +33,37 @@ impl KeyExtractor for ApiKeyExtractor {
         Ok(s.to_owned())
     }
 }
+
+/// Client address for per-IP limits.
+///
+/// With TRUST_FORWARDED_FOR the last `X-Forwarded-For` hop is used (the one our
+/// proxy appended), otherwise the peer address. `None` when the app is served
+/// without connect info (e.g. `Router::oneshot` in tests).
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if state.cfg.trust_forwarded_for
+            && let Some(ip) = parts
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit(',').next())
+                .and_then(|v| v.trim().parse().ok())
+        {
+            return Ok(Self(Some(ip)));
+        }
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(Self(peer))
+    }
+}
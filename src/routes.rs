@@ -1,5 +1,5 @@
 use crate::{rate_limit::ApiKeyExtractor, state::AppState};
-use axum::Router;
+use axum::{middleware, Router};
 use std::sync::Arc;
 use tower_governor::{governor::GovernorConfigBuilder, GovernorLayer};
 use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
@@ -13,10 +13,105 @@ pub fn app_router(state: Arc<AppState>) -> Router {
         .routes(routes!(crate::handlers::auth::register))
         .routes(routes!(crate::handlers::auth::login))
         .routes(routes!(crate::handlers::auth::refresh))
+        .routes(routes!(crate::handlers::auth::switch_org))
         .routes(routes!(crate::handlers::introspect::introspect))
         .routes(routes!(crate::handlers::auth::logout))
+        .routes(routes!(crate::handlers::auth::logout_all))
+        .routes(routes!(crate::handlers::auth::list_sessions))
+        .routes(routes!(crate::handlers::auth::revoke_session))
+        .routes(routes!(crate::handlers::auth::list_audit_events))
         .routes(routes!(crate::handlers::auth::me))
-        .routes(routes!(crate::handlers::auth::rotate_api_key));
+        .routes(routes!(crate::handlers::auth::rotate_api_key))
+        .routes(routes!(
+            crate::handlers::auth::verify_email,
+            crate::handlers::auth::verify_email_link
+        ))
+        .routes(routes!(crate::handlers::auth::resend_verification))
+        .routes(routes!(crate::handlers::auth::forgot_password))
+        .routes(routes!(crate::handlers::auth::reset_password))
+        .routes(routes!(crate::handlers::mfa::login_mfa))
+        .routes(routes!(crate::handlers::mfa::mfa_status))
+        .routes(routes!(crate::handlers::mfa::totp_setup))
+        .routes(routes!(crate::handlers::mfa::totp_enable))
+        .routes(routes!(crate::handlers::mfa::totp_disable))
+        .routes(routes!(crate::handlers::mfa::regenerate_recovery_codes))
+        .routes(routes!(crate::handlers::passkeys::register_start))
+        .routes(routes!(crate::handlers::passkeys::register_finish))
+        .routes(routes!(crate::handlers::passkeys::list_passkeys))
+        .routes(routes!(crate::handlers::passkeys::delete_passkey))
+        .routes(routes!(crate::handlers::passkeys::login_start))
+        .routes(routes!(crate::handlers::passkeys::login_finish))
+        .routes(routes!(
+            crate::handlers::api_keys::create_api_key,
+            crate::handlers::api_keys::list_api_keys
+        ))
+        .routes(routes!(
+            crate::handlers::api_keys::get_api_key,
+            crate::handlers::api_keys::update_api_key,
+            crate::handlers::api_keys::delete_api_key
+        ))
+        .routes(routes!(crate::handlers::api_keys::deactivate_api_key))
+        .routes(routes!(crate::handlers::api_keys::get_api_key_usage));
+
+    // organizations
+    let orgs = OpenApiRouter::new()
+        .routes(routes!(
+            crate::handlers::orgs::create_org,
+            crate::handlers::orgs::list_orgs
+        ))
+        .routes(routes!(
+            crate::handlers::orgs::get_org,
+            crate::handlers::orgs::delete_org
+        ))
+        .routes(routes!(
+            crate::handlers::orgs::list_members,
+            crate::handlers::orgs::add_member
+        ))
+        .routes(routes!(
+            crate::handlers::orgs::update_member,
+            crate::handlers::orgs::remove_member
+        ))
+        .routes(routes!(
+            crate::handlers::orgs::create_org_api_key,
+            crate::handlers::orgs::list_org_api_keys
+        ))
+        .routes(routes!(crate::handlers::orgs::delete_org_api_key))
+        .routes(routes!(crate::handlers::orgs::get_org_api_key_usage));
+
+    // admin (x-admin-token or the admin role)
+    let admin = OpenApiRouter::new()
+        .routes(routes!(crate::handlers::admin::list_jwt_keys))
+        .routes(routes!(crate::handlers::admin::rotate_jwt_key))
+        .routes(routes!(
+            crate::handlers::admin::create_oauth_client,
+            crate::handlers::admin::list_oauth_clients
+        ))
+        .routes(routes!(crate::handlers::admin::list_lockouts))
+        .routes(routes!(crate::handlers::admin::unlock_login))
+        .routes(routes!(crate::handlers::admin::list_audit_events))
+        .routes(routes!(crate::handlers::admin::set_user_roles))
+        .routes(routes!(crate::handlers::admin::list_users))
+        .routes(routes!(crate::handlers::admin::disable_user))
+        .routes(routes!(crate::handlers::admin::enable_user))
+        .routes(routes!(crate::handlers::admin::delete_user))
+        .routes(routes!(crate::handlers::admin::logout_user))
+        .routes(routes!(crate::handlers::admin::list_api_keys))
+        .routes(routes!(crate::handlers::admin::update_api_key))
+        .routes(routes!(crate::handlers::admin::revoke_api_key))
+        .routes(routes!(crate::handlers::admin::reset_api_key_usage))
+        .routes(routes!(crate::handlers::admin::list_plans))
+        .routes(routes!(crate::handlers::admin::upsert_plan))
+        .routes(routes!(crate::handlers::admin::set_user_plan))
+        .routes(routes!(crate::handlers::admin::set_org_plan))
+        .routes(routes!(crate::handlers::admin::get_api_key_usage));
+
+    // oauth (authorization code + PKCE)
+    let oauth = OpenApiRouter::new()
+        .routes(routes!(
+            crate::handlers::oauth::authorize,
+            crate::handlers::oauth::authorize_login
+        ))
+        .routes(routes!(crate::handlers::oauth::token));
 
     // api
     let governor_conf = Arc::new(
@@ -24,18 +119,29 @@ pub fn app_router(state: Arc<AppState>) -> Router {
             .per_second(1)
             .burst_size(10)
             .key_extractor(ApiKeyExtractor)
-            .use_headers()
             .finish()
             .unwrap(),
     );
 
+    // квота ключа снаружи burst-лимита governor: RateLimit-* есть и у его 429,
+    // а отклонённый им (или по scope) запрос возвращается в квоту и не попадает в usage
     let api = OpenApiRouter::new()
         .routes(routes!(crate::handlers::api::ping))
-        .route_layer(GovernorLayer::new(governor_conf));
+        .route_layer(GovernorLayer::new(governor_conf))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            crate::api_key::extractor::api_key_quota,
+        ));
 
     let root = OpenApiRouter::new()
+        .routes(routes!(crate::handlers::well_known::jwks))
+        .routes(routes!(crate::handlers::well_known::openid_configuration))
+        .routes(routes!(crate::handlers::oauth::userinfo))
         .nest("/auth", auth)
+        .nest("/oauth", oauth)
         .nest("/api", api)
+        .nest("/orgs", orgs)
+        .nest("/admin", admin)
         .with_state(state);
 
     let (router, mut openapi): (Router, OpenApi) = root.split_for_parts();
@@ -58,6 +164,12 @@ pub fn app_router(state: Arc<AppState>) -> Router {
         SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
     );
 
+    // Operator token in header x-admin-token
+    components.add_security_scheme(
+        "adminToken",
+        SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-admin-token"))),
+    );
+
     openapi.components = Some(components);
     router.merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", openapi))
 }
@@ -0,0 +1,443 @@
+//! Account and API key administration (`/admin/users/*`, `/admin/api-keys/*`).
+
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    auth::{admin::AdminAuth, roles::normalize_names, tokens::ClientDevice},
+    dto::admin::{
+        AdminApiKeyPublic, AdminApiKeyQuery, AdminUpdateApiKeyRequest, AdminUserQuery, ApiKeyPage,
+        SetRolesRequest, UserPage,
+    },
+    dto::api_key::{UsageQuery, UsageReport},
+    errors::AppError,
+    models::{
+        api_key::ApiKeyOwner,
+        audit_event::{AuditAction, AuditOutcome},
+        membership::OrgRole,
+        user::UserPublic,
+        user_token::UserTokenPurpose,
+    },
+    services::{
+        api_key_service::{normalize_scopes, validate_quota},
+        audit_service::{page_size, parse_id, AuditEntry},
+        auth_service::revoke_all_tokens,
+        plan_service, usage_service,
+    },
+    state::AppState,
+    store::{ApiKeyFilter, ApiKeyPatch, UserFilter},
+};
+
+fn parse_path_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|_| AppError::NotFound)
+}
+
+/// Replaces the user's roles and permissions. Access tokens with the old claims are
+/// revoked; refresh tokens stay valid, so sessions pick up the new roles on refresh.
+pub async fn set_roles(
+    state: &AppState,
+    admin: &AdminAuth,
+    user_id: &str,
+    req: SetRolesRequest,
+    device: &ClientDevice,
+) -> Result<UserPublic, AppError> {
+    let user_id = parse_path_id(user_id)?;
+    let roles = normalize_names(req.roles, "role")?;
+    let permissions = normalize_names(req.permissions, "permission")?;
+
+    let user = state
+        .users
+        .set_roles(user_id, &roles, &permissions)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state
+        .denylist
+        .revoke_user(
+            user_id,
+            user.token_version,
+            state.cfg.jwt_access_ttl_seconds,
+        )
+        .await?;
+
+    AuditEntry::new(AuditAction::RoleChange, AuditOutcome::Success, device)
+        .subject(user_id)
+        .actor(admin.actor())
+        .detail(format!(
+            "roles=[{}] permissions=[{}]",
+            roles.join(","),
+            permissions.join(",")
+        ))
+        .record(state)
+        .await;
+
+    Ok(user.into())
+}
+
+pub async fn list_users(state: &AppState, query: AdminUserQuery) -> Result<UserPage, AppError> {
+    let filter = UserFilter {
+        query: query
+            .q
+            .map(|q| q.trim().to_string())
+            .filter(|q| !q.is_empty()),
+        disabled: query.disabled,
+        role: query
+            .role
+            .map(|r| r.trim().to_ascii_lowercase())
+            .filter(|r| !r.is_empty()),
+        before: parse_id(query.before.as_deref(), "before")?,
+    };
+    let limit = page_size(query.limit);
+
+    let users = state.users.search(&filter, limit).await?;
+    let next_before = (users.len() as i64 == limit)
+        .then(|| users.last().map(|u| u.id.to_hex()))
+        .flatten();
+
+    Ok(UserPage {
+        users: users.into_iter().map(UserPublic::from).collect(),
+        next_before,
+    })
+}
+
+/// Blocks sign-in, token refresh and the user's API keys; all sessions end at once.
+pub async fn disable_user(
+    state: &AppState,
+    admin: &AdminAuth,
+    user_id: &str,
+    device: &ClientDevice,
+) -> Result<UserPublic, AppError> {
+    let user_id = parse_path_id(user_id)?;
+    state
+        .users
+        .set_disabled(user_id, true)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state
+        .api_keys
+        .bump_generation(ApiKeyOwner::User(user_id))
+        .await?;
+    state.quota.evict_owner(ApiKeyOwner::User(user_id))?;
+    let revoked = revoke_all_tokens(state, user_id).await?;
+
+    AuditEntry::new(AuditAction::UserDisable, AuditOutcome::Success, device)
+        .subject(user_id)
+        .actor(admin.actor())
+        .detail(format!("{revoked} sessions ended"))
+        .record(state)
+        .await;
+
+    // re-read: revoking bumped the token version
+    let user = state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(user.into())
+}
+
+pub async fn enable_user(
+    state: &AppState,
+    admin: &AdminAuth,
+    user_id: &str,
+    device: &ClientDevice,
+) -> Result<UserPublic, AppError> {
+    let user_id = parse_path_id(user_id)?;
+    let user = state
+        .users
+        .set_disabled(user_id, false)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state
+        .api_keys
+        .bump_generation(ApiKeyOwner::User(user_id))
+        .await?;
+    state.quota.evict_owner(ApiKeyOwner::User(user_id))?;
+
+    AuditEntry::new(AuditAction::UserEnable, AuditOutcome::Success, device)
+        .subject(user_id)
+        .actor(admin.actor())
+        .record(state)
+        .await;
+    Ok(user.into())
+}
+
+/// Deletes the account with its sessions, personal keys, memberships and second
+/// factors. Organization keys it created stay with their organization. The audit
+/// trail is kept. Refused while the user is the last owner of an organization.
+pub async fn delete_user(
+    state: &AppState,
+    admin: &AdminAuth,
+    user_id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let user_id = parse_path_id(user_id)?;
+    let user = state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let memberships = state.memberships.list_for_user(user_id).await?;
+    for m in memberships.iter().filter(|m| m.role == OrgRole::Owner) {
+        let owners = state
+            .memberships
+            .list_for_org(m.org_id)
+            .await?
+            .into_iter()
+            .filter(|o| o.role == OrgRole::Owner)
+            .count();
+        if owners <= 1 {
+            return Err(AppError::Conflict(format!(
+                "user is the last owner of organization {}",
+                m.org_id.to_hex()
+            )));
+        }
+    }
+
+    revoke_all_tokens(state, user_id).await?;
+    let keys = state
+        .api_keys
+        .delete_all_for_owner(ApiKeyOwner::User(user_id))
+        .await?;
+    state.quota.evict_owner(ApiKeyOwner::User(user_id))?;
+    let created_by = ApiKeyFilter {
+        created_by: Some(user_id),
+        ..Default::default()
+    };
+    let org_keys = state.api_keys.search(&created_by, i64::MAX).await?.len();
+    for m in &memberships {
+        state.memberships.delete(m.org_id, user_id).await?;
+    }
+    state.totp_factors.delete_for_user(user_id).await?;
+    for passkey in state.passkeys.list_for_user(user_id).await? {
+        state.passkeys.delete_for_user(passkey.id, user_id).await?;
+    }
+    for purpose in [
+        UserTokenPurpose::EmailVerification,
+        UserTokenPurpose::PasswordReset,
+        UserTokenPurpose::MfaChallenge,
+    ] {
+        state.user_tokens.delete_for_user(user_id, purpose).await?;
+    }
+    state.users.delete(user_id).await?;
+
+    AuditEntry::new(AuditAction::UserDelete, AuditOutcome::Success, device)
+        .subject(user_id)
+        .actor(admin.actor())
+        .target(&user.email)
+        .detail(format!(
+            "{keys} personal api keys deleted, {org_keys} organization api keys kept"
+        ))
+        .record(state)
+        .await;
+    Ok(())
+}
+
+/// Ends every session of the user; returns how many were ended.
+pub async fn force_logout(
+    state: &AppState,
+    admin: &AdminAuth,
+    user_id: &str,
+    device: &ClientDevice,
+) -> Result<u64, AppError> {
+    let user_id = parse_path_id(user_id)?;
+    state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let revoked = revoke_all_tokens(state, user_id).await?;
+
+    AuditEntry::new(AuditAction::LogoutAll, AuditOutcome::Success, device)
+        .subject(user_id)
+        .actor(admin.actor())
+        .detail(format!("{revoked} sessions ended"))
+        .record(state)
+        .await;
+    Ok(revoked)
+}
+
+pub async fn list_api_keys(
+    state: &AppState,
+    query: AdminApiKeyQuery,
+) -> Result<ApiKeyPage, AppError> {
+    let filter = ApiKeyFilter {
+        user_id: parse_id(query.user_id.as_deref(), "user_id")?,
+        org_id: parse_id(query.org_id.as_deref(), "org_id")?,
+        created_by: parse_id(query.created_by.as_deref(), "created_by")?,
+        active: query.active,
+        before: parse_id(query.before.as_deref(), "before")?,
+    };
+    let limit = page_size(query.limit);
+
+    let keys = state.api_keys.search(&filter, limit).await?;
+    let next_before = (keys.len() as i64 == limit)
+        .then(|| keys.last().map(|k| k.id.to_hex()))
+        .flatten();
+
+    Ok(ApiKeyPage {
+        keys: keys.into_iter().map(AdminApiKeyPublic::from).collect(),
+        next_before,
+    })
+}
+
+async fn patch_api_key(
+    state: &AppState,
+    admin: &AdminAuth,
+    key_id: &str,
+    patch: ApiKeyPatch,
+    action: AuditAction,
+    detail: Option<String>,
+    device: &ClientDevice,
+) -> Result<AdminApiKeyPublic, AppError> {
+    let key = state
+        .api_keys
+        .update(parse_path_id(key_id)?, &patch)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state.quota.evict(&key.key_hash)?;
+
+    let mut entry = AuditEntry::new(action, AuditOutcome::Success, device)
+        .actor(admin.actor())
+        .target(key.id.to_hex());
+    let detail = match key.owner {
+        ApiKeyOwner::User(user_id) => {
+            entry = entry.subject(user_id);
+            detail
+        }
+        ApiKeyOwner::Org(org_id) => Some(
+            [Some(format!("organization {}", org_id.to_hex())), detail]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+    };
+    if let Some(detail) = detail {
+        entry = entry.detail(detail);
+    }
+    entry.record(state).await;
+    Ok(key.into())
+}
+
+/// Changes the key's scopes and quotas; quotas set here are the key's own until
+/// `plan_limits` puts it back on its plan.
+pub async fn update_api_key(
+    state: &AppState,
+    admin: &AdminAuth,
+    key_id: &str,
+    req: AdminUpdateApiKeyRequest,
+    device: &ClientDevice,
+) -> Result<AdminApiKeyPublic, AppError> {
+    validate_quota(req.requests_per_minute, req.requests_per_day)?;
+    let custom = req.requests_per_minute.is_some() || req.requests_per_day.is_some();
+    if custom && req.plan_limits {
+        return Err(AppError::Validation(
+            "plan_limits cannot be combined with custom limits".into(),
+        ));
+    }
+    let scopes = req.scopes.map(normalize_scopes).transpose()?;
+
+    let mut changes = Vec::new();
+    if let Some(scopes) = &scopes {
+        changes.push(format!("scopes=[{}]", scopes.join(",")));
+    }
+    let mut patch = ApiKeyPatch {
+        scopes,
+        requests_per_minute: req.requests_per_minute,
+        requests_per_day: req.requests_per_day,
+        custom_limits: custom.then_some(true),
+        quota_algorithm: req.quota_algorithm,
+        ..Default::default()
+    };
+    if req.plan_limits {
+        let key = state
+            .api_keys
+            .find_by_id(parse_path_id(key_id)?)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let plan = plan_service::for_key(state, key.plan.as_deref(), key.owner).await?;
+        patch.requests_per_minute = Some(plan.requests_per_minute);
+        patch.requests_per_day = Some(plan.requests_per_day);
+        patch.custom_limits = Some(false);
+        changes.push(format!("plan={}", plan.name));
+    }
+    if let Some(rpm) = patch.requests_per_minute {
+        changes.push(format!("requests_per_minute={rpm}"));
+    }
+    if let Some(rpd) = patch.requests_per_day {
+        changes.push(format!("requests_per_day={rpd}"));
+    }
+    if let Some(algorithm) = patch.quota_algorithm {
+        changes.push(format!("quota_algorithm={}", algorithm.as_str()));
+    }
+
+    patch_api_key(
+        state,
+        admin,
+        key_id,
+        patch,
+        AuditAction::ApiKeyUpdate,
+        Some(changes.join(" ")),
+        device,
+    )
+    .await
+}
+
+/// Deactivates the key; its owner can still see it.
+pub async fn revoke_api_key(
+    state: &AppState,
+    admin: &AdminAuth,
+    key_id: &str,
+    device: &ClientDevice,
+) -> Result<AdminApiKeyPublic, AppError> {
+    let patch = ApiKeyPatch {
+        active: Some(false),
+        ..Default::default()
+    };
+    patch_api_key(
+        state,
+        admin,
+        key_id,
+        patch,
+        AuditAction::ApiKeyRevoke,
+        None,
+        device,
+    )
+    .await
+}
+
+/// Zeroes the key's minute and daily counters.
+pub async fn reset_api_key_usage(
+    state: &AppState,
+    admin: &AdminAuth,
+    key_id: &str,
+    device: &ClientDevice,
+) -> Result<AdminApiKeyPublic, AppError> {
+    let patch = ApiKeyPatch {
+        reset_usage: true,
+        ..Default::default()
+    };
+    patch_api_key(
+        state,
+        admin,
+        key_id,
+        patch,
+        AuditAction::ApiKeyUsageReset,
+        None,
+        device,
+    )
+    .await
+}
+
+pub async fn api_key_usage(
+    state: &AppState,
+    key_id: &str,
+    query: &UsageQuery,
+) -> Result<UsageReport, AppError> {
+    let key = state
+        .api_keys
+        .find_by_id(parse_path_id(key_id)?)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    usage_service::report(state, &key, query).await
+}
@@ -0,0 +1,350 @@
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    api_key::{crypto::encrypt_api_key, generate::generate_api_key},
+    auth::{jwt::sha256_hex, tokens::ClientDevice},
+    dto::api_key::{CreateApiKeyRequest, UpdateApiKeyRequest},
+    errors::AppError,
+    models::{
+        api_key::{ApiKeyDoc, ApiKeyOwner, ApiKeyPublic, QuotaAlgorithm},
+        audit_event::{AuditAction, AuditOutcome},
+        plan::PlanDoc,
+    },
+    services::{audit_service::AuditEntry, auth_service::require_non_empty, plan_service},
+    state::AppState,
+    store::ApiKeyPatch,
+};
+
+pub const DEFAULT_SCOPES: &[&str] = &["api"];
+
+/// Parameters of a key to be created (already validated).
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<BsonDateTime>,
+    /// Custom limits; `None` takes the plan's.
+    pub requests_per_minute: Option<i32>,
+    pub requests_per_day: Option<i64>,
+    pub quota_algorithm: QuotaAlgorithm,
+}
+
+impl NewApiKey {
+    pub fn with_defaults(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            scopes: DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect(),
+            expires_at: None,
+            requests_per_minute: None,
+            requests_per_day: None,
+            quota_algorithm: QuotaAlgorithm::default(),
+        }
+    }
+}
+
+pub struct CreatedApiKey {
+    pub key: ApiKeyDoc,
+    pub api_key: String, // plain (показываем только один раз)
+}
+
+fn parse_key_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|_| AppError::NotFound)
+}
+
+fn parse_expires_at(value: &str) -> Result<BsonDateTime, AppError> {
+    let dt = chrono::DateTime::parse_from_rfc3339(value.trim())
+        .map_err(|_| AppError::Validation("expires_at must be an RFC 3339 timestamp".into()))?;
+
+    if dt <= chrono::Utc::now() {
+        return Err(AppError::Validation(
+            "expires_at must be in the future".into(),
+        ));
+    }
+    Ok(BsonDateTime::from_millis(dt.timestamp_millis()))
+}
+
+pub(crate) fn normalize_scopes(scopes: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut out: Vec<String> = Vec::with_capacity(scopes.len());
+    for s in scopes {
+        let s = s.trim().to_string();
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(AppError::Validation(format!("invalid scope: {s:?}")));
+        }
+        if !out.contains(&s) {
+            out.push(s);
+        }
+    }
+    Ok(out)
+}
+
+pub(crate) fn validate_quota(rpm: Option<i32>, rpd: Option<i64>) -> Result<(), AppError> {
+    if rpm.is_some_and(|v| v <= 0) {
+        return Err(AppError::Validation(
+            "requests_per_minute must be positive".into(),
+        ));
+    }
+    if rpd.is_some_and(|v| v <= 0) {
+        return Err(AppError::Validation(
+            "requests_per_day must be positive".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Generates a key on `plan` (the owner's), stores it (hash + ciphertext) and
+/// returns the plaintext once.
+pub async fn insert_new_key(
+    state: &AppState,
+    owner: ApiKeyOwner,
+    created_by: ObjectId,
+    plan: &PlanDoc,
+    new_key: NewApiKey,
+) -> Result<CreatedApiKey, AppError> {
+    let custom_limits = new_key.requests_per_minute.is_some() || new_key.requests_per_day.is_some();
+
+    // In case of extremely rare sha collision / unique index conflict, retry a few times.
+    for attempt in 0..5 {
+        let api_key_plain = generate_api_key();
+        let key_hash = sha256_hex(&api_key_plain);
+        let (ct, nonce) = encrypt_api_key(&api_key_plain)?;
+
+        let key_doc = ApiKeyDoc {
+            id: ObjectId::new(),
+            owner,
+            created_by: Some(created_by),
+            name: new_key.name.clone(),
+            key_hash,
+            key_ciphertext: ct,
+            key_nonce: nonce,
+
+            active: true,
+            expires_at: new_key.expires_at,
+            generation: 0,
+
+            plan: Some(plan.name.clone()),
+            requests_per_minute: new_key
+                .requests_per_minute
+                .unwrap_or(plan.requests_per_minute),
+            requests_per_day: new_key.requests_per_day.unwrap_or(plan.requests_per_day),
+            custom_limits,
+            quota_algorithm: new_key.quota_algorithm,
+
+            // counters initialized to 0, token bucket full
+            minute_bucket: 0,
+            requests_used_minute: 0,
+            requests_prev_minute: 0,
+            usage_day: 0,
+            requests_used_today: 0,
+            bucket_tokens: None,
+            bucket_updated_at: None,
+
+            scopes: new_key.scopes.clone(),
+
+            created_at: BsonDateTime::now(),
+            last_used_at: BsonDateTime::now(),
+        };
+
+        match state.api_keys.insert(&key_doc).await {
+            Ok(()) => {
+                return Ok(CreatedApiKey {
+                    key: key_doc,
+                    api_key: api_key_plain,
+                });
+            }
+            Err(e) => {
+                if attempt == 4 {
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    Err(AppError::Internal("failed to create api key".into()))
+}
+
+/// Records a key created, changed or deleted by `actor` (organization keys under their own actions).
+async fn audit_key(
+    state: &AppState,
+    action: AuditAction,
+    org_action: AuditAction,
+    owner: ApiKeyOwner,
+    actor: ObjectId,
+    key_id: ObjectId,
+    device: &ClientDevice,
+) {
+    let mut entry = match owner {
+        ApiKeyOwner::User(_) => AuditEntry::new(action, AuditOutcome::Success, device),
+        ApiKeyOwner::Org(org_id) => AuditEntry::new(org_action, AuditOutcome::Success, device)
+            .detail(format!("organization {}", org_id.to_hex())),
+    };
+    entry = entry.user(actor).target(key_id.to_hex());
+    entry.record(state).await;
+}
+
+pub async fn create(
+    state: &AppState,
+    owner: ApiKeyOwner,
+    created_by: ObjectId,
+    req: CreateApiKeyRequest,
+    device: &ClientDevice,
+) -> Result<CreatedApiKey, AppError> {
+    let name = req.name.trim();
+    require_non_empty(name, "name")?;
+    let plan = plan_service::for_owner(state, owner).await?;
+    plan_service::check_key_limits(&plan, req.requests_per_minute, req.requests_per_day)?;
+
+    let mut new_key = NewApiKey::with_defaults(name);
+    if let Some(scopes) = req.scopes {
+        new_key.scopes = normalize_scopes(scopes)?;
+    }
+    if let Some(exp) = req.expires_at {
+        new_key.expires_at = Some(parse_expires_at(&exp)?);
+    }
+    new_key.requests_per_minute = req.requests_per_minute;
+    new_key.requests_per_day = req.requests_per_day;
+    if let Some(algorithm) = req.quota_algorithm {
+        new_key.quota_algorithm = algorithm;
+    }
+
+    let created = insert_new_key(state, owner, created_by, &plan, new_key).await?;
+    audit_key(
+        state,
+        AuditAction::ApiKeyCreate,
+        AuditAction::OrgApiKeyCreate,
+        owner,
+        created_by,
+        created.key.id,
+        device,
+    )
+    .await;
+    Ok(created)
+}
+
+pub async fn list(state: &AppState, owner: ApiKeyOwner) -> Result<Vec<ApiKeyPublic>, AppError> {
+    let keys = state.api_keys.list_for_owner(owner).await?;
+    Ok(keys.into_iter().map(ApiKeyPublic::from).collect())
+}
+
+pub async fn get(state: &AppState, owner: ApiKeyOwner, id: &str) -> Result<ApiKeyPublic, AppError> {
+    let key = state
+        .api_keys
+        .find_for_owner(parse_key_id(id)?, owner)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(ApiKeyPublic::from(key))
+}
+
+pub async fn update(
+    state: &AppState,
+    owner: ApiKeyOwner,
+    updated_by: ObjectId,
+    id: &str,
+    req: UpdateApiKeyRequest,
+    device: &ClientDevice,
+) -> Result<ApiKeyPublic, AppError> {
+    let key_id = parse_key_id(id)?;
+
+    let mut patch = ApiKeyPatch::default();
+    if req.requests_per_minute.is_some() || req.requests_per_day.is_some() {
+        let key = state
+            .api_keys
+            .find_for_owner(key_id, owner)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let plan = plan_service::for_key(state, key.plan.as_deref(), owner).await?;
+        plan_service::check_key_limits(&plan, req.requests_per_minute, req.requests_per_day)?;
+        patch.custom_limits = Some(true);
+    }
+    if let Some(name) = req.name {
+        let name = name.trim().to_string();
+        require_non_empty(&name, "name")?;
+        patch.name = Some(name);
+    }
+    if let Some(scopes) = req.scopes {
+        patch.scopes = Some(normalize_scopes(scopes)?);
+    }
+    if let Some(exp) = req.expires_at {
+        patch.expires_at = Some(exp.as_deref().map(parse_expires_at).transpose()?);
+    }
+    patch.requests_per_minute = req.requests_per_minute;
+    patch.requests_per_day = req.requests_per_day;
+    patch.quota_algorithm = req.quota_algorithm;
+
+    let key = state
+        .api_keys
+        .update_for_owner(key_id, owner, &patch)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state.quota.evict(&key.key_hash)?;
+    audit_key(
+        state,
+        AuditAction::ApiKeyUpdate,
+        AuditAction::OrgApiKeyUpdate,
+        owner,
+        updated_by,
+        key_id,
+        device,
+    )
+    .await;
+    Ok(ApiKeyPublic::from(key))
+}
+
+/// Unlike `delete`, the key stays listed. This instance refuses it at once, others
+/// once their cached copy is renewed (see `api_key::quota`).
+pub async fn deactivate(
+    state: &AppState,
+    owner: ApiKeyOwner,
+    deactivated_by: ObjectId,
+    id: &str,
+    device: &ClientDevice,
+) -> Result<ApiKeyPublic, AppError> {
+    let key_id = parse_key_id(id)?;
+    let patch = ApiKeyPatch {
+        active: Some(false),
+        ..Default::default()
+    };
+
+    let key = state
+        .api_keys
+        .update_for_owner(key_id, owner, &patch)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state.quota.evict(&key.key_hash)?;
+    audit_key(
+        state,
+        AuditAction::ApiKeyRevoke,
+        AuditAction::OrgApiKeyRevoke,
+        owner,
+        deactivated_by,
+        key_id,
+        device,
+    )
+    .await;
+    Ok(ApiKeyPublic::from(key))
+}
+
+pub async fn delete(
+    state: &AppState,
+    owner: ApiKeyOwner,
+    deleted_by: ObjectId,
+    id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let key_id = parse_key_id(id)?;
+    let key = state
+        .api_keys
+        .delete_for_owner(key_id, owner)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    state.quota.evict(&key.key_hash)?;
+    audit_key(
+        state,
+        AuditAction::ApiKeyDelete,
+        AuditAction::OrgApiKeyDelete,
+        owner,
+        deleted_by,
+        key_id,
+        device,
+    )
+    .await;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Security audit log (`audit_events`).
+//!
+//! Writing is best effort: a failed insert is logged and never fails the request
+//! that triggered it.
+
+use chrono::DateTime;
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    auth::tokens::ClientDevice,
+    dto::audit::{AdminAuditQuery, AuditPage, AuditPageQuery},
+    errors::AppError,
+    models::audit_event::{AuditAction, AuditEventDoc, AuditEventPublic, AuditOutcome},
+    state::AppState,
+    store::AuditFilter,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// One event being built: `AuditEntry::new(..).user(id).target(..).record(state)`.
+pub struct AuditEntry(AuditEventDoc);
+
+impl AuditEntry {
+    pub fn new(action: AuditAction, outcome: AuditOutcome, device: &ClientDevice) -> Self {
+        Self(AuditEventDoc {
+            id: ObjectId::new(),
+            user_id: None,
+            actor: None,
+            action,
+            target: None,
+            outcome,
+            detail: None,
+            ip: device.ip.map(|ip| ip.to_string()),
+            user_agent: device.user_agent.clone(),
+            created_at: BsonDateTime::now(),
+        })
+    }
+
+    /// The event is about this user, who is also the actor.
+    pub fn user(mut self, user_id: ObjectId) -> Self {
+        self.0.user_id = Some(user_id);
+        self.0.actor = Some(user_id.to_hex());
+        self
+    }
+
+    /// The event is about this user, but someone else (or nobody known) acted.
+    pub fn subject(mut self, user_id: ObjectId) -> Self {
+        self.0.user_id = Some(user_id);
+        self
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.0.actor = Some(actor.into());
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.0.target = Some(target.into());
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.0.detail = Some(detail.into());
+        self
+    }
+
+    pub async fn record(self, state: &AppState) {
+        let event = self.0;
+        if let Err(e) = state.audit_events.insert(&event).await {
+            tracing::warn!(
+                action = event.action.as_str(),
+                user_id = ?event.user_id,
+                error = %e,
+                "failed to write audit event"
+            );
+        }
+    }
+}
+
+pub(crate) fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+pub(crate) fn parse_id(value: Option<&str>, field: &str) -> Result<Option<ObjectId>, AppError> {
+    value
+        .map(|v| {
+            ObjectId::parse_str(v.trim())
+                .map_err(|_| AppError::Validation(format!("{field} is not a valid id")))
+        })
+        .transpose()
+}
+
+fn parse_time(value: Option<&str>, field: &str) -> Result<Option<BsonDateTime>, AppError> {
+    value
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v.trim())
+                .map(|t| BsonDateTime::from_millis(t.timestamp_millis()))
+                .map_err(|_| AppError::Validation(format!("{field} must be an RFC 3339 time")))
+        })
+        .transpose()
+}
+
+async fn page(state: &AppState, filter: AuditFilter, limit: i64) -> Result<AuditPage, AppError> {
+    let events = state.audit_events.find(&filter, limit).await?;
+    let next_before = (events.len() as i64 == limit)
+        .then(|| events.last().map(|e| e.id.to_hex()))
+        .flatten();
+
+    Ok(AuditPage {
+        events: events.into_iter().map(AuditEventPublic::from).collect(),
+        next_before,
+    })
+}
+
+/// The user's own events.
+pub async fn list_for_user(
+    state: &AppState,
+    user_id: ObjectId,
+    query: AuditPageQuery,
+) -> Result<AuditPage, AppError> {
+    let filter = AuditFilter {
+        user_id: Some(user_id),
+        before: parse_id(query.before.as_deref(), "before")?,
+        ..AuditFilter::default()
+    };
+    page(state, filter, page_size(query.limit)).await
+}
+
+pub async fn query(state: &AppState, query: AdminAuditQuery) -> Result<AuditPage, AppError> {
+    let filter = AuditFilter {
+        user_id: parse_id(query.user_id.as_deref(), "user_id")?,
+        actor: query
+            .actor
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty()),
+        action: query.action,
+        outcome: query.outcome,
+        from: parse_time(query.from.as_deref(), "from")?,
+        to: parse_time(query.to.as_deref(), "to")?,
+        before: parse_id(query.before.as_deref(), "before")?,
+    };
+    page(state, filter, page_size(query.limit)).await
+}
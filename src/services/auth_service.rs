@@ -1,5 +1,4 @@
-use bson::{spec::BinarySubtype, Binary};
-use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
 
 use crate::{
     api_key::{
@@ -8,36 +7,51 @@ use crate::{
     },
     auth::{
         jwt::{decode_token, sha256_hex},
-        tokens::{issue_tokens_and_store_refresh, IssuedTokens},
+        tokens::{
+            issue_tokens_and_store_refresh, issue_tokens_for_grant, ClientDevice, IssuedTokens,
+            TokenGrant,
+        },
     },
     dto::auth::{LoginRequest, RefreshRequest, RegisterRequest},
     errors::AppError,
     models::{
-        api_key::ApiKeyDoc,
+        api_key::ApiKeyOwner,
+        audit_event::{AuditAction, AuditOutcome},
+        refresh_token::{RefreshTokenDoc, SessionPublic},
         user::{UserDoc, UserPublic},
     },
     password::{hash_password, verify_password},
+    services::{
+        api_key_service::{self, NewApiKey},
+        audit_service::AuditEntry,
+        email_service, lockout_service, mfa_service, plan_service,
+    },
     state::AppState,
 };
 
 pub struct RegisterOutput {
     pub user: UserPublic,
     pub api_key: String, // plain (показываем только один раз)
-    pub tokens: IssuedTokens,
+    /// `None` while REQUIRE_VERIFIED_EMAIL is on: the user has to confirm first.
+    pub tokens: Option<IssuedTokens>,
 }
 
-fn normalize_email(s: &str) -> String {
+pub(crate) fn normalize_email(s: &str) -> String {
     s.trim().to_lowercase()
 }
 
-fn require_non_empty(value: &str, field: &'static str) -> Result<(), AppError> {
+pub(crate) fn require_non_empty(value: &str, field: &'static str) -> Result<(), AppError> {
     if value.trim().is_empty() {
         return Err(AppError::Validation(format!("{field} is required")));
     }
     Ok(())
 }
 
-pub async fn register(state: &AppState, req: RegisterRequest) -> Result<RegisterOutput, AppError> {
+pub async fn register(
+    state: &AppState,
+    req: RegisterRequest,
+    device: &ClientDevice,
+) -> Result<RegisterOutput, AppError> {
     let email = normalize_email(&req.email);
     let name = req.name.trim().to_string();
 
@@ -45,12 +59,7 @@ pub async fn register(state: &AppState, req: RegisterRequest) -> Result<Register
     require_non_empty(&name, "name")?;
     require_non_empty(&req.password, "password")?;
 
-    if state
-        .users
-        .find_one(doc! { "email": &email })
-        .await?
-        .is_some()
-    {
+    if state.users.find_by_email(&email).await?.is_some() {
         return Err(AppError::Conflict("user already exists".into()));
     }
 
@@ -63,102 +72,144 @@ pub async fn register(state: &AppState, req: RegisterRequest) -> Result<Register
         password_hash,
         created_at: BsonDateTime::now(),
         default_api_key_id: None,
+        email_verified: false,
+        token_version: 0,
+        roles: Vec::new(),
+        permissions: Vec::new(),
+        disabled: false,
+        plan: Some(state.cfg.default_plan.clone()),
     };
 
-    state.users.insert_one(&user).await?;
+    state.users.insert(&user).await?;
 
     // Create default API key (stored in api_keys collection)
-    let api_key_plain = generate_api_key();
-
-    // In case of extremely rare sha collision / unique index conflict, retry a few times.
-    let mut inserted_key: Option<ApiKeyDoc> = None;
-    for attempt in 0..5 {
-        let key_hash = sha256_hex(&api_key_plain);
-        let (ct, nonce) = encrypt_api_key(&api_key_plain)?;
-
-        let key_doc = ApiKeyDoc {
-            id: ObjectId::new(),
-            user_id: user.id,
-            name: "Default".into(),
-            key_hash,
-            key_ciphertext: ct,
-            key_nonce: nonce,
-
-            active: true,
-            expires_at: None,
-
-            // defaults; можно вынести в config
-            requests_per_minute: 60,
-            requests_per_day: 10_000,
-
-            // counters initialized to 0
-            minute_bucket: 0,
-            requests_used_minute: 0,
-            usage_day: 0,
-            requests_used_today: 0,
-
-            scopes: vec!["api".into()],
-
-            created_at: BsonDateTime::now(),
-            last_used_at: BsonDateTime::now(),
-        };
-
-        match state.api_keys.insert_one(&key_doc).await {
-            Ok(_) => {
-                inserted_key = Some(key_doc);
-                break;
-            }
-            Err(e) => {
-                if attempt == 4 {
-                    return Err(e.into());
-                }
-            }
-        }
-    }
-
-    let key_doc =
-        inserted_key.ok_or_else(|| AppError::Internal("failed to create api key".into()))?;
+    let owner = ApiKeyOwner::User(user.id);
+    let plan = plan_service::for_owner(state, owner).await?;
+    let created = api_key_service::insert_new_key(
+        state,
+        owner,
+        user.id,
+        &plan,
+        NewApiKey::with_defaults("Default"),
+    )
+    .await?;
 
     // Set user's default api key id
     state
         .users
-        .update_one(
-            doc! { "_id": user.id },
-            doc! { "$set": { "default_api_key_id": key_doc.id } },
-        )
+        .set_default_api_key(user.id, created.key.id)
         .await?;
 
-    let tokens = issue_tokens_and_store_refresh(state, user.id).await?;
+    // a mail outage must not block sign-ups: the link can be resent
+    if let Err(e) = email_service::send_verification(state, &user).await {
+        tracing::warn!(user_id = %user.id, error = %e, "failed to send verification email");
+    }
+
+    let tokens = if state.cfg.require_verified_email {
+        None
+    } else {
+        Some(issue_tokens_and_store_refresh(state, user.id, device).await?)
+    };
 
     Ok(RegisterOutput {
         user: UserPublic::from(user),
-        api_key: api_key_plain,
+        api_key: created.api_key,
         tokens,
     })
 }
 
-pub async fn login(state: &AppState, req: LoginRequest) -> Result<IssuedTokens, AppError> {
-    let email = normalize_email(&req.email);
+/// Checks email/password, returns the user. Failures count towards the lockout
+/// (`lockout_service`) and are audited; the caller resets the counter and audits
+/// the login once all factors passed.
+pub(crate) async fn authenticate(
+    state: &AppState,
+    email: &str,
+    password: &str,
+    device: &ClientDevice,
+) -> Result<UserDoc, AppError> {
+    let email = normalize_email(email);
     require_non_empty(&email, "email")?;
-    require_non_empty(&req.password, "password")?;
+    require_non_empty(password, "password")?;
+
+    let found = state.users.find_by_email(&email).await?;
+    let failure = |detail: &str| {
+        let entry = AuditEntry::new(AuditAction::Login, AuditOutcome::Failure, device)
+            .target(&email)
+            .detail(detail);
+        match &found {
+            Some(user) => entry.subject(user.id),
+            None => entry,
+        }
+    };
 
-    let user = state
-        .users
-        .find_one(doc! { "email": &email })
-        .await?
-        .ok_or(AppError::Unauthorized)?;
+    if let Err(e) = lockout_service::check(state, &email, device.ip).await {
+        failure("locked out").record(state).await;
+        return Err(e);
+    }
 
-    if !verify_password(&req.password, &user.password_hash)? {
-        return Err(AppError::Unauthorized);
+    let user = match &found {
+        Some(user) if verify_password(password, &user.password_hash)? => user.clone(),
+        _ => {
+            failure("invalid credentials").record(state).await;
+            lockout_service::record_failure(state, &email, device).await?;
+            return Err(AppError::Unauthorized);
+        }
+    };
+
+    if user.disabled {
+        failure("account disabled").record(state).await;
+        return Err(AppError::Forbidden("account disabled".into()));
     }
 
-    issue_tokens_and_store_refresh(state, user.id).await
+    if state.cfg.require_verified_email && !user.email_verified {
+        failure("email not verified").record(state).await;
+        return Err(AppError::Forbidden("email not verified".into()));
+    }
+
+    Ok(user)
+}
+
+/// Audits a completed login (all factors passed); `method` is how the user signed in.
+pub(crate) async fn record_login(
+    state: &AppState,
+    user_id: ObjectId,
+    method: &str,
+    device: &ClientDevice,
+) {
+    AuditEntry::new(AuditAction::Login, AuditOutcome::Success, device)
+        .user(user_id)
+        .detail(method)
+        .record(state)
+        .await;
+}
+
+pub enum LoginOutput {
+    Tokens(IssuedTokens),
+    /// TOTP is on: the challenge token for `mfa_service::complete_login`.
+    MfaRequired(String),
+}
+
+pub async fn login(
+    state: &AppState,
+    req: LoginRequest,
+    device: &ClientDevice,
+) -> Result<LoginOutput, AppError> {
+    let user = authenticate(state, &req.email, &req.password, device).await?;
+
+    if let Some(mfa_token) = mfa_service::start_login(state, user.id).await? {
+        return Ok(LoginOutput::MfaRequired(mfa_token));
+    }
+    lockout_service::record_success(state, &user.email).await?;
+
+    let tokens = issue_tokens_and_store_refresh(state, user.id, device).await?;
+    record_login(state, user.id, "password", device).await;
+    Ok(LoginOutput::Tokens(tokens))
 }
 
 pub async fn me(state: &AppState, user_id: ObjectId) -> Result<UserPublic, AppError> {
     let user = state
         .users
-        .find_one(doc! { "_id": user_id })
+        .find_by_id(user_id)
         .await?
         .ok_or(AppError::Unauthorized)?;
 
@@ -166,23 +217,40 @@ pub async fn me(state: &AppState, user_id: ObjectId) -> Result<UserPublic, AppEr
 }
 
 /// Refresh rotation:
-/// - If refresh token doc already revoked => treat as reuse, revoke all active refresh tokens for that user.
+/// - If refresh token doc already revoked => treat as reuse, revoke its family (that session only).
 /// - Otherwise issue new tokens, revoke old, set replaced_by=new_refresh_doc_id.
-pub async fn refresh(state: &AppState, req: RefreshRequest) -> Result<IssuedTokens, AppError> {
-    require_non_empty(&req.refresh_token, "refresh_token")?;
+pub async fn refresh(
+    state: &AppState,
+    req: RefreshRequest,
+    device: &ClientDevice,
+) -> Result<IssuedTokens, AppError> {
+    rotate_refresh_token(state, &req.refresh_token, None, None, device).await
+}
 
-    let data = decode_token(&req.refresh_token)?;
+/// Refresh rotation for first-party (`client_id = None`) or OAuth client tokens;
+/// a token is only accepted from the client it was issued to. `active_org` switches
+/// the session's organization (`Some(None)` back to personal), `None` keeps it.
+pub(crate) async fn rotate_refresh_token(
+    state: &AppState,
+    refresh_token: &str,
+    client_id: Option<&str>,
+    active_org: Option<Option<ObjectId>>,
+    device: &ClientDevice,
+) -> Result<IssuedTokens, AppError> {
+    require_non_empty(refresh_token, "refresh_token")?;
+
+    let data = decode_token(&state.keyring, refresh_token).await?;
     let claims = data.claims;
 
     if claims.typ != "refresh" {
         return Err(AppError::Unauthorized);
     }
 
-    let token_hash = sha256_hex(&req.refresh_token);
+    let token_hash = sha256_hex(refresh_token);
 
     let current = state
         .refresh_tokens
-        .find_one(doc! { "token_hash": &token_hash })
+        .find_by_hash(&token_hash)
         .await?
         .ok_or(AppError::Unauthorized)?;
 
@@ -192,60 +260,212 @@ pub async fn refresh(state: &AppState, req: RefreshRequest) -> Result<IssuedToke
         return Err(AppError::Unauthorized);
     }
 
+    if current.client_id.as_deref() != client_id {
+        return Err(AppError::Unauthorized);
+    }
+
     if current.expires_at < BsonDateTime::now() {
         return Err(AppError::Unauthorized);
     }
 
-    // reuse detection
     if current.revoked_at.is_some() {
-        // revoke all non-revoked refresh tokens for this user
-        let _ = state
-            .refresh_tokens
-            .update_many(
-                doc! { "user_id": current.user_id, "revoked_at": mongodb::bson::Bson::Null },
-                doc! { "$set": { "revoked_at": BsonDateTime::now() } },
-            )
-            .await?;
-        return Err(AppError::Unauthorized);
+        return Err(refresh_token_reuse(state, &current, device).await);
     }
 
     // issue new tokens (and insert new refresh doc)
-    let new_tokens = issue_tokens_and_store_refresh(state, current.user_id).await?;
+    let grant = TokenGrant {
+        client_id: current.client_id.clone(),
+        scopes: current.scopes.clone(),
+        family_id: current.family_id,
+        session_started_at: Some(current.session_started_at.unwrap_or(current.created_at)),
+        org_id: active_org.unwrap_or(current.org_id),
+    };
+    let new_tokens = issue_tokens_for_grant(state, current.user_id, &grant, device).await?;
 
-    // revoke old + replaced_by
-    state
+    // revoke old + replaced_by; losing this race to a concurrent refresh with the
+    // same token is reuse too (the family, new tokens included, is revoked)
+    let replaced = state
         .refresh_tokens
-        .update_one(
-            doc! { "_id": current.id },
-            doc! { "$set": { "revoked_at": BsonDateTime::now(), "replaced_by": new_tokens.refresh_doc_id } },
-        )
+        .mark_replaced(current.id, new_tokens.refresh_doc_id)
         .await?;
+    if !replaced {
+        return Err(refresh_token_reuse(state, &current, device).await);
+    }
 
     Ok(new_tokens)
 }
 
+/// Reuse detection: a rotated-out token came back, so either the legitimate client
+/// or an attacker holds a copy of this chain; end the chain for both.
+async fn refresh_token_reuse(
+    state: &AppState,
+    current: &RefreshTokenDoc,
+    device: &ClientDevice,
+) -> AppError {
+    let revoked = async {
+        match current.family_id {
+            Some(family_id) => {
+                let revoked = state
+                    .refresh_tokens
+                    .revoke_family(family_id, current.user_id)
+                    .await?;
+                end_session_access(state, current).await?;
+                Ok(revoked)
+            }
+            // issued before families: the chain is unknown, end every session
+            None => revoke_all_tokens(state, current.user_id).await,
+        }
+    };
+    let revoked = match revoked.await {
+        Ok(revoked) => revoked,
+        Err(e) => return e,
+    };
+    tracing::warn!(
+        target: "security",
+        event = "refresh_token_reuse",
+        user_id = %current.user_id,
+        token_id = %current.id,
+        family_id = ?current.family_id,
+        client_id = ?current.client_id,
+        ip = ?device.ip,
+        user_agent = ?device.user_agent,
+        revoked,
+        "revoked refresh token reused, session revoked"
+    );
+    let mut entry = AuditEntry::new(
+        AuditAction::RefreshTokenReuse,
+        AuditOutcome::Failure,
+        device,
+    )
+    .subject(current.user_id)
+    .target(current.family_id.unwrap_or(current.id).to_hex())
+    .detail(format!("{revoked} refresh tokens revoked"));
+    if let Some(client_id) = &current.client_id {
+        entry = entry.actor(format!("client:{client_id}"));
+    }
+    entry.record(state).await;
+    AppError::Unauthorized
+}
+
 /// Logout: revoke provided refresh token (idempotent).
-pub async fn logout(state: &AppState, req: RefreshRequest) -> Result<(), AppError> {
+pub async fn logout(
+    state: &AppState,
+    req: RefreshRequest,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
     require_non_empty(&req.refresh_token, "refresh_token")?;
     let token_hash = sha256_hex(&req.refresh_token);
 
     // Don't leak whether token exists; treat missing as ok.
-    let _ = state
+    let Some(current) = state.refresh_tokens.find_by_hash(&token_hash).await? else {
+        return Ok(());
+    };
+    state.refresh_tokens.revoke_by_hash(&token_hash).await?;
+    end_session_access(state, &current).await?;
+
+    AuditEntry::new(AuditAction::Logout, AuditOutcome::Success, device)
+        .user(current.user_id)
+        .target(current.family_id.unwrap_or(current.id).to_hex())
+        .record(state)
+        .await;
+    Ok(())
+}
+
+/// Access tokens of the token's session stop working too (legacy tokens have no session).
+async fn end_session_access(state: &AppState, token: &RefreshTokenDoc) -> Result<(), AppError> {
+    if let Some(family_id) = token.family_id {
+        state
+            .denylist
+            .revoke_session(family_id, state.cfg.jwt_access_ttl_seconds)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Revokes every refresh and access token of the user (logout everywhere, password
+/// change): refresh tokens are revoked, access tokens by bumping `token_version`.
+pub(crate) async fn revoke_all_tokens(
+    state: &AppState,
+    user_id: ObjectId,
+) -> Result<u64, AppError> {
+    let revoked = state.refresh_tokens.revoke_all_for_user(user_id).await?;
+    if let Some(version) = state.users.bump_token_version(user_id).await? {
+        state
+            .denylist
+            .revoke_user(user_id, version, state.cfg.jwt_access_ttl_seconds)
+            .await?;
+    }
+    Ok(revoked)
+}
+
+/// Active sessions (refresh token chains) of the user.
+pub async fn list_sessions(
+    state: &AppState,
+    user_id: ObjectId,
+) -> Result<Vec<SessionPublic>, AppError> {
+    let tokens = state
         .refresh_tokens
-        .update_one(
-            doc! { "token_hash": &token_hash, "revoked_at": mongodb::bson::Bson::Null },
-            doc! { "$set": { "revoked_at": BsonDateTime::now() } },
-        )
+        .list_active_for_user(user_id, BsonDateTime::now())
         .await?;
+    Ok(tokens.into_iter().map(SessionPublic::from).collect())
+}
 
+/// Ends one session: its current refresh token is revoked (older ones already are).
+pub async fn revoke_session(
+    state: &AppState,
+    user_id: ObjectId,
+    id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let id = ObjectId::parse_str(id).map_err(|_| AppError::NotFound)?;
+    if state.refresh_tokens.revoke_family(id, user_id).await? > 0 {
+        state
+            .denylist
+            .revoke_session(id, state.cfg.jwt_access_ttl_seconds)
+            .await?;
+    } else {
+        // sessions from before families are identified by their token
+        let token = state
+            .refresh_tokens
+            .revoke_for_user(id, user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        end_session_access(state, &token).await?;
+    }
+
+    AuditEntry::new(AuditAction::SessionRevoke, AuditOutcome::Success, device)
+        .user(user_id)
+        .target(id.to_hex())
+        .record(state)
+        .await;
     Ok(())
 }
 
+/// Ends every session of the user, returns how many were ended.
+pub async fn logout_all(
+    state: &AppState,
+    user_id: ObjectId,
+    device: &ClientDevice,
+) -> Result<u64, AppError> {
+    let revoked = revoke_all_tokens(state, user_id).await?;
+
+    AuditEntry::new(AuditAction::LogoutAll, AuditOutcome::Success, device)
+        .user(user_id)
+        .detail(format!("{revoked} sessions ended"))
+        .record(state)
+        .await;
+    Ok(revoked)
+}
+
 /// Returns plaintext of the user's default API key (decrypt from api_keys collection).
-pub async fn reveal_api_key(state: &AppState, user_id: ObjectId) -> Result<String, AppError> {
+pub async fn reveal_api_key(
+    state: &AppState,
+    user_id: ObjectId,
+    device: &ClientDevice,
+) -> Result<String, AppError> {
     let user = state
         .users
-        .find_one(doc! { "_id": user_id })
+        .find_by_id(user_id)
         .await?
         .ok_or(AppError::Unauthorized)?;
 
@@ -253,11 +473,18 @@ pub async fn reveal_api_key(state: &AppState, user_id: ObjectId) -> Result<Strin
 
     let key = state
         .api_keys
-        .find_one(doc! { "_id": key_id, "user_id": user_id, "active": true })
+        .find_active_for_owner(key_id, ApiKeyOwner::User(user_id))
         .await?
         .ok_or(AppError::NotFound)?;
 
-    Ok(decrypt_api_key(&key.key_ciphertext, &key.key_nonce)?)
+    let api_key = decrypt_api_key(&key.key_ciphertext, &key.key_nonce)?;
+
+    AuditEntry::new(AuditAction::ApiKeyReveal, AuditOutcome::Success, device)
+        .user(user_id)
+        .target(key_id.to_hex())
+        .record(state)
+        .await;
+    Ok(api_key)
 }
 
 /// Rotates the default API key for user and returns new plaintext key.
@@ -265,10 +492,11 @@ pub async fn reveal_api_key(state: &AppState, user_id: ObjectId) -> Result<Strin
 pub async fn rotate_default_api_key(
     state: &AppState,
     user_id: ObjectId,
+    device: &ClientDevice,
 ) -> Result<String, AppError> {
     let user = state
         .users
-        .find_one(doc! { "_id": user_id })
+        .find_by_id(user_id)
         .await?
         .ok_or(AppError::Unauthorized)?;
 
@@ -280,22 +508,18 @@ pub async fn rotate_default_api_key(
         let key_hash = sha256_hex(&api_key_plain);
         let (ct, nonce) = encrypt_api_key(&api_key_plain)?;
 
-        let upd = doc! {
-            "$set": {
-                "key_hash": key_hash,
-                "key_ciphertext": Binary { subtype: BinarySubtype::Generic, bytes: ct },
-                "key_nonce": Binary { subtype: BinarySubtype::Generic, bytes: nonce.to_vec() },
-                "last_used_at": BsonDateTime::now(),
-                "active": true,
-            }
-        };
-
-        let res = state
+        let replaced = state
             .api_keys
-            .update_one(doc! { "_id": key_id, "user_id": user_id }, upd)
+            .replace_secret(key_id, ApiKeyOwner::User(user_id), &key_hash, ct, nonce)
             .await?;
 
-        if res.matched_count == 1 {
+        if let Some(old) = replaced {
+            state.quota.evict(&old.key_hash)?;
+            AuditEntry::new(AuditAction::ApiKeyRotate, AuditOutcome::Success, device)
+                .user(user_id)
+                .target(key_id.to_hex())
+                .record(state)
+                .await;
             return Ok(api_key_plain);
         }
     }
@@ -0,0 +1,71 @@
+//! Email verification: a single-use link is mailed on registration and on request.
+
+use crate::{
+    auth::tokens::{consume_user_token, issue_user_token},
+    dto::auth::ResendVerificationRequest,
+    errors::AppError,
+    mail::Email,
+    models::{
+        user::{UserDoc, UserPublic},
+        user_token::UserTokenPurpose,
+    },
+    services::auth_service::{self, normalize_email, require_non_empty},
+    state::AppState,
+};
+
+/// Mails a fresh verification link; links sent earlier stop working.
+pub async fn send_verification(state: &AppState, user: &UserDoc) -> Result<(), AppError> {
+    state
+        .user_tokens
+        .delete_for_user(user.id, UserTokenPurpose::EmailVerification)
+        .await?;
+
+    let ttl = state.cfg.email_verification_ttl_seconds;
+    let token = issue_user_token(state, user.id, UserTokenPurpose::EmailVerification, ttl).await?;
+    let link = format!("{}/auth/email/verify?token={token}", state.cfg.issuer);
+
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Confirm your email".to_string(),
+        body: format!(
+            "Hi {},\n\nconfirm your email address by opening this link:\n\n{link}\n\n\
+             The link is valid for {} hours. If you did not sign up, ignore this email.\n",
+            user.name,
+            ttl / 3600,
+        ),
+    };
+    state.mailer.send(&email).await
+}
+
+/// Resends the link. Always succeeds: unknown and already verified addresses and
+/// mail failures are not reported, so the endpoint does not reveal which emails
+/// are registered. The lookup and the email run in the background.
+pub async fn resend(state: &AppState, req: ResendVerificationRequest) -> Result<(), AppError> {
+    let email = normalize_email(&req.email);
+    require_non_empty(&email, "email")?;
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        let user = match state.users.find_by_email(&email).await {
+            Ok(Some(user)) if !user.email_verified => user,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::warn!(error = %e, "verification resend: user lookup failed");
+                return;
+            }
+        };
+        if let Err(e) = send_verification(&state, &user).await {
+            tracing::warn!(user_id = %user.id, error = %e, "failed to send verification email");
+        }
+    });
+    Ok(())
+}
+
+pub async fn confirm(state: &AppState, token: &str) -> Result<UserPublic, AppError> {
+    require_non_empty(token, "token")?;
+
+    let doc = consume_user_token(state, token, UserTokenPurpose::EmailVerification).await?;
+    state.users.set_email_verified(doc.user_id).await?;
+
+    auth_service::me(state, doc.user_id).await
+}
@@ -0,0 +1,191 @@
+//! Brute-force protection for password logins: failed attempts are counted per
+//! account and per client IP in `login_attempts` (shared by all instances).
+//!
+//! Reaching the limit locks the key for `base_lock_seconds`; every further failure
+//! after the lock doubles it, up to `max_lock_seconds`. Attempts made while locked
+//! are rejected before the password is checked and are not counted.
+
+use std::net::IpAddr;
+
+use chrono::{Duration, Utc};
+use mongodb::bson::DateTime as BsonDateTime;
+
+use crate::{
+    auth::tokens::ClientDevice,
+    dto::admin::UnlockLoginRequest,
+    errors::AppError,
+    models::{
+        audit_event::{AuditAction, AuditOutcome},
+        login_attempt::LoginAttemptPublic,
+    },
+    services::{audit_service::AuditEntry, auth_service::normalize_email},
+    state::AppState,
+};
+
+fn account_key(email: &str) -> String {
+    format!("account:{email}")
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{ip}")
+}
+
+async fn locked_for(state: &AppState, key: &str) -> Result<Option<u64>, AppError> {
+    let now = BsonDateTime::now();
+    let until = state
+        .login_attempts
+        .find(key, now)
+        .await?
+        .and_then(|a| a.locked_until)
+        .filter(|t| *t > now);
+
+    Ok(until.map(|t| {
+        let ms = (t.timestamp_millis() - now.timestamp_millis()).max(0) as u64;
+        ms.div_ceil(1000).max(1)
+    }))
+}
+
+/// Rejects the attempt while the client (429) or the account (423) is locked.
+pub async fn check(state: &AppState, email: &str, ip: Option<IpAddr>) -> Result<(), AppError> {
+    if let Some(ip) = ip
+        && let Some(secs) = locked_for(state, &ip_key(ip)).await?
+    {
+        return Err(AppError::Throttled(secs));
+    }
+    if let Some(secs) = locked_for(state, &account_key(email)).await? {
+        return Err(AppError::Locked(secs));
+    }
+    Ok(())
+}
+
+async fn count_failure(
+    state: &AppState,
+    key: &str,
+    max_failures: i32,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    if max_failures <= 0 {
+        return Ok(()); // limit disabled
+    }
+
+    let policy = state.cfg.login_lockout;
+    let now = Utc::now();
+    let window = Duration::seconds(policy.window_seconds);
+
+    let attempt = state
+        .login_attempts
+        .record_failure(
+            key,
+            BsonDateTime::from_chrono(now),
+            BsonDateTime::from_chrono(now + window),
+        )
+        .await?;
+    if attempt.failures < max_failures {
+        return Ok(());
+    }
+
+    let doublings = (attempt.failures - max_failures).min(30) as u32;
+    let lock_seconds = policy
+        .base_lock_seconds
+        .saturating_mul(1 << doublings)
+        .min(policy.max_lock_seconds);
+    let locked_until = now + Duration::seconds(lock_seconds);
+
+    // keep the counter until a window after the lock, so the next failure escalates
+    state
+        .login_attempts
+        .lock(
+            key,
+            BsonDateTime::from_chrono(locked_until),
+            BsonDateTime::from_chrono(locked_until + window),
+        )
+        .await?;
+
+    tracing::warn!(
+        key,
+        failures = attempt.failures,
+        lock_seconds,
+        "login locked"
+    );
+    AuditEntry::new(AuditAction::LoginLockout, AuditOutcome::Failure, device)
+        .target(key)
+        .detail(format!(
+            "{} failures, locked for {lock_seconds}s",
+            attempt.failures
+        ))
+        .record(state)
+        .await;
+    Ok(())
+}
+
+/// Counts a failed password (or second factor) for the account and the client.
+/// Unknown emails are counted too, so lockouts do not reveal which accounts exist.
+pub async fn record_failure(
+    state: &AppState,
+    email: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let policy = state.cfg.login_lockout;
+
+    count_failure(
+        state,
+        &account_key(email),
+        policy.max_failures_per_account,
+        device,
+    )
+    .await?;
+    if let Some(ip) = device.ip {
+        count_failure(state, &ip_key(ip), policy.max_failures_per_ip, device).await?;
+    }
+    Ok(())
+}
+
+/// A completed login (all factors) resets the account counter. The IP counter is
+/// kept: signing in to one's own account must not lift the limit for guessing others.
+pub async fn record_success(state: &AppState, email: &str) -> Result<(), AppError> {
+    state.login_attempts.clear(&account_key(email)).await?;
+    Ok(())
+}
+
+pub async fn list_locked(state: &AppState) -> Result<Vec<LoginAttemptPublic>, AppError> {
+    let locked = state
+        .login_attempts
+        .list_locked(BsonDateTime::now())
+        .await?;
+    Ok(locked.into_iter().map(LoginAttemptPublic::from).collect())
+}
+
+/// Admin unlock; returns how many records were cleared.
+pub async fn unlock(state: &AppState, req: UnlockLoginRequest) -> Result<u64, AppError> {
+    let email = req
+        .email
+        .as_deref()
+        .map(normalize_email)
+        .filter(|e| !e.is_empty());
+    let ip = req
+        .ip
+        .as_deref()
+        .map(|ip| {
+            ip.trim()
+                .parse::<IpAddr>()
+                .map_err(|_| AppError::Validation("ip is not a valid IP address".into()))
+        })
+        .transpose()?;
+
+    if email.is_none() && ip.is_none() {
+        return Err(AppError::Validation("email or ip is required".into()));
+    }
+
+    let mut cleared = 0;
+    if let Some(email) = email
+        && state.login_attempts.clear(&account_key(&email)).await?
+    {
+        cleared += 1;
+    }
+    if let Some(ip) = ip
+        && state.login_attempts.clear(&ip_key(ip)).await?
+    {
+        cleared += 1;
+    }
+    Ok(cleared)
+}
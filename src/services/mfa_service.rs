@@ -0,0 +1,326 @@
+//! TOTP second factor: enrollment, recovery codes and the second login step.
+
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    api_key::crypto::{decrypt_secret, encrypt_secret},
+    auth::{
+        tokens::{
+            consume_user_token, issue_tokens_and_store_refresh, issue_user_token, ClientDevice,
+            IssuedTokens,
+        },
+        totp::{matching_step, new_recovery_codes, new_secret, otpauth_url, recovery_code_hash},
+    },
+    dto::auth::{MfaLoginRequest, MfaStatusResponse, RecoveryCodesResponse, TotpSetupResponse},
+    errors::AppError,
+    models::{
+        audit_event::{AuditAction, AuditOutcome},
+        totp_factor::TotpFactorDoc,
+        user_token::UserTokenPurpose,
+    },
+    services::{
+        audit_service::AuditEntry,
+        auth_service::{self, require_non_empty},
+        lockout_service,
+    },
+    state::AppState,
+};
+
+/// Time to enter the code after the password was accepted.
+const MFA_CHALLENGE_TTL_SECONDS: i64 = 300;
+
+fn now_unix() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
+async fn enabled_factor(
+    state: &AppState,
+    user_id: ObjectId,
+) -> Result<Option<TotpFactorDoc>, AppError> {
+    Ok(state
+        .totp_factors
+        .find_for_user(user_id)
+        .await?
+        .filter(|f| f.enabled))
+}
+
+async fn require_enabled(state: &AppState, user_id: ObjectId) -> Result<TotpFactorDoc, AppError> {
+    enabled_factor(state, user_id)
+        .await?
+        .ok_or_else(|| AppError::Conflict("totp is not enabled".into()))
+}
+
+/// Accepts a current TOTP code (each time step once) or, if allowed, an unused recovery code.
+async fn verify_code(
+    state: &AppState,
+    factor: &TotpFactorDoc,
+    code: &str,
+    allow_recovery: bool,
+) -> Result<bool, AppError> {
+    let secret = decrypt_secret(&factor.secret_ciphertext, &factor.secret_nonce)?;
+
+    if let Some(step) = matching_step(&secret, code, now_unix())? {
+        return state.totp_factors.use_step(factor.user_id, step).await;
+    }
+
+    if allow_recovery {
+        return state
+            .totp_factors
+            .take_recovery_code(factor.user_id, &recovery_code_hash(code))
+            .await;
+    }
+
+    Ok(false)
+}
+
+/// `verify_code` for account settings, under the same lockout as the login step: a
+/// stolen access token must not become an unlimited TOTP guessing oracle.
+async fn verify_settings_code(
+    state: &AppState,
+    factor: &TotpFactorDoc,
+    code: &str,
+    allow_recovery: bool,
+    action: AuditAction,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let user = state
+        .users
+        .find_by_id(factor.user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let failure = |detail: &str| {
+        AuditEntry::new(action, AuditOutcome::Failure, device)
+            .user(user.id)
+            .detail(detail)
+    };
+
+    if let Err(e) = lockout_service::check(state, &user.email, device.ip).await {
+        failure("locked out").record(state).await;
+        return Err(e);
+    }
+    if !verify_code(state, factor, code, allow_recovery).await? {
+        failure("invalid code").record(state).await;
+        lockout_service::record_failure(state, &user.email, device).await?;
+        return Err(AppError::Validation("invalid code".into()));
+    }
+    Ok(())
+}
+
+pub async fn status(state: &AppState, user_id: ObjectId) -> Result<MfaStatusResponse, AppError> {
+    let factor = enabled_factor(state, user_id).await?;
+
+    Ok(MfaStatusResponse {
+        totp_enabled: factor.is_some(),
+        recovery_codes_remaining: factor.map_or(0, |f| f.recovery_code_hashes.len()),
+    })
+}
+
+/// Starts enrollment with a new secret; a previous unfinished enrollment is dropped.
+pub async fn setup(state: &AppState, user_id: ObjectId) -> Result<TotpSetupResponse, AppError> {
+    let user = state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    if let Some(existing) = state.totp_factors.find_for_user(user_id).await? {
+        if existing.enabled {
+            return Err(AppError::Conflict("totp is already enabled".into()));
+        }
+        state.totp_factors.delete_for_user(user_id).await?;
+    }
+
+    let secret = new_secret();
+    let otpauth_url = otpauth_url(&secret, &state.cfg.totp_issuer, &user.email)?;
+    let (secret_ciphertext, secret_nonce) = encrypt_secret(&secret)?;
+
+    let factor = TotpFactorDoc {
+        id: ObjectId::new(),
+        user_id,
+        secret_ciphertext,
+        secret_nonce,
+        enabled: false,
+        recovery_code_hashes: Vec::new(),
+        last_used_step: 0,
+        created_at: BsonDateTime::now(),
+        enabled_at: None,
+    };
+    state.totp_factors.insert(&factor).await?;
+
+    Ok(TotpSetupResponse {
+        secret,
+        otpauth_url,
+    })
+}
+
+/// Confirms enrollment with a first code and hands out the recovery codes.
+pub async fn enable(
+    state: &AppState,
+    user_id: ObjectId,
+    code: &str,
+    device: &ClientDevice,
+) -> Result<RecoveryCodesResponse, AppError> {
+    require_non_empty(code, "code")?;
+
+    let factor = match state.totp_factors.find_for_user(user_id).await? {
+        Some(f) if f.enabled => return Err(AppError::Conflict("totp is already enabled".into())),
+        Some(f) => f,
+        None => return Err(AppError::Conflict("no pending totp setup".into())),
+    };
+
+    if !verify_code(state, &factor, code, false).await? {
+        return Err(AppError::Validation("invalid code".into()));
+    }
+
+    let (recovery_codes, hashes) = new_recovery_codes();
+    if !state
+        .totp_factors
+        .enable(user_id, &hashes, BsonDateTime::now())
+        .await?
+    {
+        return Err(AppError::Conflict("totp is already enabled".into()));
+    }
+
+    AuditEntry::new(AuditAction::TotpEnable, AuditOutcome::Success, device)
+        .user(user_id)
+        .record(state)
+        .await;
+    Ok(RecoveryCodesResponse { recovery_codes })
+}
+
+/// Turns TOTP off; takes a TOTP or a recovery code (the device may be lost).
+pub async fn disable(
+    state: &AppState,
+    user_id: ObjectId,
+    code: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    require_non_empty(code, "code")?;
+
+    let factor = require_enabled(state, user_id).await?;
+    verify_settings_code(state, &factor, code, true, AuditAction::TotpDisable, device).await?;
+
+    state.totp_factors.delete_for_user(user_id).await?;
+    AuditEntry::new(AuditAction::TotpDisable, AuditOutcome::Success, device)
+        .user(user_id)
+        .record(state)
+        .await;
+    Ok(())
+}
+
+/// Replaces all recovery codes; takes a TOTP code.
+pub async fn regenerate_recovery_codes(
+    state: &AppState,
+    user_id: ObjectId,
+    code: &str,
+    device: &ClientDevice,
+) -> Result<RecoveryCodesResponse, AppError> {
+    require_non_empty(code, "code")?;
+
+    let factor = require_enabled(state, user_id).await?;
+    verify_settings_code(
+        state,
+        &factor,
+        code,
+        false,
+        AuditAction::RecoveryCodesRegenerate,
+        device,
+    )
+    .await?;
+
+    let (recovery_codes, hashes) = new_recovery_codes();
+    state
+        .totp_factors
+        .set_recovery_codes(user_id, &hashes)
+        .await?;
+
+    AuditEntry::new(
+        AuditAction::RecoveryCodesRegenerate,
+        AuditOutcome::Success,
+        device,
+    )
+    .user(user_id)
+    .record(state)
+    .await;
+    Ok(RecoveryCodesResponse { recovery_codes })
+}
+
+/// After the password check: a challenge token if the user has TOTP on, `None` otherwise.
+pub(crate) async fn start_login(
+    state: &AppState,
+    user_id: ObjectId,
+) -> Result<Option<String>, AppError> {
+    if enabled_factor(state, user_id).await?.is_none() {
+        return Ok(None);
+    }
+
+    let token = issue_user_token(
+        state,
+        user_id,
+        UserTokenPurpose::MfaChallenge,
+        MFA_CHALLENGE_TTL_SECONDS,
+    )
+    .await?;
+    Ok(Some(token))
+}
+
+/// Second login step. The challenge is single-use: a wrong code means logging in
+/// again, so every guess costs a password check.
+pub async fn complete_login(
+    state: &AppState,
+    req: MfaLoginRequest,
+    device: &ClientDevice,
+) -> Result<IssuedTokens, AppError> {
+    require_non_empty(&req.mfa_token, "mfa_token")?;
+    require_non_empty(&req.code, "code")?;
+
+    let challenge =
+        consume_user_token(state, &req.mfa_token, UserTokenPurpose::MfaChallenge).await?;
+
+    let user = state
+        .users
+        .find_by_id(challenge.user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let failure = |detail: &str| {
+        AuditEntry::new(AuditAction::Login, AuditOutcome::Failure, device)
+            .subject(user.id)
+            .target(&user.email)
+            .detail(detail)
+    };
+
+    if let Err(e) = lockout_service::check(state, &user.email, device.ip).await {
+        failure("locked out").record(state).await;
+        return Err(e);
+    }
+
+    let factor = enabled_factor(state, user.id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    if !verify_code(state, &factor, &req.code, true).await? {
+        failure("invalid second factor").record(state).await;
+        lockout_service::record_failure(state, &user.email, device).await?;
+        return Err(AppError::Unauthorized);
+    }
+    lockout_service::record_success(state, &user.email).await?;
+
+    let tokens = issue_tokens_and_store_refresh(state, user.id, device).await?;
+    auth_service::record_login(state, user.id, "totp", device).await;
+    Ok(tokens)
+}
+
+/// One-step variant for the OAuth login page, where the code is posted with the password.
+pub(crate) async fn check_login_code(
+    state: &AppState,
+    user_id: ObjectId,
+    code: Option<&str>,
+) -> Result<(), AppError> {
+    let Some(factor) = enabled_factor(state, user_id).await? else {
+        return Ok(());
+    };
+
+    match code.filter(|c| !c.trim().is_empty()) {
+        Some(code) if verify_code(state, &factor, code, true).await? => Ok(()),
+        _ => Err(AppError::Unauthorized),
+    }
+}
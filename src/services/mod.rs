@@ -1 +1,13 @@
+pub mod admin_service;
+pub mod api_key_service;
+pub mod audit_service;
 pub mod auth_service;
+pub mod email_service;
+pub mod lockout_service;
+pub mod mfa_service;
+pub mod oauth_service;
+pub mod org_service;
+pub mod passkey_service;
+pub mod password_service;
+pub mod plan_service;
+pub mod usage_service;
@@ -0,0 +1,475 @@
+//! OAuth 2.0 authorization server: authorization code grant with PKCE (S256 only)
+//! and `client_credentials` for confidential clients.
+//!
+//! User tokens are the same JWT access/refresh pair as `/auth/login`, issued through
+//! `issue_tokens_for_grant`, so refresh rotation and reuse detection apply as is.
+//! Client secrets are stored like API keys: only their sha256.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        jwt::sha256_hex,
+        oidc::{make_id_token, scoped_claims},
+        scopes::{
+            split_scope, SCOPE_API, SCOPE_API_KEYS, SCOPE_EMAIL, SCOPE_OPENID, SCOPE_PROFILE,
+        },
+        tokens::{
+            issue_client_access_token, issue_tokens_for_grant, random_token, ClientDevice,
+            IssuedTokens, TokenGrant,
+        },
+    },
+    dto::{
+        admin::{CreateOAuthClientRequest, CreateOAuthClientResponse},
+        oauth::{
+            AuthorizeRequest, OpenIdConfiguration, TokenRequest, TokenResponse, UserInfoResponse,
+        },
+    },
+    errors::AppError,
+    models::{
+        auth_code::AuthCodeDoc,
+        oauth_client::{OAuthClientDoc, OAuthClientPublic},
+    },
+    services::{
+        api_key_service::normalize_scopes,
+        auth_service::{self, require_non_empty, rotate_refresh_token},
+    },
+    state::AppState,
+};
+
+pub const AUTH_CODE_TTL_SECONDS: i64 = 60;
+
+fn oauth_error(code: &'static str, description: impl Into<String>) -> AppError {
+    AppError::OAuth(code, description.into())
+}
+
+/// https, or plain http for loopback (RFC 8252 §7.3); no fragment (RFC 6749 §3.1.2).
+fn validate_redirect_uri(uri: &str) -> Result<(), AppError> {
+    let invalid = || AppError::Validation(format!("invalid redirect_uri: {uri}"));
+    let url = Url::parse(uri).map_err(|_| invalid())?;
+
+    let loopback = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    let scheme_ok = url.scheme() == "https" || (url.scheme() == "http" && loopback);
+    if !scheme_ok || url.fragment().is_some() {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+pub async fn create_client(
+    state: &AppState,
+    req: CreateOAuthClientRequest,
+) -> Result<CreateOAuthClientResponse, AppError> {
+    let name = req.name.trim().to_string();
+    require_non_empty(&name, "name")?;
+
+    // a public client can only use the authorization code flow
+    if req.redirect_uris.is_empty() && !req.confidential {
+        return Err(AppError::Validation(
+            "redirect_uris is required for public clients".into(),
+        ));
+    }
+    for uri in &req.redirect_uris {
+        validate_redirect_uri(uri)?;
+    }
+
+    let scopes = normalize_scopes(req.scopes)?;
+    if scopes.is_empty() {
+        return Err(AppError::Validation("scopes is required".into()));
+    }
+
+    let client_secret = req.confidential.then(random_token);
+
+    let client = OAuthClientDoc {
+        id: ObjectId::new(),
+        client_id: Uuid::new_v4().simple().to_string(),
+        name,
+        redirect_uris: req.redirect_uris,
+        scopes,
+        client_secret_hash: client_secret.as_deref().map(sha256_hex),
+        active: true,
+        created_at: BsonDateTime::now(),
+    };
+    state.oauth_clients.insert(&client).await?;
+
+    Ok(CreateOAuthClientResponse {
+        client: OAuthClientPublic::from(client),
+        client_secret,
+    })
+}
+
+pub async fn list_clients(state: &AppState) -> Result<Vec<OAuthClientPublic>, AppError> {
+    let clients = state.oauth_clients.list().await?;
+    Ok(clients.into_iter().map(OAuthClientPublic::from).collect())
+}
+
+/// Client + exact `redirect_uri` match. These errors are shown to the user and
+/// never redirected (RFC 6749 §4.1.2.1).
+pub async fn resolve_client(
+    state: &AppState,
+    client_id: &str,
+    redirect_uri: &str,
+) -> Result<OAuthClientDoc, AppError> {
+    let client = state
+        .oauth_clients
+        .find_active(client_id)
+        .await?
+        .ok_or_else(|| AppError::Validation("unknown client_id".into()))?;
+
+    if !client.redirect_uris.iter().any(|u| u == redirect_uri) {
+        return Err(AppError::Validation(
+            "redirect_uri is not registered for this client".into(),
+        ));
+    }
+    Ok(client)
+}
+
+/// Requested scopes must be a subset of the client's; none requested means all of them.
+fn granted_scopes(
+    client: &OAuthClientDoc,
+    requested: Option<&str>,
+) -> Result<Vec<String>, AppError> {
+    let Some(requested) = requested.filter(|s| !s.trim().is_empty()) else {
+        return Ok(client.scopes.clone());
+    };
+
+    // drops repeated scopes wherever they are, keeping the requested order
+    let scopes = normalize_scopes(split_scope(requested))?;
+    if let Some(s) = scopes.iter().find(|s| !client.scopes.contains(s)) {
+        return Err(oauth_error(
+            "invalid_scope",
+            format!("scope {s} is not allowed for this client"),
+        ));
+    }
+    Ok(scopes)
+}
+
+/// Checks the rest of the authorization request, returns the scopes to grant.
+/// Errors are `AppError::OAuth` and go back to the client via `error_redirect`.
+pub fn validate_authorize(
+    client: &OAuthClientDoc,
+    req: &AuthorizeRequest,
+) -> Result<Vec<String>, AppError> {
+    if req.response_type.as_deref() != Some("code") {
+        return Err(oauth_error(
+            "unsupported_response_type",
+            "response_type must be code",
+        ));
+    }
+
+    let challenge = req.code_challenge.as_deref().unwrap_or_default();
+    let challenge_ok = URL_SAFE_NO_PAD
+        .decode(challenge)
+        .is_ok_and(|raw| raw.len() == 32);
+    if !challenge_ok {
+        return Err(oauth_error(
+            "invalid_request",
+            "code_challenge (BASE64URL of SHA-256) is required",
+        ));
+    }
+    if req.code_challenge_method.as_deref() != Some("S256") {
+        return Err(oauth_error(
+            "invalid_request",
+            "code_challenge_method must be S256",
+        ));
+    }
+
+    granted_scopes(client, req.scope.as_deref())
+}
+
+/// `redirect_uri` with extra query parameters (existing query is kept).
+fn redirect_with(redirect_uri: &str, params: &[(&str, &str)]) -> Result<String, AppError> {
+    let mut url = Url::parse(redirect_uri)
+        .map_err(|_| AppError::Internal("registered redirect_uri is invalid".into()))?;
+    url.query_pairs_mut().extend_pairs(params);
+    Ok(url.into())
+}
+
+/// Error response of the authorization endpoint (RFC 6749 §4.1.2.1): OAuth errors
+/// are sent to the (already validated) `redirect_uri`, anything else is returned.
+pub fn error_redirect(req: &AuthorizeRequest, err: AppError) -> Result<String, AppError> {
+    let AppError::OAuth(code, description) = err else {
+        return Err(err);
+    };
+
+    let mut params = vec![("error", code), ("error_description", description.as_str())];
+    if let Some(s) = req.state.as_deref() {
+        params.push(("state", s));
+    }
+    redirect_with(&req.redirect_uri, &params)
+}
+
+/// Stores a code for the authenticated user, returns where to redirect the user agent.
+pub async fn issue_code(
+    state: &AppState,
+    client: &OAuthClientDoc,
+    req: &AuthorizeRequest,
+    user_id: ObjectId,
+    scopes: Vec<String>,
+) -> Result<String, AppError> {
+    let code = random_token();
+    let now = Utc::now();
+
+    let doc = AuthCodeDoc {
+        id: ObjectId::new(),
+        code_hash: sha256_hex(&code),
+        client_id: client.client_id.clone(),
+        user_id,
+        redirect_uri: req.redirect_uri.clone(),
+        scopes,
+        code_challenge: req.code_challenge.clone().unwrap_or_default(),
+        nonce: req.nonce.clone(),
+        auth_time: BsonDateTime::from_chrono(now),
+        created_at: BsonDateTime::from_chrono(now),
+        expires_at: BsonDateTime::from_chrono(now + Duration::seconds(AUTH_CODE_TTL_SECONDS)),
+    };
+    state.auth_codes.insert(&doc).await?;
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(s) = req.state.as_deref() {
+        params.push(("state", s));
+    }
+    redirect_with(&req.redirect_uri, &params)
+}
+
+fn required<'a>(value: Option<&'a str>, field: &str) -> Result<&'a str, AppError> {
+    value
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| oauth_error("invalid_request", format!("{field} is required")))
+}
+
+/// RFC 7636 §4.6: BASE64URL(SHA256(code_verifier)) == code_challenge.
+fn verify_pkce(verifier: &str, challenge: &str) -> bool {
+    let well_formed = (43..=128).contains(&verifier.len())
+        && verifier
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b));
+
+    well_formed && URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == challenge
+}
+
+/// Public clients send only `client_id`; confidential ones must prove their secret.
+async fn authenticate_client(
+    state: &AppState,
+    req: &TokenRequest,
+) -> Result<OAuthClientDoc, AppError> {
+    let client_id = required(req.client_id.as_deref(), "client_id")?;
+    let client = state
+        .oauth_clients
+        .find_active(client_id)
+        .await?
+        .ok_or_else(|| oauth_error("invalid_client", "unknown client"))?;
+
+    let presented = req.client_secret.as_deref().filter(|s| !s.is_empty());
+    let authenticated = match (&client.client_secret_hash, presented) {
+        (Some(hash), Some(secret)) => sha256_hex(secret) == *hash,
+        (None, None) => true,
+        _ => false,
+    };
+    if !authenticated {
+        return Err(oauth_error(
+            "invalid_client",
+            "client authentication failed",
+        ));
+    }
+
+    Ok(client)
+}
+
+fn token_response(
+    state: &AppState,
+    tokens: IssuedTokens,
+    scopes: Option<&[String]>,
+) -> TokenResponse {
+    TokenResponse {
+        access_token: tokens.access_token,
+        token_type: tokens.token_type,
+        expires_in: state.cfg.jwt_access_ttl_seconds,
+        refresh_token: Some(tokens.refresh_token),
+        scope: scopes.map(|s| s.join(" ")),
+        id_token: None,
+    }
+}
+
+async fn exchange_code(
+    state: &AppState,
+    req: &TokenRequest,
+    device: &ClientDevice,
+) -> Result<TokenResponse, AppError> {
+    let client = authenticate_client(state, req).await?;
+    let code = required(req.code.as_deref(), "code")?;
+    let redirect_uri = required(req.redirect_uri.as_deref(), "redirect_uri")?;
+    let verifier = required(req.code_verifier.as_deref(), "code_verifier")?;
+
+    // taken (deleted) before any other check: a code is never usable twice
+    let doc = state
+        .auth_codes
+        .take_by_hash(&sha256_hex(code), BsonDateTime::now())
+        .await?
+        .ok_or_else(|| oauth_error("invalid_grant", "authorization code is invalid or expired"))?;
+
+    if doc.client_id != client.client_id || doc.redirect_uri != redirect_uri {
+        return Err(oauth_error(
+            "invalid_grant",
+            "authorization code was issued to another client or redirect_uri",
+        ));
+    }
+    if !verify_pkce(verifier, &doc.code_challenge) {
+        return Err(oauth_error("invalid_grant", "code_verifier does not match"));
+    }
+
+    let grant = TokenGrant {
+        client_id: Some(client.client_id),
+        scopes: Some(doc.scopes.clone()),
+        ..TokenGrant::default()
+    };
+    let tokens = issue_tokens_for_grant(state, doc.user_id, &grant, device).await?;
+
+    let mut out = token_response(state, tokens, Some(&doc.scopes));
+    if doc.scopes.iter().any(|s| s == SCOPE_OPENID) {
+        let user = auth_service::me(state, doc.user_id).await?;
+        out.id_token = Some(make_id_token(
+            state,
+            &user,
+            &doc.client_id,
+            &doc.scopes,
+            doc.nonce,
+            doc.auth_time,
+        )?);
+    }
+    Ok(out)
+}
+
+async fn refresh(
+    state: &AppState,
+    req: &TokenRequest,
+    device: &ClientDevice,
+) -> Result<TokenResponse, AppError> {
+    let client = authenticate_client(state, req).await?;
+    let refresh_token = required(req.refresh_token.as_deref(), "refresh_token")?;
+
+    let tokens = rotate_refresh_token(state, refresh_token, Some(&client.client_id), None, device)
+        .await
+        .map_err(|e| match e {
+            AppError::Unauthorized | AppError::Jwt => oauth_error(
+                "invalid_grant",
+                "refresh token is invalid, expired or revoked",
+            ),
+            e => e,
+        })?;
+
+    Ok(token_response(state, tokens, None))
+}
+
+/// RFC 6749 §4.4: the client acts on its own behalf, access token only.
+async fn client_credentials(
+    state: &AppState,
+    req: &TokenRequest,
+) -> Result<TokenResponse, AppError> {
+    let client = authenticate_client(state, req).await?;
+    if client.client_secret_hash.is_none() {
+        return Err(oauth_error(
+            "unauthorized_client",
+            "client_credentials requires a confidential client",
+        ));
+    }
+
+    let scopes = granted_scopes(&client, req.scope.as_deref())?;
+    let access_token = issue_client_access_token(state, &client.client_id, &scopes)?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: state.cfg.oauth_client_token_ttl_seconds,
+        refresh_token: None,
+        scope: Some(scopes.join(" ")),
+        id_token: None,
+    })
+}
+
+/// Token endpoint: dispatches on `grant_type`. `device` is the caller of the endpoint
+/// (for confidential clients, their backend).
+pub async fn token(
+    state: &AppState,
+    req: TokenRequest,
+    device: &ClientDevice,
+) -> Result<TokenResponse, AppError> {
+    match req.grant_type.as_str() {
+        "authorization_code" => exchange_code(state, &req, device).await,
+        "refresh_token" => refresh(state, &req, device).await,
+        "client_credentials" => client_credentials(state, &req).await,
+        other => Err(oauth_error(
+            "unsupported_grant_type",
+            format!("grant_type {other} is not supported"),
+        )),
+    }
+}
+
+/// OIDC UserInfo (Core §5.3) for the user behind an access token.
+pub async fn userinfo(
+    state: &AppState,
+    user_id: ObjectId,
+    scopes: &[String],
+) -> Result<UserInfoResponse, AppError> {
+    let user = auth_service::me(state, user_id).await?;
+    let (email, name) = scoped_claims(&user, scopes);
+
+    Ok(UserInfoResponse {
+        sub: user.id,
+        email,
+        name,
+    })
+}
+
+/// OIDC Discovery document; every endpoint is derived from `OIDC_ISSUER`.
+pub fn openid_configuration(state: &AppState) -> Result<OpenIdConfiguration, AppError> {
+    let issuer = &state.cfg.issuer;
+    let alg = state.keyring.signing_key()?.alg;
+    let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    Ok(OpenIdConfiguration {
+        issuer: issuer.clone(),
+        authorization_endpoint: format!("{issuer}/oauth/authorize"),
+        token_endpoint: format!("{issuer}/oauth/token"),
+        userinfo_endpoint: format!("{issuer}/userinfo"),
+        jwks_uri: format!("{issuer}/.well-known/jwks.json"),
+        introspection_endpoint: format!("{issuer}/auth/introspect"),
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&[
+            "authorization_code",
+            "refresh_token",
+            "client_credentials",
+        ]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: vec![format!("{alg:?}")],
+        scopes_supported: strings(&[
+            SCOPE_OPENID,
+            SCOPE_EMAIL,
+            SCOPE_PROFILE,
+            SCOPE_API,
+            SCOPE_API_KEYS,
+        ]),
+        claims_supported: strings(&[
+            "iss",
+            "sub",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "email",
+            "name",
+        ]),
+        token_endpoint_auth_methods_supported: strings(&[
+            "none",
+            "client_secret_basic",
+            "client_secret_post",
+        ]),
+        code_challenge_methods_supported: strings(&["S256"]),
+    })
+}
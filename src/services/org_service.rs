@@ -0,0 +1,468 @@
+//! Organizations, their members and organization-owned API keys.
+//!
+//! Non-members get 404 for an organization, so ids cannot be probed; members
+//! without the needed role get 403.
+
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    auth::{
+        jwt::decode_token,
+        tokens::{ClientDevice, IssuedTokens},
+    },
+    dto::{
+        api_key::{CreateApiKeyRequest, UsageQuery, UsageReport},
+        org::{
+            AddMemberRequest, CreateOrganizationRequest, MemberPublic,
+            OrganizationMembershipPublic, SwitchOrganizationRequest, UpdateMemberRequest,
+        },
+    },
+    errors::AppError,
+    models::{
+        api_key::{ApiKeyOwner, ApiKeyPublic},
+        audit_event::{AuditAction, AuditOutcome},
+        bson_to_rfc3339,
+        membership::{MembershipDoc, OrgRole},
+        organization::OrganizationDoc,
+    },
+    services::{
+        api_key_service::{self, CreatedApiKey},
+        audit_service::AuditEntry,
+        auth_service::{self, require_non_empty},
+        usage_service,
+    },
+    state::AppState,
+};
+
+const MAX_NAME_LEN: usize = 100;
+
+fn parse_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|_| AppError::NotFound)
+}
+
+fn summary(org: OrganizationDoc, role: OrgRole) -> OrganizationMembershipPublic {
+    OrganizationMembershipPublic {
+        id: org.id.to_hex(),
+        name: org.name,
+        role,
+        created_at: bson_to_rfc3339(org.created_at),
+    }
+}
+
+/// The caller's membership in organization `org_id`; 404 for non-members.
+async fn membership(
+    state: &AppState,
+    org_id: &str,
+    user_id: ObjectId,
+) -> Result<MembershipDoc, AppError> {
+    state
+        .memberships
+        .find(parse_id(org_id)?, user_id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+/// Like `membership`, but the caller must be an owner or admin.
+async fn manager(
+    state: &AppState,
+    org_id: &str,
+    user_id: ObjectId,
+) -> Result<MembershipDoc, AppError> {
+    let m = membership(state, org_id, user_id).await?;
+    if !m.role.can_manage() {
+        return Err(AppError::Forbidden(
+            "organization admin role required".into(),
+        ));
+    }
+    Ok(m)
+}
+
+/// Owners can only be appointed, changed or removed by owners, and the last owner
+/// cannot be demoted or removed.
+async fn check_owner_change(
+    state: &AppState,
+    caller: &MembershipDoc,
+    target: &MembershipDoc,
+    new_role: Option<OrgRole>,
+) -> Result<(), AppError> {
+    let touches_owner = target.role == OrgRole::Owner || new_role == Some(OrgRole::Owner);
+    if touches_owner && caller.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "organization owner role required".into(),
+        ));
+    }
+
+    if target.role == OrgRole::Owner && new_role != Some(OrgRole::Owner) {
+        let owners = state
+            .memberships
+            .list_for_org(target.org_id)
+            .await?
+            .into_iter()
+            .filter(|m| m.role == OrgRole::Owner)
+            .count();
+        if owners <= 1 {
+            return Err(AppError::Conflict(
+                "organization must keep at least one owner".into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+async fn audit_member(
+    state: &AppState,
+    action: AuditAction,
+    caller: ObjectId,
+    member: ObjectId,
+    org_id: ObjectId,
+    detail: Option<String>,
+    device: &ClientDevice,
+) {
+    let mut entry = AuditEntry::new(action, AuditOutcome::Success, device)
+        .subject(member)
+        .actor(caller.to_hex())
+        .target(org_id.to_hex());
+    if let Some(detail) = detail {
+        entry = entry.detail(detail);
+    }
+    entry.record(state).await;
+}
+
+/// Creates an organization with the caller as its owner.
+pub async fn create(
+    state: &AppState,
+    user_id: ObjectId,
+    req: CreateOrganizationRequest,
+) -> Result<OrganizationMembershipPublic, AppError> {
+    let name = req.name.trim().to_string();
+    require_non_empty(&name, "name")?;
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::Validation(format!(
+            "name must be at most {MAX_NAME_LEN} characters"
+        )));
+    }
+
+    let now = BsonDateTime::now();
+    let org = OrganizationDoc {
+        id: ObjectId::new(),
+        name,
+        created_by: user_id,
+        created_at: now,
+        plan: Some(state.cfg.default_plan.clone()),
+    };
+    state.organizations.insert(&org).await?;
+    state
+        .memberships
+        .insert(&MembershipDoc {
+            id: ObjectId::new(),
+            org_id: org.id,
+            user_id,
+            role: OrgRole::Owner,
+            created_at: now,
+        })
+        .await?;
+
+    Ok(summary(org, OrgRole::Owner))
+}
+
+/// Organizations the user belongs to, with their role.
+pub async fn list_mine(
+    state: &AppState,
+    user_id: ObjectId,
+) -> Result<Vec<OrganizationMembershipPublic>, AppError> {
+    let memberships = state.memberships.list_for_user(user_id).await?;
+    let ids: Vec<ObjectId> = memberships.iter().map(|m| m.org_id).collect();
+    let orgs = state.organizations.find_many(&ids).await?;
+
+    Ok(memberships
+        .into_iter()
+        .filter_map(|m| {
+            let org = orgs.iter().find(|o| o.id == m.org_id)?.clone();
+            Some(summary(org, m.role))
+        })
+        .collect())
+}
+
+pub async fn get(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+) -> Result<OrganizationMembershipPublic, AppError> {
+    let m = membership(state, org_id, user_id).await?;
+    let org = state
+        .organizations
+        .find_by_id(m.org_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(summary(org, m.role))
+}
+
+/// Deletes the organization together with its memberships and API keys (owners only).
+pub async fn delete(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let m = membership(state, org_id, user_id).await?;
+    if m.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "organization owner role required".into(),
+        ));
+    }
+
+    let keys = state
+        .api_keys
+        .delete_all_for_owner(ApiKeyOwner::Org(m.org_id))
+        .await?;
+    state.quota.evict_owner(ApiKeyOwner::Org(m.org_id))?;
+    let members = state.memberships.delete_for_org(m.org_id).await?;
+    state.organizations.delete(m.org_id).await?;
+
+    AuditEntry::new(AuditAction::OrgDelete, AuditOutcome::Success, device)
+        .user(user_id)
+        .target(m.org_id.to_hex())
+        .detail(format!("{members} members, {keys} api keys removed"))
+        .record(state)
+        .await;
+    Ok(())
+}
+
+pub async fn list_members(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+) -> Result<Vec<MemberPublic>, AppError> {
+    let m = membership(state, org_id, user_id).await?;
+
+    let mut out = Vec::new();
+    for member in state.memberships.list_for_org(m.org_id).await? {
+        let Some(user) = state.users.find_by_id(member.user_id).await? else {
+            continue;
+        };
+        out.push(MemberPublic {
+            user_id: user.id.to_hex(),
+            email: user.email,
+            name: user.name,
+            role: member.role,
+            joined_at: bson_to_rfc3339(member.created_at),
+        });
+    }
+    Ok(out)
+}
+
+/// Adds an existing user by email (owners and admins; only owners add owners).
+pub async fn add_member(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    req: AddMemberRequest,
+    device: &ClientDevice,
+) -> Result<MemberPublic, AppError> {
+    let caller = manager(state, org_id, user_id).await?;
+    let role = req.role.unwrap_or(OrgRole::Member);
+    if role == OrgRole::Owner && caller.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "organization owner role required".into(),
+        ));
+    }
+
+    let email = auth_service::normalize_email(&req.email);
+    require_non_empty(&email, "email")?;
+    let user = state
+        .users
+        .find_by_email(&email)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    if state
+        .memberships
+        .find(caller.org_id, user.id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict("already a member".into()));
+    }
+    let member = MembershipDoc {
+        id: ObjectId::new(),
+        org_id: caller.org_id,
+        user_id: user.id,
+        role,
+        created_at: BsonDateTime::now(),
+    };
+    state.memberships.insert(&member).await?;
+
+    audit_member(
+        state,
+        AuditAction::OrgMemberAdd,
+        user_id,
+        user.id,
+        caller.org_id,
+        Some(format!("role={}", role.as_str())),
+        device,
+    )
+    .await;
+
+    Ok(MemberPublic {
+        user_id: user.id.to_hex(),
+        email: user.email,
+        name: user.name,
+        role,
+        joined_at: bson_to_rfc3339(member.created_at),
+    })
+}
+
+/// Changes a member's role. The member's tokens keep the old `org_role` until
+/// their next refresh.
+pub async fn update_member(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    member_id: &str,
+    req: UpdateMemberRequest,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let caller = manager(state, org_id, user_id).await?;
+    let target = state
+        .memberships
+        .find(caller.org_id, parse_id(member_id)?)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    check_owner_change(state, &caller, &target, Some(req.role)).await?;
+
+    state
+        .memberships
+        .set_role(caller.org_id, target.user_id, req.role)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    audit_member(
+        state,
+        AuditAction::OrgMemberUpdate,
+        user_id,
+        target.user_id,
+        caller.org_id,
+        Some(format!(
+            "role {} -> {}",
+            target.role.as_str(),
+            req.role.as_str()
+        )),
+        device,
+    )
+    .await;
+    Ok(())
+}
+
+/// Removes a member (owners and admins), or leaves the organization (any member).
+pub async fn remove_member(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    member_id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let caller = membership(state, org_id, user_id).await?;
+    let target_id = parse_id(member_id)?;
+    let target = if target_id == user_id {
+        caller.clone()
+    } else {
+        if !caller.role.can_manage() {
+            return Err(AppError::Forbidden(
+                "organization admin role required".into(),
+            ));
+        }
+        state
+            .memberships
+            .find(caller.org_id, target_id)
+            .await?
+            .ok_or(AppError::NotFound)?
+    };
+    check_owner_change(state, &caller, &target, None).await?;
+
+    if !state
+        .memberships
+        .delete(caller.org_id, target.user_id)
+        .await?
+    {
+        return Err(AppError::NotFound);
+    }
+
+    audit_member(
+        state,
+        AuditAction::OrgMemberRemove,
+        user_id,
+        target.user_id,
+        caller.org_id,
+        None,
+        device,
+    )
+    .await;
+    Ok(())
+}
+
+/// Rotates the refresh token into a session acting in organization `org_id`
+/// (`None`: personal). The caller must be a member.
+pub async fn switch(
+    state: &AppState,
+    req: SwitchOrganizationRequest,
+    device: &ClientDevice,
+) -> Result<IssuedTokens, AppError> {
+    require_non_empty(&req.refresh_token, "refresh_token")?;
+
+    let org_id = match req.org_id.as_deref() {
+        None => None,
+        Some(id) => {
+            // the rotation below validates the token fully; here only who is asking
+            let claims = decode_token(&state.keyring, &req.refresh_token)
+                .await?
+                .claims;
+            let user_id = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)?;
+            Some(membership(state, id, user_id).await?.org_id)
+        }
+    };
+
+    auth_service::rotate_refresh_token(state, &req.refresh_token, None, Some(org_id), device).await
+}
+
+pub async fn create_api_key(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    req: CreateApiKeyRequest,
+    device: &ClientDevice,
+) -> Result<CreatedApiKey, AppError> {
+    let m = manager(state, org_id, user_id).await?;
+    api_key_service::create(state, ApiKeyOwner::Org(m.org_id), user_id, req, device).await
+}
+
+pub async fn list_api_keys(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+) -> Result<Vec<ApiKeyPublic>, AppError> {
+    let m = membership(state, org_id, user_id).await?;
+    api_key_service::list(state, ApiKeyOwner::Org(m.org_id)).await
+}
+
+pub async fn delete_api_key(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    key_id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let m = manager(state, org_id, user_id).await?;
+    api_key_service::delete(state, ApiKeyOwner::Org(m.org_id), user_id, key_id, device).await
+}
+
+/// Usage of an organization key (any member; billing goes by organization).
+pub async fn api_key_usage(
+    state: &AppState,
+    user_id: ObjectId,
+    org_id: &str,
+    key_id: &str,
+    query: &UsageQuery,
+) -> Result<UsageReport, AppError> {
+    let m = membership(state, org_id, user_id).await?;
+    usage_service::for_owner(state, ApiKeyOwner::Org(m.org_id), key_id, query).await
+}
@@ -0,0 +1,348 @@
+//! Passkeys (WebAuthn): registration by a signed-in user and passwordless login.
+//!
+//! Both ceremonies are two requests: `*_start` stores a single-use challenge and
+//! returns the options for `navigator.credentials.create()/get()`, `*_finish`
+//! checks the authenticator response against it.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    auth::{
+        jwt::sha256_hex,
+        tokens::{issue_tokens_and_store_refresh, random_token, ClientDevice, IssuedTokens},
+        webauthn::{
+            attestation_auth_data, cose_key_alg, invalid, parse_client_data, verify_assertion,
+            AuthenticatorData, SUPPORTED_ALGS,
+        },
+    },
+    dto::passkey::{
+        AssertionCredential, AuthenticatorSelection, CredentialDescriptor, CredentialParameter,
+        PasskeyLoginOptions, PasskeyRegisterFinishRequest, PasskeyRegistrationOptions,
+        PasskeyUserEntity, RelyingParty,
+    },
+    errors::AppError,
+    models::{
+        audit_event::{AuditAction, AuditOutcome},
+        passkey::{PasskeyDoc, PasskeyPublic},
+        webauthn_challenge::{WebAuthnCeremony, WebAuthnChallengeDoc},
+    },
+    services::{audit_service::AuditEntry, auth_service},
+    state::AppState,
+};
+
+const CEREMONY_TTL_SECONDS: i64 = 300;
+const PUBLIC_KEY: &str = "public-key";
+
+/// Browsers send unpadded base64url; tolerate padding anyway.
+fn decode_b64(value: &str, field: &str) -> Result<Vec<u8>, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| invalid(&format!("{field} is not base64url")))
+}
+
+fn user_handle(user_id: ObjectId) -> String {
+    URL_SAFE_NO_PAD.encode(user_id.bytes())
+}
+
+async fn new_challenge(
+    state: &AppState,
+    ceremony: WebAuthnCeremony,
+    user_id: Option<ObjectId>,
+) -> Result<String, AppError> {
+    let challenge = random_token();
+    let now = Utc::now();
+
+    let doc = WebAuthnChallengeDoc {
+        id: ObjectId::new(),
+        challenge_hash: sha256_hex(&challenge),
+        ceremony,
+        user_id,
+        created_at: BsonDateTime::from_chrono(now),
+        expires_at: BsonDateTime::from_chrono(now + Duration::seconds(CEREMONY_TTL_SECONDS)),
+    };
+    state.webauthn_challenges.insert(&doc).await?;
+
+    Ok(challenge)
+}
+
+async fn take_challenge(
+    state: &AppState,
+    challenge: &str,
+    ceremony: WebAuthnCeremony,
+) -> Result<WebAuthnChallengeDoc, AppError> {
+    state
+        .webauthn_challenges
+        .take_by_hash(&sha256_hex(challenge), ceremony, BsonDateTime::now())
+        .await?
+        .ok_or_else(|| AppError::Validation("unknown or expired challenge".into()))
+}
+
+pub async fn register_start(
+    state: &AppState,
+    user_id: ObjectId,
+) -> Result<PasskeyRegistrationOptions, AppError> {
+    let user = state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let exclude_credentials = state
+        .passkeys
+        .list_for_user(user_id)
+        .await?
+        .into_iter()
+        .map(|p| CredentialDescriptor {
+            typ: PUBLIC_KEY.into(),
+            id: p.credential_id,
+        })
+        .collect();
+
+    let challenge = new_challenge(state, WebAuthnCeremony::Registration, Some(user_id)).await?;
+
+    Ok(PasskeyRegistrationOptions {
+        challenge,
+        rp: RelyingParty {
+            id: state.cfg.webauthn_rp_id.clone(),
+            name: state.cfg.webauthn_rp_name.clone(),
+        },
+        user: PasskeyUserEntity {
+            id: user_handle(user_id),
+            name: user.email,
+            display_name: user.name,
+        },
+        pub_key_cred_params: SUPPORTED_ALGS
+            .iter()
+            .map(|&alg| CredentialParameter {
+                typ: PUBLIC_KEY.into(),
+                alg,
+            })
+            .collect(),
+        timeout: (CEREMONY_TTL_SECONDS * 1000) as u64,
+        attestation: "none".into(),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "required".into(),
+            require_resident_key: true,
+            user_verification: "required".into(),
+        },
+        exclude_credentials,
+    })
+}
+
+pub async fn register_finish(
+    state: &AppState,
+    user_id: ObjectId,
+    req: PasskeyRegisterFinishRequest,
+    device: &ClientDevice,
+) -> Result<PasskeyPublic, AppError> {
+    let cred = req.credential;
+    if cred.typ != PUBLIC_KEY {
+        return Err(invalid("type must be public-key"));
+    }
+
+    let client_data_json = decode_b64(&cred.response.client_data_json, "clientDataJSON")?;
+    let client_data = parse_client_data(
+        &client_data_json,
+        "webauthn.create",
+        &state.cfg.webauthn_origins,
+    )?;
+
+    let challenge = take_challenge(
+        state,
+        &client_data.challenge,
+        WebAuthnCeremony::Registration,
+    )
+    .await?;
+    if challenge.user_id != Some(user_id) {
+        return Err(AppError::Validation("unknown or expired challenge".into()));
+    }
+
+    let attestation_object = decode_b64(&cred.response.attestation_object, "attestationObject")?;
+    let auth_data = AuthenticatorData::parse(&attestation_auth_data(&attestation_object)?)?;
+    auth_data.check(&state.cfg.webauthn_rp_id)?;
+
+    let attested = auth_data
+        .credential
+        .ok_or_else(|| invalid("no attested credential"))?;
+    let credential_id = URL_SAFE_NO_PAD.encode(&attested.credential_id);
+    if credential_id != cred.id.trim_end_matches('=') {
+        return Err(invalid("credential id mismatch"));
+    }
+    let alg = cose_key_alg(&attested.public_key)?;
+
+    if state
+        .passkeys
+        .find_by_credential_id(&credential_id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict("passkey already registered".into()));
+    }
+
+    let name = req
+        .name
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| "Passkey".to_string());
+
+    let passkey = PasskeyDoc {
+        id: ObjectId::new(),
+        user_id,
+        name,
+        credential_id,
+        public_key: attested.public_key,
+        alg,
+        sign_count: auth_data.sign_count as i64,
+        created_at: BsonDateTime::now(),
+        last_used_at: None,
+    };
+    state.passkeys.insert(&passkey).await?;
+
+    AuditEntry::new(AuditAction::PasskeyAdd, AuditOutcome::Success, device)
+        .user(user_id)
+        .target(passkey.id.to_hex())
+        .record(state)
+        .await;
+    Ok(PasskeyPublic::from(passkey))
+}
+
+pub async fn list(state: &AppState, user_id: ObjectId) -> Result<Vec<PasskeyPublic>, AppError> {
+    let passkeys = state.passkeys.list_for_user(user_id).await?;
+    Ok(passkeys.into_iter().map(PasskeyPublic::from).collect())
+}
+
+pub async fn delete(
+    state: &AppState,
+    user_id: ObjectId,
+    id: &str,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    let id = ObjectId::parse_str(id).map_err(|_| AppError::NotFound)?;
+    if !state.passkeys.delete_for_user(id, user_id).await? {
+        return Err(AppError::NotFound);
+    }
+    AuditEntry::new(AuditAction::PasskeyRemove, AuditOutcome::Success, device)
+        .user(user_id)
+        .target(id.to_hex())
+        .record(state)
+        .await;
+    Ok(())
+}
+
+pub async fn login_start(state: &AppState) -> Result<PasskeyLoginOptions, AppError> {
+    let challenge = new_challenge(state, WebAuthnCeremony::Authentication, None).await?;
+
+    Ok(PasskeyLoginOptions {
+        challenge,
+        rp_id: state.cfg.webauthn_rp_id.clone(),
+        timeout: (CEREMONY_TTL_SECONDS * 1000) as u64,
+        user_verification: "required".into(),
+        allow_credentials: Vec::new(),
+    })
+}
+
+/// Passwordless login; a verified passkey counts as both factors, so TOTP is not asked.
+pub async fn login_finish(
+    state: &AppState,
+    cred: AssertionCredential,
+    device: &ClientDevice,
+) -> Result<IssuedTokens, AppError> {
+    if cred.typ != PUBLIC_KEY {
+        return Err(invalid("type must be public-key"));
+    }
+
+    let client_data_json = decode_b64(&cred.response.client_data_json, "clientDataJSON")?;
+    let client_data = parse_client_data(
+        &client_data_json,
+        "webauthn.get",
+        &state.cfg.webauthn_origins,
+    )?;
+    take_challenge(
+        state,
+        &client_data.challenge,
+        WebAuthnCeremony::Authentication,
+    )
+    .await?;
+
+    let passkey = state
+        .passkeys
+        .find_by_credential_id(cred.id.trim_end_matches('='))
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    if let Some(handle) = cred
+        .response
+        .user_handle
+        .as_deref()
+        .filter(|h| !h.is_empty())
+        && decode_b64(handle, "userHandle")? != passkey.user_id.bytes()
+    {
+        return Err(AppError::Unauthorized);
+    }
+
+    let auth_data_bytes = decode_b64(&cred.response.authenticator_data, "authenticatorData")?;
+    let auth_data = AuthenticatorData::parse(&auth_data_bytes)?;
+    auth_data.check(&state.cfg.webauthn_rp_id)?;
+
+    let signature = decode_b64(&cred.response.signature, "signature")?;
+    if !verify_assertion(
+        &passkey.public_key,
+        &auth_data_bytes,
+        &client_data_json,
+        &signature,
+    )? {
+        passkey_failure(state, &passkey, "invalid signature", device).await;
+        return Err(AppError::Unauthorized);
+    }
+
+    // clone detection: the counter must grow, unless the authenticator has none (always 0);
+    // checked again by the store, so concurrent assertions with one counter can't both pass
+    let sign_count = auth_data.sign_count as i64;
+    let counter_ok =
+        (sign_count == 0 && passkey.sign_count == 0) || sign_count > passkey.sign_count;
+    if !counter_ok
+        || !state
+            .passkeys
+            .record_use(passkey.id, sign_count, BsonDateTime::now())
+            .await?
+    {
+        tracing::warn!(
+            passkey_id = %passkey.id,
+            user_id = %passkey.user_id,
+            stored = passkey.sign_count,
+            received = sign_count,
+            "passkey counter did not increase, possible cloned authenticator"
+        );
+        passkey_failure(state, &passkey, "counter did not increase", device).await;
+        return Err(AppError::Unauthorized);
+    }
+
+    let user = state
+        .users
+        .find_by_id(passkey.user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    if state.cfg.require_verified_email && !user.email_verified {
+        return Err(AppError::Forbidden("email not verified".into()));
+    }
+
+    let tokens = issue_tokens_and_store_refresh(state, user.id, device).await?;
+    auth_service::record_login(state, user.id, "passkey", device).await;
+    Ok(tokens)
+}
+
+async fn passkey_failure(
+    state: &AppState,
+    passkey: &PasskeyDoc,
+    detail: &str,
+    device: &ClientDevice,
+) {
+    AuditEntry::new(AuditAction::Login, AuditOutcome::Failure, device)
+        .subject(passkey.user_id)
+        .target(passkey.id.to_hex())
+        .detail(format!("passkey: {detail}"))
+        .record(state)
+        .await;
+}
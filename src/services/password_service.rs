@@ -0,0 +1,106 @@
+//! Password reset: a single-use token is mailed on request and exchanged for a new password.
+
+use crate::{
+    auth::tokens::{consume_user_token, issue_user_token, ClientDevice},
+    dto::auth::{ForgotPasswordRequest, ResetPasswordRequest},
+    errors::AppError,
+    mail::Email,
+    models::{
+        audit_event::{AuditAction, AuditOutcome},
+        user::UserDoc,
+        user_token::UserTokenPurpose,
+    },
+    password::hash_password,
+    services::{
+        audit_service::AuditEntry,
+        auth_service::{self, normalize_email, require_non_empty},
+    },
+    state::AppState,
+};
+
+async fn send_reset(state: &AppState, user: &UserDoc) -> Result<(), AppError> {
+    // only the latest link works
+    state
+        .user_tokens
+        .delete_for_user(user.id, UserTokenPurpose::PasswordReset)
+        .await?;
+
+    let ttl = state.cfg.password_reset_ttl_seconds;
+    let token = issue_user_token(state, user.id, UserTokenPurpose::PasswordReset, ttl).await?;
+
+    let how_to = match &state.cfg.password_reset_url {
+        Some(url) => format!("Open this link to choose a new password:\n\n{url}?token={token}"),
+        None => format!(
+            "Use this token to choose a new password (POST {}/auth/password/reset):\n\n{token}",
+            state.cfg.issuer
+        ),
+    };
+
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nsomeone (hopefully you) asked to reset your password. {how_to}\n\n\
+             It is valid for {} minutes. If you did not ask for it, ignore this email.\n",
+            user.name,
+            ttl / 60,
+        ),
+    };
+    state.mailer.send(&email).await
+}
+
+/// Always succeeds: unknown addresses and mail failures are not reported, so the
+/// endpoint does not reveal which emails are registered. The token and the email
+/// are handled in the background, so both cases take the same time.
+pub async fn forgot(state: &AppState, req: ForgotPasswordRequest) -> Result<(), AppError> {
+    let email = normalize_email(&req.email);
+    require_non_empty(&email, "email")?;
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        let user = match state.users.find_by_email(&email).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!(error = %e, "password reset: user lookup failed");
+                return;
+            }
+        };
+        if let Err(e) = send_reset(&state, &user).await {
+            tracing::warn!(user_id = %user.id, error = %e, "failed to send password reset email");
+        }
+    });
+    Ok(())
+}
+
+/// Sets the new password and revokes every refresh token of the user.
+pub async fn reset(
+    state: &AppState,
+    req: ResetPasswordRequest,
+    device: &ClientDevice,
+) -> Result<(), AppError> {
+    require_non_empty(&req.token, "token")?;
+    require_non_empty(&req.password, "password")?;
+
+    // validate the password first, so a rejected one does not burn the token
+    let password_hash = hash_password(&req.password)?;
+    let doc = consume_user_token(state, &req.token, UserTokenPurpose::PasswordReset).await?;
+
+    state
+        .users
+        .set_password_hash(doc.user_id, &password_hash)
+        .await?;
+    state
+        .user_tokens
+        .delete_for_user(doc.user_id, UserTokenPurpose::PasswordReset)
+        .await?;
+
+    let revoked = auth_service::revoke_all_tokens(state, doc.user_id).await?;
+    tracing::info!(user_id = %doc.user_id, revoked, "password reset");
+    AuditEntry::new(AuditAction::PasswordReset, AuditOutcome::Success, device)
+        .user(doc.user_id)
+        .record(state)
+        .await;
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+//! Subscription plans: named API key quotas assigned to users and organizations.
+//!
+//! Keys copy the limits of their owner's plan (`ApiKeyDoc::plan`), so the quota
+//! check stays a single update; assigning or redefining a plan rewrites the
+//! limits of the affected keys in place.
+
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    auth::{admin::AdminAuth, roles::normalize_names, tokens::ClientDevice},
+    dto::admin::{SetPlanRequest, UpsertPlanRequest},
+    errors::AppError,
+    models::{
+        api_key::ApiKeyOwner,
+        audit_event::{AuditAction, AuditOutcome},
+        organization::OrganizationPublic,
+        plan::{PlanDoc, PlanPublic},
+        user::UserPublic,
+    },
+    services::{api_key_service::validate_quota, audit_service::AuditEntry},
+    state::AppState,
+    store::PlanKeys,
+};
+
+/// Configured plans with the stored ones applied on top.
+pub async fn list(state: &AppState) -> Result<Vec<PlanDoc>, AppError> {
+    let mut plans = state.cfg.plans.clone();
+    for stored in state.plans.list().await? {
+        match plans.iter_mut().find(|p| p.name == stored.name) {
+            Some(p) => *p = stored,
+            None => plans.push(stored),
+        }
+    }
+    Ok(plans)
+}
+
+pub async fn find(state: &AppState, name: &str) -> Result<Option<PlanDoc>, AppError> {
+    if let Some(plan) = state.plans.find(name).await? {
+        return Ok(Some(plan));
+    }
+    Ok(state.cfg.plans.iter().find(|p| p.name == name).cloned())
+}
+
+async fn default_plan(state: &AppState) -> Result<PlanDoc, AppError> {
+    find(state, &state.cfg.default_plan)
+        .await?
+        .ok_or_else(|| AppError::Internal("DEFAULT_PLAN is not defined".into()))
+}
+
+/// Plan by name for `name`, or DEFAULT_PLAN when unset or no longer defined.
+async fn resolve(state: &AppState, name: Option<&str>) -> Result<PlanDoc, AppError> {
+    if let Some(name) = name {
+        match find(state, name).await? {
+            Some(plan) => return Ok(plan),
+            None => tracing::warn!(plan = name, "unknown plan, using the default one"),
+        }
+    }
+    default_plan(state).await
+}
+
+/// Plan whose limits the owner's keys get.
+pub async fn for_owner(state: &AppState, owner: ApiKeyOwner) -> Result<PlanDoc, AppError> {
+    let name = match owner {
+        ApiKeyOwner::User(user_id) => {
+            state
+                .users
+                .find_by_id(user_id)
+                .await?
+                .ok_or(AppError::NotFound)?
+                .plan
+        }
+        ApiKeyOwner::Org(org_id) => {
+            state
+                .organizations
+                .find_by_id(org_id)
+                .await?
+                .ok_or(AppError::NotFound)?
+                .plan
+        }
+    };
+    resolve(state, name.as_deref()).await
+}
+
+/// Plan a key follows (its owner's if the key predates plans).
+pub async fn for_key(
+    state: &AppState,
+    plan: Option<&str>,
+    owner: ApiKeyOwner,
+) -> Result<PlanDoc, AppError> {
+    match plan {
+        Some(name) => resolve(state, Some(name)).await,
+        None => for_owner(state, owner).await,
+    }
+}
+
+/// Limits an owner may set on a key themselves: positive and within the plan.
+pub fn check_key_limits(
+    plan: &PlanDoc,
+    requests_per_minute: Option<i32>,
+    requests_per_day: Option<i64>,
+) -> Result<(), AppError> {
+    validate_quota(requests_per_minute, requests_per_day)?;
+    if requests_per_minute.is_some_and(|v| v > plan.requests_per_minute) {
+        return Err(AppError::Validation(format!(
+            "requests_per_minute exceeds the {} plan limit ({})",
+            plan.name, plan.requests_per_minute
+        )));
+    }
+    if requests_per_day.is_some_and(|v| v > plan.requests_per_day) {
+        return Err(AppError::Validation(format!(
+            "requests_per_day exceeds the {} plan limit ({})",
+            plan.name, plan.requests_per_day
+        )));
+    }
+    Ok(())
+}
+
+async fn known_plan(state: &AppState, name: &str) -> Result<PlanDoc, AppError> {
+    let name = name.trim().to_ascii_lowercase();
+    find(state, &name)
+        .await?
+        .ok_or_else(|| AppError::Validation(format!("unknown plan: {name:?}")))
+}
+
+/// Creates or redefines a plan; keys on it get the new limits at once.
+pub async fn upsert(
+    state: &AppState,
+    admin: &AdminAuth,
+    name: &str,
+    req: UpsertPlanRequest,
+    device: &ClientDevice,
+) -> Result<PlanPublic, AppError> {
+    let name = normalize_names(vec![name.to_string()], "plan")?.remove(0);
+    validate_quota(Some(req.requests_per_minute), Some(req.requests_per_day))?;
+
+    let plan = PlanDoc {
+        name,
+        requests_per_minute: req.requests_per_minute,
+        requests_per_day: req.requests_per_day,
+    };
+    state.plans.upsert(&plan).await?;
+    let keys = state
+        .api_keys
+        .apply_plan(PlanKeys::Plan(plan.name.clone()), &plan)
+        .await?;
+    state.quota.evict_plan(&plan.name)?;
+
+    AuditEntry::new(AuditAction::PlanChange, AuditOutcome::Success, device)
+        .actor(admin.actor())
+        .target(&plan.name)
+        .detail(format!(
+            "requests_per_minute={} requests_per_day={} keys={keys}",
+            plan.requests_per_minute, plan.requests_per_day
+        ))
+        .record(state)
+        .await;
+    Ok(plan.into())
+}
+
+pub async fn set_user_plan(
+    state: &AppState,
+    admin: &AdminAuth,
+    user_id: &str,
+    req: SetPlanRequest,
+    device: &ClientDevice,
+) -> Result<UserPublic, AppError> {
+    let user_id = ObjectId::parse_str(user_id).map_err(|_| AppError::NotFound)?;
+    let plan = known_plan(state, &req.plan).await?;
+
+    let user = state
+        .users
+        .set_plan(user_id, &plan.name)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let keys = state
+        .api_keys
+        .apply_plan(PlanKeys::Owner(ApiKeyOwner::User(user_id)), &plan)
+        .await?;
+    state.quota.evict_owner(ApiKeyOwner::User(user_id))?;
+
+    AuditEntry::new(AuditAction::PlanChange, AuditOutcome::Success, device)
+        .subject(user_id)
+        .actor(admin.actor())
+        .target(&plan.name)
+        .detail(format!("keys={keys}"))
+        .record(state)
+        .await;
+    Ok(user.into())
+}
+
+pub async fn set_org_plan(
+    state: &AppState,
+    admin: &AdminAuth,
+    org_id: &str,
+    req: SetPlanRequest,
+    device: &ClientDevice,
+) -> Result<OrganizationPublic, AppError> {
+    let org_id = ObjectId::parse_str(org_id).map_err(|_| AppError::NotFound)?;
+    let plan = known_plan(state, &req.plan).await?;
+
+    let org = state
+        .organizations
+        .set_plan(org_id, &plan.name)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let keys = state
+        .api_keys
+        .apply_plan(PlanKeys::Owner(ApiKeyOwner::Org(org_id)), &plan)
+        .await?;
+    state.quota.evict_owner(ApiKeyOwner::Org(org_id))?;
+
+    AuditEntry::new(AuditAction::PlanChange, AuditOutcome::Success, device)
+        .actor(admin.actor())
+        .target(&plan.name)
+        .detail(format!("org={} keys={keys}", org_id.to_hex()))
+        .record(state)
+        .await;
+    Ok(org.into())
+}
+
+/// Brings keys in line with the current plan definitions (PLANS may have changed
+/// since the last start) and puts keys created before plans on DEFAULT_PLAN.
+pub async fn sync_keys(state: &AppState) -> Result<u64, AppError> {
+    let mut changed = 0;
+    for plan in list(state).await? {
+        changed += state
+            .api_keys
+            .apply_plan(PlanKeys::Plan(plan.name.clone()), &plan)
+            .await?;
+    }
+    changed += state
+        .api_keys
+        .apply_plan(PlanKeys::Unassigned, &default_plan(state).await?)
+        .await?;
+    Ok(changed)
+}
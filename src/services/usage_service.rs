@@ -0,0 +1,114 @@
+//! API key usage reports from the hourly/daily rollups (`api_key_usage`).
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    dto::api_key::{UsageQuery, UsageReport},
+    errors::AppError,
+    models::{
+        api_key::{ApiKeyDoc, ApiKeyOwner},
+        api_key_usage::{UsageGranularity, UsagePoint},
+        bson_to_rfc3339,
+    },
+    state::AppState,
+};
+
+/// RFC 3339 timestamp or a `YYYY-MM-DD` date (midnight UTC).
+fn parse_time(value: &str, field: &str) -> Result<DateTime<Utc>, AppError> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| {
+            AppError::Validation(format!("{field} must be an RFC 3339 timestamp or a date"))
+        })
+}
+
+/// Longest range one report may cover.
+fn max_range(granularity: UsageGranularity) -> Duration {
+    match granularity {
+        UsageGranularity::Hour => Duration::days(31),
+        UsageGranularity::Day => Duration::days(366),
+    }
+}
+
+pub async fn report(
+    state: &AppState,
+    key: &ApiKeyDoc,
+    query: &UsageQuery,
+) -> Result<UsageReport, AppError> {
+    let granularity = query.granularity.unwrap_or(UsageGranularity::Day);
+    let to = match &query.to {
+        Some(v) => parse_time(v, "to")?,
+        None => Utc::now(),
+    };
+    let from = match &query.from {
+        Some(v) => parse_time(v, "from")?,
+        None => {
+            let periods = match granularity {
+                UsageGranularity::Hour => 24,
+                UsageGranularity::Day => 30,
+            };
+            let start =
+                granularity.period_start(to.timestamp()) - (periods - 1) * granularity.seconds();
+            DateTime::from_timestamp(start, 0).unwrap_or_default()
+        }
+    };
+    if from >= to {
+        return Err(AppError::Validation("from must be before to".into()));
+    }
+    if to - from > max_range(granularity) {
+        return Err(AppError::Validation(format!(
+            "range too long for {} usage (max {} days)",
+            granularity.as_str(),
+            max_range(granularity).num_days()
+        )));
+    }
+
+    let from = BsonDateTime::from_chrono(from);
+    let to = BsonDateTime::from_chrono(to);
+    let rollups = state
+        .api_key_usage
+        .find(key.id, granularity, from, to)
+        .await?;
+
+    Ok(UsageReport {
+        key_id: key.id.to_hex(),
+        granularity,
+        from: bson_to_rfc3339(from),
+        to: bson_to_rfc3339(to),
+        total: rollups.iter().map(|u| u.requests).sum(),
+        points: rollups.into_iter().map(UsagePoint::from).collect(),
+    })
+}
+
+/// Usage of key `id` of `owner`.
+pub async fn for_owner(
+    state: &AppState,
+    owner: ApiKeyOwner,
+    id: &str,
+    query: &UsageQuery,
+) -> Result<UsageReport, AppError> {
+    let id = ObjectId::parse_str(id).map_err(|_| AppError::NotFound)?;
+    let key = state
+        .api_keys
+        .find_for_owner(id, owner)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    report(state, &key, query).await
+}
+
+/// `key_id,period_start,requests` rows, for spreadsheets and billing imports.
+pub fn to_csv(report: &UsageReport) -> String {
+    let mut out = String::from("key_id,period_start,requests\n");
+    for p in &report.points {
+        out.push_str(&format!(
+            "{},{},{}\n",
+            report.key_id, p.period_start, p.requests
+        ));
+    }
+    out
+}
@@ -1,76 +1,166 @@
 use crate::{
-    config::Config,
-    models::{api_key::ApiKeyDoc, refresh_token::RefreshTokenDoc, user::UserDoc},
-};
-use bson::doc;
-use mongodb::{
-    options::{ClientOptions, IndexOptions},
-    Client, Collection, IndexModel,
+    api_key::{quota::QuotaCache, usage::UsageRecorder},
+    auth::{denylist::Denylist, keyring::Keyring},
+    config::{Config, StorageBackend},
+    errors::AppError,
+    mail::{self, Mailer},
+    store::{
+        memory::{
+            MemoryApiKeyStore, MemoryApiKeyUsageStore, MemoryAuditEventStore, MemoryAuthCodeStore,
+            MemoryLoginAttemptStore, MemoryMembershipStore, MemoryOAuthClientStore,
+            MemoryOrganizationStore, MemoryPasskeyStore, MemoryPlanStore, MemoryRefreshTokenStore,
+            MemoryRevokedTokenStore, MemorySigningKeyStore, MemoryTotpFactorStore, MemoryUserStore,
+            MemoryUserTokenStore, MemoryWebAuthnChallengeStore,
+        },
+        mongo::{
+            MongoApiKeyStore, MongoApiKeyUsageStore, MongoAuditEventStore, MongoAuthCodeStore,
+            MongoLoginAttemptStore, MongoMembershipStore, MongoOAuthClientStore,
+            MongoOrganizationStore, MongoPasskeyStore, MongoPlanStore, MongoRefreshTokenStore,
+            MongoRevokedTokenStore, MongoSigningKeyStore, MongoTotpFactorStore, MongoUserStore,
+            MongoUserTokenStore, MongoWebAuthnChallengeStore,
+        },
+        ApiKeyStore, ApiKeyUsageStore, AuditEventStore, AuthCodeStore, LoginAttemptStore,
+        MembershipStore, OAuthClientStore, OrganizationStore, PasskeyStore, PlanStore,
+        RefreshTokenStore, TotpFactorStore, UserStore, UserTokenStore, WebAuthnChallengeStore,
+    },
 };
+use mongodb::{options::ClientOptions, Client};
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub cfg: Arc<Config>,
-    pub users: Collection<UserDoc>,
-    pub refresh_tokens: Collection<RefreshTokenDoc>,
-    pub api_keys: Collection<ApiKeyDoc>,
+    pub keyring: Arc<Keyring>,
+    pub denylist: Arc<Denylist>,
+    pub users: Arc<dyn UserStore>,
+    pub refresh_tokens: Arc<dyn RefreshTokenStore>,
+    pub api_keys: Arc<dyn ApiKeyStore>,
+    /// Cached keys and quota leases for `/api` requests.
+    pub quota: Arc<QuotaCache>,
+    pub api_key_usage: Arc<dyn ApiKeyUsageStore>,
+    /// Counts `/api` requests per key and flushes them to `api_key_usage`.
+    pub usage: Arc<UsageRecorder>,
+    pub oauth_clients: Arc<dyn OAuthClientStore>,
+    pub auth_codes: Arc<dyn AuthCodeStore>,
+    pub user_tokens: Arc<dyn UserTokenStore>,
+    pub totp_factors: Arc<dyn TotpFactorStore>,
+    pub passkeys: Arc<dyn PasskeyStore>,
+    pub webauthn_challenges: Arc<dyn WebAuthnChallengeStore>,
+    pub login_attempts: Arc<dyn LoginAttemptStore>,
+    pub audit_events: Arc<dyn AuditEventStore>,
+    pub organizations: Arc<dyn OrganizationStore>,
+    pub memberships: Arc<dyn MembershipStore>,
+    pub plans: Arc<dyn PlanStore>,
+    pub mailer: Arc<dyn Mailer>,
 }
 
 impl AppState {
-    pub async fn new(cfg: Config) -> mongodb::error::Result<Self> {
+    /// Builds state for the backend selected in `cfg.storage`.
+    pub async fn new(cfg: Config) -> Result<Self, AppError> {
+        match cfg.storage {
+            StorageBackend::Mongo => Self::mongo(cfg).await,
+            StorageBackend::Memory => Self::in_memory(cfg).await,
+        }
+    }
+
+    pub async fn mongo(cfg: Config) -> Result<Self, AppError> {
         let mut opts = ClientOptions::parse(&cfg.mongodb_uri).await?;
         opts.app_name = Some("axum-mongo-auth".to_string());
 
         let client = Client::with_options(opts)?;
         let db = client.database(&cfg.db_name);
 
-        let users: Collection<UserDoc> = db.collection("users");
-        let refresh_tokens: Collection<RefreshTokenDoc> = db.collection("refresh_tokens");
+        let users: Arc<dyn UserStore> = Arc::new(MongoUserStore::new(&db).await?);
+        let refresh_tokens = MongoRefreshTokenStore::new(&db).await?;
+        let api_keys: Arc<dyn ApiKeyStore> = Arc::new(MongoApiKeyStore::new(&db).await?);
+        let api_key_usage: Arc<dyn ApiKeyUsageStore> =
+            Arc::new(MongoApiKeyUsageStore::new(&db).await?);
+        let signing_keys = MongoSigningKeyStore::new(&db).await?;
+        let oauth_clients = MongoOAuthClientStore::new(&db).await?;
+        let auth_codes = MongoAuthCodeStore::new(&db).await?;
+        let user_tokens = MongoUserTokenStore::new(&db).await?;
+        let totp_factors = MongoTotpFactorStore::new(&db).await?;
+        let passkeys = MongoPasskeyStore::new(&db).await?;
+        let webauthn_challenges = MongoWebAuthnChallengeStore::new(&db).await?;
+        let login_attempts = MongoLoginAttemptStore::new(&db).await?;
+        let revoked_tokens = MongoRevokedTokenStore::new(&db).await?;
+        let audit_events = MongoAuditEventStore::new(&db).await?;
+        let organizations: Arc<dyn OrganizationStore> =
+            Arc::new(MongoOrganizationStore::new(&db).await?);
+        let memberships = MongoMembershipStore::new(&db).await?;
+        let plans = MongoPlanStore::new(&db).await?;
 
-        // unique email
-        let email_index = IndexModel::builder()
-            .keys(mongodb::bson::doc! { "email": 1 })
-            .options(IndexOptions::builder().unique(true).build())
-            .build();
-        let _ = users.create_index(email_index).await?;
+        let keyring = Keyring::load(Arc::new(signing_keys), &cfg).await?;
+        let denylist = Denylist::load(Arc::new(revoked_tokens)).await?;
+        let mailer = mail::from_config(&cfg)?;
 
-        // unique refresh token hash
-        let token_hash_index = IndexModel::builder()
-            .keys(mongodb::bson::doc! { "token_hash": 1 })
-            .options(IndexOptions::builder().unique(true).build())
-            .build();
-        let _ = refresh_tokens.create_index(token_hash_index).await?;
-
-        // unique jti
-        let jti_index = IndexModel::builder()
-            .keys(mongodb::bson::doc! { "jti": 1 })
-            .options(IndexOptions::builder().unique(true).build())
-            .build();
-        let _ = refresh_tokens.create_index(jti_index).await?;
-        let api_keys: Collection<ApiKeyDoc> = db.collection("api_keys");
+        Ok(Self {
+            quota: Arc::new(QuotaCache::new(
+                api_keys.clone(),
+                users.clone(),
+                organizations.clone(),
+                &cfg,
+            )),
+            cfg: Arc::new(cfg),
+            keyring: Arc::new(keyring),
+            denylist: Arc::new(denylist),
+            users,
+            refresh_tokens: Arc::new(refresh_tokens),
+            api_keys,
+            usage: Arc::new(UsageRecorder::new(api_key_usage.clone())),
+            api_key_usage,
+            oauth_clients: Arc::new(oauth_clients),
+            auth_codes: Arc::new(auth_codes),
+            user_tokens: Arc::new(user_tokens),
+            totp_factors: Arc::new(totp_factors),
+            passkeys: Arc::new(passkeys),
+            webauthn_challenges: Arc::new(webauthn_challenges),
+            login_attempts: Arc::new(login_attempts),
+            audit_events: Arc::new(audit_events),
+            organizations,
+            memberships: Arc::new(memberships),
+            plans: Arc::new(plans),
+            mailer,
+        })
+    }
 
-        // unique key_hash
-        let key_hash_index = IndexModel::builder()
-            .keys(doc! { "key_hash": 1 })
-            .options(IndexOptions::builder().unique(true).build())
-            .build();
-        api_keys.create_index(key_hash_index).await?;
+    pub async fn in_memory(cfg: Config) -> Result<Self, AppError> {
+        let keyring = Keyring::load(Arc::new(MemorySigningKeyStore::default()), &cfg).await?;
+        let denylist = Denylist::load(Arc::new(MemoryRevokedTokenStore::default())).await?;
+        let api_key_usage: Arc<dyn ApiKeyUsageStore> = Arc::new(MemoryApiKeyUsageStore::default());
+        let users: Arc<dyn UserStore> = Arc::new(MemoryUserStore::default());
+        let api_keys: Arc<dyn ApiKeyStore> = Arc::new(MemoryApiKeyStore::default());
+        let organizations: Arc<dyn OrganizationStore> =
+            Arc::new(MemoryOrganizationStore::default());
+        let mailer = mail::from_config(&cfg)?;
 
-        // compound index для быстрого поиска active + user_id + scopes
-        let active_user_index = IndexModel::builder()
-            .keys(doc! {
-                "user_id": 1,
-                "active": 1,
-                "expires_at": 1
-            })
-            .build();
-        api_keys.create_index(active_user_index).await?;
         Ok(Self {
+            quota: Arc::new(QuotaCache::new(
+                api_keys.clone(),
+                users.clone(),
+                organizations.clone(),
+                &cfg,
+            )),
             cfg: Arc::new(cfg),
+            keyring: Arc::new(keyring),
+            denylist: Arc::new(denylist),
             users,
-            refresh_tokens,
+            refresh_tokens: Arc::new(MemoryRefreshTokenStore::default()),
             api_keys,
+            usage: Arc::new(UsageRecorder::new(api_key_usage.clone())),
+            api_key_usage,
+            oauth_clients: Arc::new(MemoryOAuthClientStore::default()),
+            auth_codes: Arc::new(MemoryAuthCodeStore::default()),
+            user_tokens: Arc::new(MemoryUserTokenStore::default()),
+            totp_factors: Arc::new(MemoryTotpFactorStore::default()),
+            passkeys: Arc::new(MemoryPasskeyStore::default()),
+            webauthn_challenges: Arc::new(MemoryWebAuthnChallengeStore::default()),
+            login_attempts: Arc::new(MemoryLoginAttemptStore::default()),
+            audit_events: Arc::new(MemoryAuditEventStore::default()),
+            organizations,
+            memberships: Arc::new(MemoryMembershipStore::default()),
+            plans: Arc::new(MemoryPlanStore::default()),
+            mailer,
         })
     }
 }
@@ -0,0 +1,1284 @@
+//! In-memory backend: plain `HashMap`s behind `RwLock`s.
+//!
+//! Mirrors the MongoDB semantics (unique indexes, filters, quota update) closely
+//! enough for integration tests and local dev; nothing survives a restart.
+
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use std::{collections::HashMap, sync::RwLock};
+
+use crate::{
+    errors::AppError,
+    models::{
+        api_key::{previous_minute_weight, ApiKeyDoc, ApiKeyOwner, QuotaAlgorithm},
+        api_key_usage::{ApiKeyUsageDoc, UsageGranularity, APPLIED_INCREMENTS_KEPT},
+        audit_event::AuditEventDoc,
+        auth_code::AuthCodeDoc,
+        login_attempt::LoginAttemptDoc,
+        membership::{MembershipDoc, OrgRole},
+        oauth_client::OAuthClientDoc,
+        organization::OrganizationDoc,
+        passkey::PasskeyDoc,
+        plan::PlanDoc,
+        refresh_token::RefreshTokenDoc,
+        revoked_token::RevokedTokenDoc,
+        signing_key::SigningKeyDoc,
+        totp_factor::TotpFactorDoc,
+        user::UserDoc,
+        user_token::{UserTokenDoc, UserTokenPurpose},
+        webauthn_challenge::{WebAuthnCeremony, WebAuthnChallengeDoc},
+    },
+    store::{
+        ApiKeyFilter, ApiKeyPatch, ApiKeyStore, ApiKeyUsageStore, AuditEventStore, AuditFilter,
+        AuthCodeStore, LoginAttemptStore, MembershipStore, OAuthClientStore, OrganizationStore,
+        PasskeyStore, PlanKeys, PlanStore, RefreshTokenStore, RevokedTokenStore, SigningKeyStore,
+        TotpFactorStore, UsageIncrement, UserFilter, UserStore, UserTokenStore,
+        WebAuthnChallengeStore,
+    },
+};
+
+fn poisoned() -> AppError {
+    AppError::Internal("in-memory store lock poisoned".into())
+}
+
+fn not_expired(expires_at: Option<BsonDateTime>, now: BsonDateTime) -> bool {
+    expires_at.is_none_or(|exp| exp > now)
+}
+
+#[derive(Default)]
+pub struct MemoryUserStore {
+    users: RwLock<HashMap<ObjectId, UserDoc>>,
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<UserDoc>, AppError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        Ok(users.get(&id).cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<UserDoc>, AppError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        Ok(users.values().find(|u| u.email == email).cloned())
+    }
+
+    async fn insert(&self, user: &UserDoc) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if users.values().any(|u| u.email == user.email) {
+            return Err(AppError::Conflict("user already exists".into()));
+        }
+        users.insert(user.id, user.clone());
+        Ok(())
+    }
+
+    async fn set_default_api_key(
+        &self,
+        user_id: ObjectId,
+        key_id: ObjectId,
+    ) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if let Some(u) = users.get_mut(&user_id) {
+            u.default_api_key_id = Some(key_id);
+        }
+        Ok(())
+    }
+
+    async fn set_email_verified(&self, user_id: ObjectId) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if let Some(u) = users.get_mut(&user_id) {
+            u.email_verified = true;
+        }
+        Ok(())
+    }
+
+    async fn set_password_hash(
+        &self,
+        user_id: ObjectId,
+        password_hash: &str,
+    ) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        if let Some(u) = users.get_mut(&user_id) {
+            u.password_hash = password_hash.to_string();
+        }
+        Ok(())
+    }
+
+    async fn bump_token_version(&self, user_id: ObjectId) -> Result<Option<i64>, AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        Ok(users.get_mut(&user_id).map(|u| {
+            u.token_version += 1;
+            u.token_version
+        }))
+    }
+
+    async fn set_roles(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String],
+    ) -> Result<Option<UserDoc>, AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        Ok(users.get_mut(&user_id).map(|u| {
+            u.roles = roles.to_vec();
+            u.permissions = permissions.to_vec();
+            u.token_version += 1;
+            u.clone()
+        }))
+    }
+
+    async fn set_disabled(
+        &self,
+        user_id: ObjectId,
+        disabled: bool,
+    ) -> Result<Option<UserDoc>, AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        Ok(users.get_mut(&user_id).map(|u| {
+            u.disabled = disabled;
+            u.clone()
+        }))
+    }
+
+    async fn delete(&self, user_id: ObjectId) -> Result<bool, AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        Ok(users.remove(&user_id).is_some())
+    }
+
+    async fn search(&self, filter: &UserFilter, limit: i64) -> Result<Vec<UserDoc>, AppError> {
+        let users = self.users.read().map_err(|_| poisoned())?;
+        let query = filter.query.as_deref().map(str::to_lowercase);
+        let mut out: Vec<UserDoc> = users
+            .values()
+            .filter(|u| {
+                query.as_deref().is_none_or(|q| {
+                    u.email.to_lowercase().contains(q) || u.name.to_lowercase().contains(q)
+                })
+            })
+            .filter(|u| filter.disabled.is_none_or(|d| u.disabled == d))
+            .filter(|u| filter.role.as_ref().is_none_or(|r| u.roles.contains(r)))
+            .filter(|u| filter.before.is_none_or(|id| u.id < id))
+            .cloned()
+            .collect();
+        out.sort_by_key(|d| std::cmp::Reverse(d.id));
+        out.truncate(limit.max(0) as usize);
+        Ok(out)
+    }
+
+    async fn set_plan(&self, user_id: ObjectId, plan: &str) -> Result<Option<UserDoc>, AppError> {
+        let mut users = self.users.write().map_err(|_| poisoned())?;
+        Ok(users.get_mut(&user_id).map(|u| {
+            u.plan = Some(plan.to_string());
+            u.clone()
+        }))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryRefreshTokenStore {
+    tokens: RwLock<HashMap<ObjectId, RefreshTokenDoc>>,
+}
+
+#[async_trait]
+impl RefreshTokenStore for MemoryRefreshTokenStore {
+    async fn insert(&self, token: &RefreshTokenDoc) -> Result<(), AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        if tokens
+            .values()
+            .any(|t| t.token_hash == token.token_hash || t.jti == token.jti)
+        {
+            return Err(AppError::Conflict("refresh token already exists".into()));
+        }
+        tokens.insert(token.id, token.clone());
+        Ok(())
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> Result<Option<RefreshTokenDoc>, AppError> {
+        let tokens = self.tokens.read().map_err(|_| poisoned())?;
+        Ok(tokens
+            .values()
+            .find(|t| t.token_hash == token_hash)
+            .cloned())
+    }
+
+    async fn mark_replaced(&self, id: ObjectId, replaced_by: ObjectId) -> Result<bool, AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        match tokens.get_mut(&id) {
+            Some(t) if t.revoked_at.is_none() => {
+                t.revoked_at = Some(BsonDateTime::now());
+                t.replaced_by = Some(replaced_by);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn revoke_by_hash(&self, token_hash: &str) -> Result<(), AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        if let Some(t) = tokens
+            .values_mut()
+            .find(|t| t.token_hash == token_hash && t.revoked_at.is_none())
+        {
+            t.revoked_at = Some(BsonDateTime::now());
+        }
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: ObjectId) -> Result<u64, AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        let now = BsonDateTime::now();
+        let mut revoked = 0;
+        for t in tokens
+            .values_mut()
+            .filter(|t| t.user_id == user_id && t.revoked_at.is_none())
+        {
+            t.revoked_at = Some(now);
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+
+    async fn revoke_family(&self, family_id: ObjectId, user_id: ObjectId) -> Result<u64, AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        let now = BsonDateTime::now();
+        let mut revoked = 0;
+        for t in tokens.values_mut().filter(|t| {
+            t.family_id == Some(family_id) && t.user_id == user_id && t.revoked_at.is_none()
+        }) {
+            t.revoked_at = Some(now);
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+
+    async fn list_active_for_user(
+        &self,
+        user_id: ObjectId,
+        now: BsonDateTime,
+    ) -> Result<Vec<RefreshTokenDoc>, AppError> {
+        let tokens = self.tokens.read().map_err(|_| poisoned())?;
+        let mut out: Vec<RefreshTokenDoc> = tokens
+            .values()
+            .filter(|t| t.user_id == user_id && t.revoked_at.is_none() && t.expires_at > now)
+            .cloned()
+            .collect();
+        out.sort_by_key(|t| std::cmp::Reverse(t.created_at));
+        Ok(out)
+    }
+
+    async fn revoke_for_user(
+        &self,
+        id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<RefreshTokenDoc>, AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        match tokens.get_mut(&id) {
+            Some(t) if t.user_id == user_id && t.revoked_at.is_none() => {
+                t.revoked_at = Some(BsonDateTime::now());
+                Ok(Some(t.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn apply_patch(k: &mut ApiKeyDoc, patch: &ApiKeyPatch) {
+    if let Some(name) = &patch.name {
+        k.name = name.clone();
+    }
+    if let Some(scopes) = &patch.scopes {
+        k.scopes = scopes.clone();
+    }
+    if let Some(expires_at) = patch.expires_at {
+        k.expires_at = expires_at;
+    }
+    if let Some(rpm) = patch.requests_per_minute {
+        k.requests_per_minute = rpm;
+    }
+    if let Some(rpd) = patch.requests_per_day {
+        k.requests_per_day = rpd;
+    }
+    if let Some(custom) = patch.custom_limits {
+        k.custom_limits = custom;
+    }
+    if let Some(algorithm) = patch.quota_algorithm {
+        k.quota_algorithm = algorithm;
+    }
+    if let Some(active) = patch.active {
+        k.active = active;
+    }
+    if patch.reset_usage {
+        k.requests_used_minute = 0;
+        k.requests_prev_minute = 0;
+        k.requests_used_today = 0;
+        k.bucket_tokens = None;
+    }
+    k.generation += 1;
+}
+
+#[derive(Default)]
+pub struct MemoryApiKeyStore {
+    keys: RwLock<HashMap<ObjectId, ApiKeyDoc>>,
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn insert(&self, key: &ApiKeyDoc) -> Result<(), AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        if keys.values().any(|k| k.key_hash == key.key_hash) {
+            return Err(AppError::Conflict("api key already exists".into()));
+        }
+        keys.insert(key.id, key.clone());
+        Ok(())
+    }
+
+    async fn find_active_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        let now = BsonDateTime::now();
+        Ok(keys
+            .values()
+            .find(|k| k.key_hash == key_hash && k.active && not_expired(k.expires_at, now))
+            .cloned())
+    }
+
+    async fn find_active_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        Ok(keys
+            .get(&id)
+            .filter(|k| k.owner == owner && k.active)
+            .cloned())
+    }
+
+    async fn list_for_owner(&self, owner: ApiKeyOwner) -> Result<Vec<ApiKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        let mut out: Vec<ApiKeyDoc> = keys
+            .values()
+            .filter(|k| k.owner == owner)
+            .cloned()
+            .collect();
+        out.sort_by_key(|k| k.created_at);
+        Ok(out)
+    }
+
+    async fn find_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        Ok(keys.get(&id).filter(|k| k.owner == owner).cloned())
+    }
+
+    async fn update_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let Some(k) = keys.get_mut(&id).filter(|k| k.owner == owner) else {
+            return Ok(None);
+        };
+        apply_patch(k, patch);
+        Ok(Some(k.clone()))
+    }
+
+    async fn update(
+        &self,
+        id: ObjectId,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let Some(k) = keys.get_mut(&id) else {
+            return Ok(None);
+        };
+        apply_patch(k, patch);
+        Ok(Some(k.clone()))
+    }
+
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<ApiKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        Ok(keys.get(&id).cloned())
+    }
+
+    async fn search(&self, filter: &ApiKeyFilter, limit: i64) -> Result<Vec<ApiKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        let mut out: Vec<ApiKeyDoc> = keys
+            .values()
+            .filter(|k| {
+                filter
+                    .user_id
+                    .is_none_or(|id| k.owner == ApiKeyOwner::User(id))
+            })
+            .filter(|k| {
+                filter
+                    .org_id
+                    .is_none_or(|id| k.owner == ApiKeyOwner::Org(id))
+            })
+            .filter(|k| filter.created_by.is_none_or(|id| k.created_by == Some(id)))
+            .filter(|k| filter.active.is_none_or(|a| k.active == a))
+            .filter(|k| filter.before.is_none_or(|id| k.id < id))
+            .cloned()
+            .collect();
+        out.sort_by_key(|d| std::cmp::Reverse(d.id));
+        out.truncate(limit.max(0) as usize);
+        Ok(out)
+    }
+
+    async fn delete_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        if keys.get(&id).is_some_and(|k| k.owner == owner) {
+            return Ok(keys.remove(&id));
+        }
+        Ok(None)
+    }
+
+    async fn delete_all_for_owner(&self, owner: ApiKeyOwner) -> Result<u64, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let before = keys.len();
+        keys.retain(|_, k| k.owner != owner);
+        Ok((before - keys.len()) as u64)
+    }
+
+    async fn replace_secret(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+        key_hash: &str,
+        key_ciphertext: Vec<u8>,
+        key_nonce: [u8; 12],
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        if keys.values().any(|k| k.id != id && k.key_hash == key_hash) {
+            return Err(AppError::Conflict("api key already exists".into()));
+        }
+
+        let Some(k) = keys.get_mut(&id).filter(|k| k.owner == owner) else {
+            return Ok(None);
+        };
+        let before = k.clone();
+        k.key_hash = key_hash.to_string();
+        k.key_ciphertext = key_ciphertext;
+        k.key_nonce = key_nonce;
+        k.last_used_at = BsonDateTime::now();
+        k.active = true;
+        k.generation += 1;
+        Ok(Some(before))
+    }
+
+    async fn bump_generation(&self, owner: ApiKeyOwner) -> Result<u64, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let mut bumped = 0;
+        for k in keys.values_mut().filter(|k| k.owner == owner) {
+            k.generation += 1;
+            bumped += 1;
+        }
+        Ok(bumped)
+    }
+
+    async fn apply_plan(&self, target: PlanKeys, plan: &PlanDoc) -> Result<u64, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let mut changed = 0;
+        for k in keys.values_mut() {
+            let matches = match &target {
+                PlanKeys::Owner(owner) => k.owner == *owner,
+                PlanKeys::Plan(name) => k.plan.as_deref() == Some(name.as_str()),
+                PlanKeys::Unassigned => k.plan.is_none(),
+            };
+            if !matches {
+                continue;
+            }
+
+            let (rpm, rpd) = if k.custom_limits {
+                (
+                    k.requests_per_minute.min(plan.requests_per_minute),
+                    k.requests_per_day.min(plan.requests_per_day),
+                )
+            } else {
+                (plan.requests_per_minute, plan.requests_per_day)
+            };
+            if k.plan.as_deref() != Some(plan.name.as_str())
+                || k.requests_per_minute != rpm
+                || k.requests_per_day != rpd
+            {
+                k.plan = Some(plan.name.clone());
+                k.requests_per_minute = rpm;
+                k.requests_per_day = rpd;
+                k.generation += 1;
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    async fn consume_quota(
+        &self,
+        key_hash: &str,
+        now: BsonDateTime,
+        minute: i64,
+        day: i32,
+        count: i32,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let Some(k) = keys
+            .values_mut()
+            .find(|k| k.key_hash == key_hash && k.active && not_expired(k.expires_at, now))
+        else {
+            return Ok(None);
+        };
+
+        let (prev, used) = match k.minute_bucket {
+            b if b == minute => (k.requests_prev_minute, k.requests_used_minute),
+            b if b == minute - 1 => (k.requests_used_minute, 0),
+            _ => (0, 0),
+        };
+        let capacity = f64::from(k.requests_per_minute);
+        let refill = k.bucket_updated_at.map_or(0.0, |at| {
+            (now.timestamp_millis() - at.timestamp_millis()) as f64 * capacity / 60_000.0
+        });
+        let tokens = (k.bucket_tokens.unwrap_or(capacity) + refill).min(capacity);
+
+        let minute_ok = match k.quota_algorithm {
+            QuotaAlgorithm::FixedWindow => used + count <= k.requests_per_minute,
+            QuotaAlgorithm::SlidingWindow => {
+                f64::from(prev) * previous_minute_weight(now, minute) + f64::from(used + count)
+                    <= capacity
+            }
+            QuotaAlgorithm::TokenBucket => tokens >= f64::from(count),
+        };
+        let used_today = if k.usage_day == day {
+            k.requests_used_today
+        } else {
+            0
+        };
+        if !minute_ok || used_today + i64::from(count) > k.requests_per_day {
+            return Ok(None);
+        }
+
+        k.minute_bucket = minute;
+        k.requests_prev_minute = prev;
+        k.requests_used_minute = used + count;
+        k.usage_day = day;
+        k.requests_used_today = used_today + i64::from(count);
+        k.bucket_tokens = Some(tokens - f64::from(count));
+        k.bucket_updated_at = Some(now);
+        k.last_used_at = now;
+
+        Ok(Some(k.clone()))
+    }
+
+    async fn release_quota(
+        &self,
+        id: ObjectId,
+        minute: i64,
+        day: i32,
+        count: i32,
+        last_used_at: BsonDateTime,
+    ) -> Result<(), AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let Some(k) = keys.get_mut(&id) else {
+            return Ok(());
+        };
+        if k.minute_bucket == minute {
+            k.requests_used_minute = (k.requests_used_minute - count).max(0);
+        }
+        if k.usage_day == day {
+            k.requests_used_today = (k.requests_used_today - i64::from(count)).max(0);
+        }
+        if let Some(tokens) = k.bucket_tokens {
+            k.bucket_tokens =
+                Some((tokens + f64::from(count)).min(f64::from(k.requests_per_minute)));
+        }
+        k.last_used_at = k.last_used_at.max(last_used_at);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryApiKeyUsageStore {
+    rollups: RwLock<HashMap<(ObjectId, UsageGranularity, BsonDateTime), ApiKeyUsageDoc>>,
+}
+
+#[async_trait]
+impl ApiKeyUsageStore for MemoryApiKeyUsageStore {
+    async fn add(&self, increment: &UsageIncrement) -> Result<(), AppError> {
+        let mut rollups = self.rollups.write().map_err(|_| poisoned())?;
+        let rollup = rollups
+            .entry((
+                increment.key_id,
+                increment.granularity,
+                increment.period_start,
+            ))
+            .or_insert_with(|| ApiKeyUsageDoc {
+                id: ObjectId::new(),
+                key_id: increment.key_id,
+                owner: increment.owner,
+                granularity: increment.granularity,
+                period_start: increment.period_start,
+                requests: 0,
+                applied: Vec::new(),
+            });
+        if rollup.applied.contains(&increment.id) {
+            return Ok(());
+        }
+        rollup.requests += increment.requests;
+        rollup.applied.push(increment.id);
+        let excess = rollup.applied.len().saturating_sub(APPLIED_INCREMENTS_KEPT);
+        rollup.applied.drain(..excess);
+        Ok(())
+    }
+
+    async fn find(
+        &self,
+        key_id: ObjectId,
+        granularity: UsageGranularity,
+        from: BsonDateTime,
+        to: BsonDateTime,
+    ) -> Result<Vec<ApiKeyUsageDoc>, AppError> {
+        let rollups = self.rollups.read().map_err(|_| poisoned())?;
+        let mut out: Vec<ApiKeyUsageDoc> = rollups
+            .values()
+            .filter(|u| u.key_id == key_id && u.granularity == granularity)
+            .filter(|u| u.period_start >= from && u.period_start < to)
+            .cloned()
+            .collect();
+        out.sort_by_key(|u| u.period_start);
+        Ok(out)
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySigningKeyStore {
+    keys: RwLock<Vec<SigningKeyDoc>>,
+}
+
+#[async_trait]
+impl SigningKeyStore for MemorySigningKeyStore {
+    async fn list(&self) -> Result<Vec<SigningKeyDoc>, AppError> {
+        let keys = self.keys.read().map_err(|_| poisoned())?;
+        Ok(keys.clone())
+    }
+
+    async fn insert(&self, key: &SigningKeyDoc) -> Result<(), AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        if keys.iter().any(|k| k.kid == key.kid) {
+            return Err(AppError::Conflict("signing key already exists".into()));
+        }
+        keys.push(key.clone());
+        Ok(())
+    }
+
+    async fn activate(&self, id: ObjectId, now: BsonDateTime) -> Result<(), AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        for k in keys.iter_mut() {
+            if k.id == id {
+                k.active = true;
+                k.retired_at = None;
+            } else if k.active {
+                k.active = false;
+                k.retired_at = Some(now);
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_retired_before(&self, cutoff: BsonDateTime) -> Result<u64, AppError> {
+        let mut keys = self.keys.write().map_err(|_| poisoned())?;
+        let before = keys.len();
+        keys.retain(|k| k.active || k.retired_at.is_none_or(|r| r >= cutoff));
+        Ok((before - keys.len()) as u64)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryOAuthClientStore {
+    clients: RwLock<HashMap<String, OAuthClientDoc>>,
+}
+
+#[async_trait]
+impl OAuthClientStore for MemoryOAuthClientStore {
+    async fn insert(&self, client: &OAuthClientDoc) -> Result<(), AppError> {
+        let mut clients = self.clients.write().map_err(|_| poisoned())?;
+        if clients.contains_key(&client.client_id) {
+            return Err(AppError::Conflict("oauth client already exists".into()));
+        }
+        clients.insert(client.client_id.clone(), client.clone());
+        Ok(())
+    }
+
+    async fn find_active(&self, client_id: &str) -> Result<Option<OAuthClientDoc>, AppError> {
+        let clients = self.clients.read().map_err(|_| poisoned())?;
+        Ok(clients.get(client_id).filter(|c| c.active).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<OAuthClientDoc>, AppError> {
+        let clients = self.clients.read().map_err(|_| poisoned())?;
+        let mut out: Vec<OAuthClientDoc> = clients.values().cloned().collect();
+        out.sort_by_key(|c| c.created_at);
+        Ok(out)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryAuthCodeStore {
+    codes: RwLock<HashMap<String, AuthCodeDoc>>,
+}
+
+#[async_trait]
+impl AuthCodeStore for MemoryAuthCodeStore {
+    async fn insert(&self, code: &AuthCodeDoc) -> Result<(), AppError> {
+        let mut codes = self.codes.write().map_err(|_| poisoned())?;
+        if codes.contains_key(&code.code_hash) {
+            return Err(AppError::Conflict(
+                "authorization code already exists".into(),
+            ));
+        }
+        // no TTL index here: drop expired codes on the way
+        let now = BsonDateTime::now();
+        codes.retain(|_, c| c.expires_at > now);
+        codes.insert(code.code_hash.clone(), code.clone());
+        Ok(())
+    }
+
+    async fn take_by_hash(
+        &self,
+        code_hash: &str,
+        now: BsonDateTime,
+    ) -> Result<Option<AuthCodeDoc>, AppError> {
+        let mut codes = self.codes.write().map_err(|_| poisoned())?;
+        Ok(codes.remove(code_hash).filter(|c| c.expires_at > now))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryUserTokenStore {
+    tokens: RwLock<HashMap<String, UserTokenDoc>>,
+}
+
+#[async_trait]
+impl UserTokenStore for MemoryUserTokenStore {
+    async fn insert(&self, token: &UserTokenDoc) -> Result<(), AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        if tokens.contains_key(&token.token_hash) {
+            return Err(AppError::Conflict("token already exists".into()));
+        }
+        // no TTL index here: drop expired tokens on the way
+        let now = BsonDateTime::now();
+        tokens.retain(|_, t| t.expires_at > now);
+        tokens.insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn take_by_hash(
+        &self,
+        token_hash: &str,
+        purpose: UserTokenPurpose,
+        now: BsonDateTime,
+    ) -> Result<Option<UserTokenDoc>, AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        if !tokens
+            .get(token_hash)
+            .is_some_and(|t| t.purpose == purpose && t.expires_at > now)
+        {
+            return Ok(None);
+        }
+        Ok(tokens.remove(token_hash))
+    }
+
+    async fn delete_for_user(
+        &self,
+        user_id: ObjectId,
+        purpose: UserTokenPurpose,
+    ) -> Result<u64, AppError> {
+        let mut tokens = self.tokens.write().map_err(|_| poisoned())?;
+        let before = tokens.len();
+        tokens.retain(|_, t| !(t.user_id == user_id && t.purpose == purpose));
+        Ok((before - tokens.len()) as u64)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryTotpFactorStore {
+    factors: RwLock<HashMap<ObjectId, TotpFactorDoc>>, // by user_id
+}
+
+#[async_trait]
+impl TotpFactorStore for MemoryTotpFactorStore {
+    async fn find_for_user(&self, user_id: ObjectId) -> Result<Option<TotpFactorDoc>, AppError> {
+        let factors = self.factors.read().map_err(|_| poisoned())?;
+        Ok(factors.get(&user_id).cloned())
+    }
+
+    async fn insert(&self, factor: &TotpFactorDoc) -> Result<(), AppError> {
+        let mut factors = self.factors.write().map_err(|_| poisoned())?;
+        if factors.contains_key(&factor.user_id) {
+            return Err(AppError::Conflict("totp factor already exists".into()));
+        }
+        factors.insert(factor.user_id, factor.clone());
+        Ok(())
+    }
+
+    async fn enable(
+        &self,
+        user_id: ObjectId,
+        recovery_code_hashes: &[String],
+        now: BsonDateTime,
+    ) -> Result<bool, AppError> {
+        let mut factors = self.factors.write().map_err(|_| poisoned())?;
+        match factors.get_mut(&user_id) {
+            Some(f) if !f.enabled => {
+                f.enabled = true;
+                f.enabled_at = Some(now);
+                f.recovery_code_hashes = recovery_code_hashes.to_vec();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<bool, AppError> {
+        let mut factors = self.factors.write().map_err(|_| poisoned())?;
+        match factors.get_mut(&user_id) {
+            Some(f) if f.last_used_step < step => {
+                f.last_used_step = step;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn take_recovery_code(
+        &self,
+        user_id: ObjectId,
+        code_hash: &str,
+    ) -> Result<bool, AppError> {
+        let mut factors = self.factors.write().map_err(|_| poisoned())?;
+        let Some(f) = factors.get_mut(&user_id) else {
+            return Ok(false);
+        };
+        let before = f.recovery_code_hashes.len();
+        f.recovery_code_hashes.retain(|h| h != code_hash);
+        Ok(f.recovery_code_hashes.len() != before)
+    }
+
+    async fn set_recovery_codes(
+        &self,
+        user_id: ObjectId,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError> {
+        let mut factors = self.factors.write().map_err(|_| poisoned())?;
+        if let Some(f) = factors.get_mut(&user_id) {
+            f.recovery_code_hashes = recovery_code_hashes.to_vec();
+        }
+        Ok(())
+    }
+
+    async fn delete_for_user(&self, user_id: ObjectId) -> Result<bool, AppError> {
+        let mut factors = self.factors.write().map_err(|_| poisoned())?;
+        Ok(factors.remove(&user_id).is_some())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryPasskeyStore {
+    passkeys: RwLock<HashMap<ObjectId, PasskeyDoc>>,
+}
+
+#[async_trait]
+impl PasskeyStore for MemoryPasskeyStore {
+    async fn insert(&self, passkey: &PasskeyDoc) -> Result<(), AppError> {
+        let mut passkeys = self.passkeys.write().map_err(|_| poisoned())?;
+        if passkeys
+            .values()
+            .any(|p| p.credential_id == passkey.credential_id)
+        {
+            return Err(AppError::Conflict("passkey already registered".into()));
+        }
+        passkeys.insert(passkey.id, passkey.clone());
+        Ok(())
+    }
+
+    async fn find_by_credential_id(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<PasskeyDoc>, AppError> {
+        let passkeys = self.passkeys.read().map_err(|_| poisoned())?;
+        Ok(passkeys
+            .values()
+            .find(|p| p.credential_id == credential_id)
+            .cloned())
+    }
+
+    async fn list_for_user(&self, user_id: ObjectId) -> Result<Vec<PasskeyDoc>, AppError> {
+        let passkeys = self.passkeys.read().map_err(|_| poisoned())?;
+        let mut out: Vec<PasskeyDoc> = passkeys
+            .values()
+            .filter(|p| p.user_id == user_id)
+            .cloned()
+            .collect();
+        out.sort_by_key(|p| p.created_at);
+        Ok(out)
+    }
+
+    async fn delete_for_user(&self, id: ObjectId, user_id: ObjectId) -> Result<bool, AppError> {
+        let mut passkeys = self.passkeys.write().map_err(|_| poisoned())?;
+        if passkeys.get(&id).is_some_and(|p| p.user_id == user_id) {
+            passkeys.remove(&id);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn record_use(
+        &self,
+        id: ObjectId,
+        sign_count: i64,
+        now: BsonDateTime,
+    ) -> Result<bool, AppError> {
+        let mut passkeys = self.passkeys.write().map_err(|_| poisoned())?;
+        match passkeys.get_mut(&id) {
+            Some(p) if p.sign_count < sign_count || (p.sign_count == 0 && sign_count == 0) => {
+                p.sign_count = sign_count;
+                p.last_used_at = Some(now);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryWebAuthnChallengeStore {
+    challenges: RwLock<HashMap<String, WebAuthnChallengeDoc>>,
+}
+
+#[async_trait]
+impl WebAuthnChallengeStore for MemoryWebAuthnChallengeStore {
+    async fn insert(&self, challenge: &WebAuthnChallengeDoc) -> Result<(), AppError> {
+        let mut challenges = self.challenges.write().map_err(|_| poisoned())?;
+        // no TTL index here: drop expired challenges on the way
+        let now = BsonDateTime::now();
+        challenges.retain(|_, c| c.expires_at > now);
+        challenges.insert(challenge.challenge_hash.clone(), challenge.clone());
+        Ok(())
+    }
+
+    async fn take_by_hash(
+        &self,
+        challenge_hash: &str,
+        ceremony: WebAuthnCeremony,
+        now: BsonDateTime,
+    ) -> Result<Option<WebAuthnChallengeDoc>, AppError> {
+        let mut challenges = self.challenges.write().map_err(|_| poisoned())?;
+        if !challenges
+            .get(challenge_hash)
+            .is_some_and(|c| c.ceremony == ceremony && c.expires_at > now)
+        {
+            return Ok(None);
+        }
+        Ok(challenges.remove(challenge_hash))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryLoginAttemptStore {
+    attempts: RwLock<HashMap<String, LoginAttemptDoc>>,
+}
+
+#[async_trait]
+impl LoginAttemptStore for MemoryLoginAttemptStore {
+    async fn find(
+        &self,
+        key: &str,
+        now: BsonDateTime,
+    ) -> Result<Option<LoginAttemptDoc>, AppError> {
+        let attempts = self.attempts.read().map_err(|_| poisoned())?;
+        Ok(attempts.get(key).filter(|a| a.expires_at > now).cloned())
+    }
+
+    async fn record_failure(
+        &self,
+        key: &str,
+        now: BsonDateTime,
+        expires_at: BsonDateTime,
+    ) -> Result<LoginAttemptDoc, AppError> {
+        let mut attempts = self.attempts.write().map_err(|_| poisoned())?;
+        // no TTL index here: drop expired records on the way
+        attempts.retain(|_, a| a.expires_at > now);
+
+        let entry = attempts
+            .entry(key.to_string())
+            .or_insert_with(|| LoginAttemptDoc {
+                id: ObjectId::new(),
+                key: key.to_string(),
+                failures: 0,
+                last_failure_at: now,
+                locked_until: None,
+                expires_at,
+            });
+        entry.failures += 1;
+        entry.last_failure_at = now;
+        entry.expires_at = entry.expires_at.max(expires_at);
+        Ok(entry.clone())
+    }
+
+    async fn lock(
+        &self,
+        key: &str,
+        locked_until: BsonDateTime,
+        expires_at: BsonDateTime,
+    ) -> Result<(), AppError> {
+        let mut attempts = self.attempts.write().map_err(|_| poisoned())?;
+        if let Some(a) = attempts.get_mut(key) {
+            a.locked_until = Some(locked_until);
+            a.expires_at = a.expires_at.max(expires_at);
+        }
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<bool, AppError> {
+        let mut attempts = self.attempts.write().map_err(|_| poisoned())?;
+        Ok(attempts.remove(key).is_some())
+    }
+
+    async fn list_locked(&self, now: BsonDateTime) -> Result<Vec<LoginAttemptDoc>, AppError> {
+        let attempts = self.attempts.read().map_err(|_| poisoned())?;
+        let mut out: Vec<LoginAttemptDoc> = attempts
+            .values()
+            .filter(|a| a.locked_until.is_some_and(|t| t > now))
+            .cloned()
+            .collect();
+        out.sort_by_key(|a| std::cmp::Reverse(a.locked_until));
+        Ok(out)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryRevokedTokenStore {
+    entries: RwLock<HashMap<String, RevokedTokenDoc>>,
+}
+
+#[async_trait]
+impl RevokedTokenStore for MemoryRevokedTokenStore {
+    async fn revoke(
+        &self,
+        key: &str,
+        token_version: Option<i64>,
+        expires_at: BsonDateTime,
+    ) -> Result<(), AppError> {
+        let mut entries = self.entries.write().map_err(|_| poisoned())?;
+        let now = BsonDateTime::now();
+        // no TTL index here: drop expired entries on the way
+        entries.retain(|_, e| e.expires_at > now);
+
+        let entry = entries
+            .entry(key.to_string())
+            .or_insert_with(|| RevokedTokenDoc {
+                id: ObjectId::new(),
+                key: key.to_string(),
+                token_version,
+                created_at: now,
+                expires_at,
+            });
+        entry.token_version = entry.token_version.max(token_version);
+        entry.expires_at = entry.expires_at.max(expires_at);
+        Ok(())
+    }
+
+    async fn list_active(&self, now: BsonDateTime) -> Result<Vec<RevokedTokenDoc>, AppError> {
+        let entries = self.entries.read().map_err(|_| poisoned())?;
+        Ok(entries
+            .values()
+            .filter(|e| e.expires_at > now)
+            .cloned()
+            .collect())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryAuditEventStore {
+    events: RwLock<Vec<AuditEventDoc>>,
+}
+
+#[async_trait]
+impl AuditEventStore for MemoryAuditEventStore {
+    async fn insert(&self, event: &AuditEventDoc) -> Result<(), AppError> {
+        let mut events = self.events.write().map_err(|_| poisoned())?;
+        events.push(event.clone());
+        Ok(())
+    }
+
+    async fn find(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditEventDoc>, AppError> {
+        let events = self.events.read().map_err(|_| poisoned())?;
+        Ok(events
+            .iter()
+            .rev()
+            .filter(|e| filter.user_id.is_none_or(|id| e.user_id == Some(id)))
+            .filter(|e| filter.actor.is_none() || e.actor == filter.actor)
+            .filter(|e| filter.action.is_none_or(|a| e.action == a))
+            .filter(|e| filter.outcome.is_none_or(|o| e.outcome == o))
+            .filter(|e| filter.from.is_none_or(|t| e.created_at >= t))
+            .filter(|e| filter.to.is_none_or(|t| e.created_at < t))
+            .filter(|e| filter.before.is_none_or(|id| e.id < id))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryOrganizationStore {
+    orgs: RwLock<HashMap<ObjectId, OrganizationDoc>>,
+}
+
+#[async_trait]
+impl OrganizationStore for MemoryOrganizationStore {
+    async fn insert(&self, org: &OrganizationDoc) -> Result<(), AppError> {
+        let mut orgs = self.orgs.write().map_err(|_| poisoned())?;
+        orgs.insert(org.id, org.clone());
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<OrganizationDoc>, AppError> {
+        let orgs = self.orgs.read().map_err(|_| poisoned())?;
+        Ok(orgs.get(&id).cloned())
+    }
+
+    async fn find_many(&self, ids: &[ObjectId]) -> Result<Vec<OrganizationDoc>, AppError> {
+        let orgs = self.orgs.read().map_err(|_| poisoned())?;
+        Ok(ids.iter().filter_map(|id| orgs.get(id).cloned()).collect())
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<bool, AppError> {
+        let mut orgs = self.orgs.write().map_err(|_| poisoned())?;
+        Ok(orgs.remove(&id).is_some())
+    }
+
+    async fn set_plan(
+        &self,
+        id: ObjectId,
+        plan: &str,
+    ) -> Result<Option<OrganizationDoc>, AppError> {
+        let mut orgs = self.orgs.write().map_err(|_| poisoned())?;
+        Ok(orgs.get_mut(&id).map(|o| {
+            o.plan = Some(plan.to_string());
+            o.clone()
+        }))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryPlanStore {
+    plans: RwLock<HashMap<String, PlanDoc>>,
+}
+
+#[async_trait]
+impl PlanStore for MemoryPlanStore {
+    async fn list(&self) -> Result<Vec<PlanDoc>, AppError> {
+        let plans = self.plans.read().map_err(|_| poisoned())?;
+        let mut out: Vec<PlanDoc> = plans.values().cloned().collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    async fn find(&self, name: &str) -> Result<Option<PlanDoc>, AppError> {
+        let plans = self.plans.read().map_err(|_| poisoned())?;
+        Ok(plans.get(name).cloned())
+    }
+
+    async fn upsert(&self, plan: &PlanDoc) -> Result<(), AppError> {
+        let mut plans = self.plans.write().map_err(|_| poisoned())?;
+        plans.insert(plan.name.clone(), plan.clone());
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryMembershipStore {
+    memberships: RwLock<HashMap<ObjectId, MembershipDoc>>,
+}
+
+#[async_trait]
+impl MembershipStore for MemoryMembershipStore {
+    async fn insert(&self, membership: &MembershipDoc) -> Result<(), AppError> {
+        let mut memberships = self.memberships.write().map_err(|_| poisoned())?;
+        if memberships
+            .values()
+            .any(|m| m.org_id == membership.org_id && m.user_id == membership.user_id)
+        {
+            return Err(AppError::Conflict("already a member".into()));
+        }
+        memberships.insert(membership.id, membership.clone());
+        Ok(())
+    }
+
+    async fn find(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<MembershipDoc>, AppError> {
+        let memberships = self.memberships.read().map_err(|_| poisoned())?;
+        Ok(memberships
+            .values()
+            .find(|m| m.org_id == org_id && m.user_id == user_id)
+            .cloned())
+    }
+
+    async fn list_for_org(&self, org_id: ObjectId) -> Result<Vec<MembershipDoc>, AppError> {
+        let memberships = self.memberships.read().map_err(|_| poisoned())?;
+        let mut out: Vec<MembershipDoc> = memberships
+            .values()
+            .filter(|m| m.org_id == org_id)
+            .cloned()
+            .collect();
+        out.sort_by_key(|m| m.id);
+        Ok(out)
+    }
+
+    async fn list_for_user(&self, user_id: ObjectId) -> Result<Vec<MembershipDoc>, AppError> {
+        let memberships = self.memberships.read().map_err(|_| poisoned())?;
+        let mut out: Vec<MembershipDoc> = memberships
+            .values()
+            .filter(|m| m.user_id == user_id)
+            .cloned()
+            .collect();
+        out.sort_by_key(|m| m.id);
+        Ok(out)
+    }
+
+    async fn set_role(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+        role: OrgRole,
+    ) -> Result<Option<MembershipDoc>, AppError> {
+        let mut memberships = self.memberships.write().map_err(|_| poisoned())?;
+        Ok(memberships
+            .values_mut()
+            .find(|m| m.org_id == org_id && m.user_id == user_id)
+            .map(|m| {
+                m.role = role;
+                m.clone()
+            }))
+    }
+
+    async fn delete(&self, org_id: ObjectId, user_id: ObjectId) -> Result<bool, AppError> {
+        let mut memberships = self.memberships.write().map_err(|_| poisoned())?;
+        let before = memberships.len();
+        memberships.retain(|_, m| !(m.org_id == org_id && m.user_id == user_id));
+        Ok(memberships.len() < before)
+    }
+
+    async fn delete_for_org(&self, org_id: ObjectId) -> Result<u64, AppError> {
+        let mut memberships = self.memberships.write().map_err(|_| poisoned())?;
+        let before = memberships.len();
+        memberships.retain(|_, m| m.org_id != org_id);
+        Ok((before - memberships.len()) as u64)
+    }
+}
@@ -0,0 +1,566 @@
+//! Storage abstraction behind `AppState`.
+//!
+//! Services talk to these traits instead of raw `mongodb::Collection`s, so the
+//! same router runs either against MongoDB (`mongo`) or fully in memory
+//! (`memory`, for integration tests and local dev).
+
+pub mod memory;
+pub mod mongo;
+
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+
+use crate::{
+    errors::AppError,
+    models::{
+        api_key::{ApiKeyDoc, ApiKeyOwner, QuotaAlgorithm},
+        api_key_usage::{ApiKeyUsageDoc, UsageGranularity},
+        audit_event::{AuditAction, AuditEventDoc, AuditOutcome},
+        auth_code::AuthCodeDoc,
+        login_attempt::LoginAttemptDoc,
+        membership::{MembershipDoc, OrgRole},
+        oauth_client::OAuthClientDoc,
+        organization::OrganizationDoc,
+        passkey::PasskeyDoc,
+        plan::PlanDoc,
+        refresh_token::RefreshTokenDoc,
+        revoked_token::RevokedTokenDoc,
+        signing_key::SigningKeyDoc,
+        totp_factor::TotpFactorDoc,
+        user::UserDoc,
+        user_token::{UserTokenDoc, UserTokenPurpose},
+        webauthn_challenge::{WebAuthnCeremony, WebAuthnChallengeDoc},
+    },
+};
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<UserDoc>, AppError>;
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<UserDoc>, AppError>;
+
+    /// Inserts a new user; email must be unique.
+    async fn insert(&self, user: &UserDoc) -> Result<(), AppError>;
+
+    async fn set_default_api_key(
+        &self,
+        user_id: ObjectId,
+        key_id: ObjectId,
+    ) -> Result<(), AppError>;
+
+    async fn set_email_verified(&self, user_id: ObjectId) -> Result<(), AppError>;
+
+    async fn set_password_hash(
+        &self,
+        user_id: ObjectId,
+        password_hash: &str,
+    ) -> Result<(), AppError>;
+
+    /// Atomically increments `token_version`, returns the new value (`None` if no such user).
+    async fn bump_token_version(&self, user_id: ObjectId) -> Result<Option<i64>, AppError>;
+
+    /// Replaces roles and permissions and bumps `token_version` (tokens carrying the
+    /// old roles must be refreshed); `None` if the user does not exist.
+    async fn set_roles(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String],
+    ) -> Result<Option<UserDoc>, AppError>;
+
+    /// Sets the `disabled` flag, returns the updated doc (`None` if no such user).
+    async fn set_disabled(
+        &self,
+        user_id: ObjectId,
+        disabled: bool,
+    ) -> Result<Option<UserDoc>, AppError>;
+
+    /// Returns false if no such user.
+    async fn delete(&self, user_id: ObjectId) -> Result<bool, AppError>;
+
+    /// Matching users, newest first, at most `limit`.
+    async fn search(&self, filter: &UserFilter, limit: i64) -> Result<Vec<UserDoc>, AppError>;
+
+    /// Assigns a plan, returns the updated doc (`None` if no such user).
+    async fn set_plan(&self, user_id: ObjectId, plan: &str) -> Result<Option<UserDoc>, AppError>;
+}
+
+/// Admin user search; `None` fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    /// Case-insensitive substring of the email or name.
+    pub query: Option<String>,
+    pub disabled: Option<bool>,
+    pub role: Option<String>,
+    /// Pagination cursor: only users older than this id.
+    pub before: Option<ObjectId>,
+}
+
+#[async_trait]
+pub trait RefreshTokenStore: Send + Sync {
+    async fn insert(&self, token: &RefreshTokenDoc) -> Result<(), AppError>;
+
+    async fn find_by_hash(&self, token_hash: &str) -> Result<Option<RefreshTokenDoc>, AppError>;
+
+    /// Rotation: revoke `id` and point `replaced_by` at the new token doc, only if
+    /// `id` is not revoked yet. `false` means another rotation (or a revocation)
+    /// got there first.
+    async fn mark_replaced(&self, id: ObjectId, replaced_by: ObjectId) -> Result<bool, AppError>;
+
+    /// Revokes the token with this hash if it is not revoked yet (no-op otherwise).
+    async fn revoke_by_hash(&self, token_hash: &str) -> Result<(), AppError>;
+
+    /// Revokes every non-revoked refresh token of the user, returns how many were revoked.
+    async fn revoke_all_for_user(&self, user_id: ObjectId) -> Result<u64, AppError>;
+
+    /// Revokes every non-revoked token of a rotation chain owned by `user_id`, returns
+    /// how many were revoked.
+    async fn revoke_family(&self, family_id: ObjectId, user_id: ObjectId) -> Result<u64, AppError>;
+
+    /// Not revoked and not expired tokens of the user, most recently used first.
+    async fn list_active_for_user(
+        &self,
+        user_id: ObjectId,
+        now: BsonDateTime,
+    ) -> Result<Vec<RefreshTokenDoc>, AppError>;
+
+    /// Revokes token `id` owned by `user_id` and returns it; `None` if no such
+    /// non-revoked token.
+    async fn revoke_for_user(
+        &self,
+        id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<RefreshTokenDoc>, AppError>;
+}
+
+/// Partial update of an API key; `None` fields are left untouched.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeyPatch {
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    /// `Some(None)` removes the expiry.
+    pub expires_at: Option<Option<BsonDateTime>>,
+    pub requests_per_minute: Option<i32>,
+    pub requests_per_day: Option<i64>,
+    /// Whether the limits are the key's own or follow its plan.
+    pub custom_limits: Option<bool>,
+    pub quota_algorithm: Option<QuotaAlgorithm>,
+    pub active: Option<bool>,
+    /// Zeroes the minute and day usage counters and refills the token bucket.
+    pub reset_usage: bool,
+}
+
+/// Admin key search; `None` fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeyFilter {
+    /// Owner of personal keys.
+    pub user_id: Option<ObjectId>,
+    pub org_id: Option<ObjectId>,
+    /// Creator of personal and organization keys.
+    pub created_by: Option<ObjectId>,
+    pub active: Option<bool>,
+    /// Pagination cursor: only keys older than this id.
+    pub before: Option<ObjectId>,
+}
+
+/// Keys a plan is applied to.
+#[derive(Debug, Clone)]
+pub enum PlanKeys {
+    /// Every key of the owner (its plan changed).
+    Owner(ApiKeyOwner),
+    /// Keys already on the plan (its limits changed).
+    Plan(String),
+    /// Keys created before plans existed.
+    Unassigned,
+}
+
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// Inserts a new key; `key_hash` must be unique.
+    async fn insert(&self, key: &ApiKeyDoc) -> Result<(), AppError>;
+
+    /// Active and not expired key by `key_hash`.
+    async fn find_active_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Active key `id` of `owner`.
+    async fn find_active_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// All keys of `owner` (active or not), oldest first.
+    async fn list_for_owner(&self, owner: ApiKeyOwner) -> Result<Vec<ApiKeyDoc>, AppError>;
+
+    /// Key `id` of `owner`, regardless of its state.
+    async fn find_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Applies `patch` to key `id` of `owner`, returns the updated doc.
+    async fn update_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Deletes key `id` of `owner` and returns it; `None` if no such key.
+    async fn delete_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Deletes every key of `owner`; returns how many.
+    async fn delete_all_for_owner(&self, owner: ApiKeyOwner) -> Result<u64, AppError>;
+
+    /// Any key by id (admin).
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Matching keys, newest first, at most `limit` (admin).
+    async fn search(&self, filter: &ApiKeyFilter, limit: i64) -> Result<Vec<ApiKeyDoc>, AppError>;
+
+    /// Applies `patch` to key `id` whoever owns it (admin), returns the updated doc.
+    async fn update(
+        &self,
+        id: ObjectId,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Puts `keys` on `plan`: keys following their plan take its limits, keys with
+    /// custom limits are only lowered to them. Bumps `generation` of the keys that
+    /// changed and returns how many.
+    async fn apply_plan(&self, keys: PlanKeys, plan: &PlanDoc) -> Result<u64, AppError>;
+
+    /// Replaces hash + ciphertext of key `id` of `owner` (and re-activates it).
+    /// Returns the key as it was before; `None` if no such key.
+    async fn replace_secret(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+        key_hash: &str,
+        key_ciphertext: Vec<u8>,
+        key_nonce: [u8; 12],
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Bumps `generation` of every key of `owner` after the owner itself changed
+    /// (e.g. a user was disabled). Returns how many keys.
+    async fn bump_generation(&self, owner: ApiKeyOwner) -> Result<u64, AppError>;
+
+    /// Atomically checks minute/day quota of an active key and counts `count`
+    /// requests (a lease when more than one).
+    ///
+    /// `minute` is the unix minute, `day` is yyyymmdd (UTC); counters are rolled over
+    /// when the stored bucket differs. The minute limit is checked with the key's
+    /// `quota_algorithm`. Returns the updated doc, or `None` if the key is
+    /// unknown/inactive/expired or fewer than `count` requests are left.
+    async fn consume_quota(
+        &self,
+        key_hash: &str,
+        now: BsonDateTime,
+        minute: i64,
+        day: i32,
+        count: i32,
+    ) -> Result<Option<ApiKeyDoc>, AppError>;
+
+    /// Gives back `count` unused requests of a lease taken in `minute`/`day`; each
+    /// counter is only lowered if it is still in that window. Moves `last_used_at`
+    /// forward to `last_used_at`.
+    async fn release_quota(
+        &self,
+        id: ObjectId,
+        minute: i64,
+        day: i32,
+        count: i32,
+        last_used_at: BsonDateTime,
+    ) -> Result<(), AppError>;
+}
+
+/// Requests to add to one usage rollup.
+#[derive(Debug, Clone)]
+pub struct UsageIncrement {
+    /// Unique per increment: writing it again (a retry) changes nothing.
+    pub id: ObjectId,
+    pub key_id: ObjectId,
+    pub owner: ApiKeyOwner,
+    pub granularity: UsageGranularity,
+    pub period_start: BsonDateTime,
+    pub requests: i64,
+}
+
+#[async_trait]
+pub trait ApiKeyUsageStore: Send + Sync {
+    /// Adds to the rollup of (`key_id`, `granularity`, `period_start`), creating it.
+    /// An increment whose `id` was already added is skipped.
+    async fn add(&self, increment: &UsageIncrement) -> Result<(), AppError>;
+
+    /// Rollups of the key with `from <= period_start < to`, oldest first.
+    async fn find(
+        &self,
+        key_id: ObjectId,
+        granularity: UsageGranularity,
+        from: BsonDateTime,
+        to: BsonDateTime,
+    ) -> Result<Vec<ApiKeyUsageDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait SigningKeyStore: Send + Sync {
+    /// All keys (active and retired), oldest first.
+    async fn list(&self) -> Result<Vec<SigningKeyDoc>, AppError>;
+
+    async fn insert(&self, key: &SigningKeyDoc) -> Result<(), AppError>;
+
+    /// Makes `id` the only active key; other active keys get `retired_at = now`.
+    async fn activate(&self, id: ObjectId, now: BsonDateTime) -> Result<(), AppError>;
+
+    /// Deletes keys retired before `cutoff`, returns how many were deleted.
+    async fn delete_retired_before(&self, cutoff: BsonDateTime) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait OAuthClientStore: Send + Sync {
+    /// Inserts a new client; `client_id` must be unique.
+    async fn insert(&self, client: &OAuthClientDoc) -> Result<(), AppError>;
+
+    /// Active client by `client_id`.
+    async fn find_active(&self, client_id: &str) -> Result<Option<OAuthClientDoc>, AppError>;
+
+    /// All clients, oldest first.
+    async fn list(&self) -> Result<Vec<OAuthClientDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait AuthCodeStore: Send + Sync {
+    async fn insert(&self, code: &AuthCodeDoc) -> Result<(), AppError>;
+
+    /// Atomically removes and returns a not expired code (codes are single-use).
+    async fn take_by_hash(
+        &self,
+        code_hash: &str,
+        now: BsonDateTime,
+    ) -> Result<Option<AuthCodeDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait UserTokenStore: Send + Sync {
+    /// Inserts a new token; `token_hash` must be unique.
+    async fn insert(&self, token: &UserTokenDoc) -> Result<(), AppError>;
+
+    /// Atomically removes and returns a not expired token for `purpose` (single-use).
+    async fn take_by_hash(
+        &self,
+        token_hash: &str,
+        purpose: UserTokenPurpose,
+        now: BsonDateTime,
+    ) -> Result<Option<UserTokenDoc>, AppError>;
+
+    /// Invalidates every outstanding token of the user for `purpose`.
+    async fn delete_for_user(
+        &self,
+        user_id: ObjectId,
+        purpose: UserTokenPurpose,
+    ) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait TotpFactorStore: Send + Sync {
+    async fn find_for_user(&self, user_id: ObjectId) -> Result<Option<TotpFactorDoc>, AppError>;
+
+    /// Inserts a new factor; one per user.
+    async fn insert(&self, factor: &TotpFactorDoc) -> Result<(), AppError>;
+
+    /// Finishes a pending enrollment. Returns false if there is no pending factor.
+    async fn enable(
+        &self,
+        user_id: ObjectId,
+        recovery_code_hashes: &[String],
+        now: BsonDateTime,
+    ) -> Result<bool, AppError>;
+
+    /// Atomically records `step` as used; false if it (or a later one) was used already.
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<bool, AppError>;
+
+    /// Atomically removes an unused recovery code; false if there is no such code.
+    async fn take_recovery_code(
+        &self,
+        user_id: ObjectId,
+        code_hash: &str,
+    ) -> Result<bool, AppError>;
+
+    async fn set_recovery_codes(
+        &self,
+        user_id: ObjectId,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError>;
+
+    /// Returns false if the user has no factor.
+    async fn delete_for_user(&self, user_id: ObjectId) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait PasskeyStore: Send + Sync {
+    /// Inserts a new passkey; `credential_id` must be unique.
+    async fn insert(&self, passkey: &PasskeyDoc) -> Result<(), AppError>;
+
+    async fn find_by_credential_id(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<PasskeyDoc>, AppError>;
+
+    /// All passkeys of the user, oldest first.
+    async fn list_for_user(&self, user_id: ObjectId) -> Result<Vec<PasskeyDoc>, AppError>;
+
+    /// Deletes passkey `id` owned by `user_id`. Returns false if no such passkey.
+    async fn delete_for_user(&self, id: ObjectId, user_id: ObjectId) -> Result<bool, AppError>;
+
+    /// Stores the counter of a successful assertion if it is above the stored one
+    /// (or both are 0: the authenticator has no counter). Returns false otherwise,
+    /// e.g. when a concurrent assertion with the same counter got there first.
+    async fn record_use(
+        &self,
+        id: ObjectId,
+        sign_count: i64,
+        now: BsonDateTime,
+    ) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait WebAuthnChallengeStore: Send + Sync {
+    async fn insert(&self, challenge: &WebAuthnChallengeDoc) -> Result<(), AppError>;
+
+    /// Atomically removes and returns a not expired challenge (single-use).
+    async fn take_by_hash(
+        &self,
+        challenge_hash: &str,
+        ceremony: WebAuthnCeremony,
+        now: BsonDateTime,
+    ) -> Result<Option<WebAuthnChallengeDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait LoginAttemptStore: Send + Sync {
+    /// Not expired record for `key`.
+    async fn find(&self, key: &str, now: BsonDateTime)
+        -> Result<Option<LoginAttemptDoc>, AppError>;
+
+    /// Atomically counts one failure (an expired record starts over from zero) and
+    /// pushes `expires_at` to at least `expires_at`. Returns the updated record.
+    async fn record_failure(
+        &self,
+        key: &str,
+        now: BsonDateTime,
+        expires_at: BsonDateTime,
+    ) -> Result<LoginAttemptDoc, AppError>;
+
+    async fn lock(
+        &self,
+        key: &str,
+        locked_until: BsonDateTime,
+        expires_at: BsonDateTime,
+    ) -> Result<(), AppError>;
+
+    /// Forgets the record. Returns false if there was none.
+    async fn clear(&self, key: &str) -> Result<bool, AppError>;
+
+    /// Records locked at `now`, longest lock first.
+    async fn list_locked(&self, now: BsonDateTime) -> Result<Vec<LoginAttemptDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait RevokedTokenStore: Send + Sync {
+    /// Adds or extends the entry for `key`; `expires_at` and `token_version` only grow.
+    async fn revoke(
+        &self,
+        key: &str,
+        token_version: Option<i64>,
+        expires_at: BsonDateTime,
+    ) -> Result<(), AppError>;
+
+    /// Entries not expired at `now`.
+    async fn list_active(&self, now: BsonDateTime) -> Result<Vec<RevokedTokenDoc>, AppError>;
+}
+
+/// Audit log query; `None` fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub user_id: Option<ObjectId>,
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub outcome: Option<AuditOutcome>,
+    pub from: Option<BsonDateTime>,
+    pub to: Option<BsonDateTime>,
+    /// Pagination cursor: only events older than this id.
+    pub before: Option<ObjectId>,
+}
+
+#[async_trait]
+pub trait AuditEventStore: Send + Sync {
+    async fn insert(&self, event: &AuditEventDoc) -> Result<(), AppError>;
+
+    /// Matching events, newest first, at most `limit`.
+    async fn find(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditEventDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait OrganizationStore: Send + Sync {
+    async fn insert(&self, org: &OrganizationDoc) -> Result<(), AppError>;
+
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<OrganizationDoc>, AppError>;
+
+    /// Organizations among `ids` (missing ones are skipped).
+    async fn find_many(&self, ids: &[ObjectId]) -> Result<Vec<OrganizationDoc>, AppError>;
+
+    /// Returns false if no such organization.
+    async fn delete(&self, id: ObjectId) -> Result<bool, AppError>;
+
+    /// Assigns a plan, returns the updated doc (`None` if no such organization).
+    async fn set_plan(&self, id: ObjectId, plan: &str)
+        -> Result<Option<OrganizationDoc>, AppError>;
+}
+
+#[async_trait]
+pub trait PlanStore: Send + Sync {
+    /// Plans stored by admins (without the configured ones).
+    async fn list(&self) -> Result<Vec<PlanDoc>, AppError>;
+
+    async fn find(&self, name: &str) -> Result<Option<PlanDoc>, AppError>;
+
+    /// Creates or replaces the plan.
+    async fn upsert(&self, plan: &PlanDoc) -> Result<(), AppError>;
+}
+
+#[async_trait]
+pub trait MembershipStore: Send + Sync {
+    /// Inserts a membership; (`org_id`, `user_id`) must be unique.
+    async fn insert(&self, membership: &MembershipDoc) -> Result<(), AppError>;
+
+    async fn find(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<MembershipDoc>, AppError>;
+
+    /// Members of the organization, oldest first.
+    async fn list_for_org(&self, org_id: ObjectId) -> Result<Vec<MembershipDoc>, AppError>;
+
+    /// Organizations the user belongs to, oldest first.
+    async fn list_for_user(&self, user_id: ObjectId) -> Result<Vec<MembershipDoc>, AppError>;
+
+    /// Changes the member's role, returns the updated doc.
+    async fn set_role(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+        role: OrgRole,
+    ) -> Result<Option<MembershipDoc>, AppError>;
+
+    /// Returns false if the user is not a member.
+    async fn delete(&self, org_id: ObjectId, user_id: ObjectId) -> Result<bool, AppError>;
+
+    /// Removes every member of the organization; returns how many.
+    async fn delete_for_org(&self, org_id: ObjectId) -> Result<u64, AppError>;
+}
@@ -0,0 +1,1739 @@
+use async_trait::async_trait;
+use bson::{spec::BinarySubtype, Binary};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime as BsonDateTime, Document},
+    error::{ErrorKind, WriteFailure},
+    options::{IndexOptions, ReturnDocument},
+    Collection, Database, IndexModel,
+};
+use std::time::Duration;
+
+use crate::{
+    errors::AppError,
+    models::{
+        api_key::{previous_minute_weight, ApiKeyDoc, ApiKeyOwner, QuotaAlgorithm},
+        api_key_usage::{ApiKeyUsageDoc, UsageGranularity, APPLIED_INCREMENTS_KEPT},
+        audit_event::AuditEventDoc,
+        auth_code::AuthCodeDoc,
+        login_attempt::LoginAttemptDoc,
+        membership::{MembershipDoc, OrgRole},
+        oauth_client::OAuthClientDoc,
+        organization::OrganizationDoc,
+        passkey::PasskeyDoc,
+        plan::PlanDoc,
+        refresh_token::RefreshTokenDoc,
+        revoked_token::RevokedTokenDoc,
+        signing_key::SigningKeyDoc,
+        totp_factor::TotpFactorDoc,
+        user::UserDoc,
+        user_token::{UserTokenDoc, UserTokenPurpose},
+        webauthn_challenge::{WebAuthnCeremony, WebAuthnChallengeDoc},
+    },
+    store::{
+        ApiKeyFilter, ApiKeyPatch, ApiKeyStore, ApiKeyUsageStore, AuditEventStore, AuditFilter,
+        AuthCodeStore, LoginAttemptStore, MembershipStore, OAuthClientStore, OrganizationStore,
+        PasskeyStore, PlanKeys, PlanStore, RefreshTokenStore, RevokedTokenStore, SigningKeyStore,
+        TotpFactorStore, UsageIncrement, UserFilter, UserStore, UserTokenStore,
+        WebAuthnChallengeStore,
+    },
+};
+
+fn not_expired(now: BsonDateTime) -> Document {
+    doc! {
+        "$or": [
+            { "expires_at": Bson::Null },
+            { "expires_at": { "$exists": false } },
+            { "expires_at": { "$gt": now } },
+        ]
+    }
+}
+
+pub struct MongoUserStore {
+    users: Collection<UserDoc>,
+}
+
+impl MongoUserStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let users: Collection<UserDoc> = db.collection("users");
+
+        // unique email
+        let email_index = IndexModel::builder()
+            .keys(doc! { "email": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        users.create_index(email_index).await?;
+
+        Ok(Self { users })
+    }
+}
+
+#[async_trait]
+impl UserStore for MongoUserStore {
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<UserDoc>, AppError> {
+        Ok(self.users.find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<UserDoc>, AppError> {
+        Ok(self.users.find_one(doc! { "email": email }).await?)
+    }
+
+    async fn insert(&self, user: &UserDoc) -> Result<(), AppError> {
+        self.users.insert_one(user).await?;
+        Ok(())
+    }
+
+    async fn set_default_api_key(
+        &self,
+        user_id: ObjectId,
+        key_id: ObjectId,
+    ) -> Result<(), AppError> {
+        self.users
+            .update_one(
+                doc! { "_id": user_id },
+                doc! { "$set": { "default_api_key_id": key_id } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn set_email_verified(&self, user_id: ObjectId) -> Result<(), AppError> {
+        self.users
+            .update_one(
+                doc! { "_id": user_id },
+                doc! { "$set": { "email_verified": true } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn set_password_hash(
+        &self,
+        user_id: ObjectId,
+        password_hash: &str,
+    ) -> Result<(), AppError> {
+        self.users
+            .update_one(
+                doc! { "_id": user_id },
+                doc! { "$set": { "password_hash": password_hash } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn bump_token_version(&self, user_id: ObjectId) -> Result<Option<i64>, AppError> {
+        let updated = self
+            .users
+            .find_one_and_update(
+                doc! { "_id": user_id },
+                doc! { "$inc": { "token_version": 1_i64 } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?;
+        Ok(updated.map(|u| u.token_version))
+    }
+
+    async fn set_roles(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String],
+    ) -> Result<Option<UserDoc>, AppError> {
+        Ok(self
+            .users
+            .find_one_and_update(
+                doc! { "_id": user_id },
+                doc! {
+                    "$set": { "roles": roles, "permissions": permissions },
+                    "$inc": { "token_version": 1_i64 },
+                },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    async fn set_disabled(
+        &self,
+        user_id: ObjectId,
+        disabled: bool,
+    ) -> Result<Option<UserDoc>, AppError> {
+        Ok(self
+            .users
+            .find_one_and_update(
+                doc! { "_id": user_id },
+                doc! { "$set": { "disabled": disabled } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    async fn delete(&self, user_id: ObjectId) -> Result<bool, AppError> {
+        let res = self.users.delete_one(doc! { "_id": user_id }).await?;
+        Ok(res.deleted_count == 1)
+    }
+
+    async fn search(&self, filter: &UserFilter, limit: i64) -> Result<Vec<UserDoc>, AppError> {
+        let mut query = Document::new();
+        if let Some(q) = &filter.query {
+            let pattern = escape_regex(q);
+            query.insert(
+                "$or",
+                vec![
+                    doc! { "email": { "$regex": &pattern, "$options": "i" } },
+                    doc! { "name": { "$regex": &pattern, "$options": "i" } },
+                ],
+            );
+        }
+        if let Some(disabled) = filter.disabled {
+            // users created before the flag have no field
+            query.insert(
+                "disabled",
+                if disabled {
+                    doc! { "$eq": true }
+                } else {
+                    doc! { "$ne": true }
+                },
+            );
+        }
+        if let Some(role) = &filter.role {
+            query.insert("roles", role);
+        }
+        if let Some(before) = filter.before {
+            query.insert("_id", doc! { "$lt": before });
+        }
+
+        let cursor = self
+            .users
+            .find(query)
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn set_plan(&self, user_id: ObjectId, plan: &str) -> Result<Option<UserDoc>, AppError> {
+        Ok(self
+            .users
+            .find_one_and_update(doc! { "_id": user_id }, doc! { "$set": { "plan": plan } })
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+}
+
+/// Literal text for a `$regex`.
+fn escape_regex(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub struct MongoRefreshTokenStore {
+    refresh_tokens: Collection<RefreshTokenDoc>,
+}
+
+impl MongoRefreshTokenStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let refresh_tokens: Collection<RefreshTokenDoc> = db.collection("refresh_tokens");
+
+        // unique refresh token hash
+        let token_hash_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        refresh_tokens.create_index(token_hash_index).await?;
+
+        // unique jti
+        let jti_index = IndexModel::builder()
+            .keys(doc! { "jti": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        refresh_tokens.create_index(jti_index).await?;
+
+        // sessions of a user
+        let user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "revoked_at": 1 })
+            .build();
+        refresh_tokens.create_index(user_index).await?;
+
+        // rotation chains (reuse detection)
+        let family_index = IndexModel::builder().keys(doc! { "family_id": 1 }).build();
+        refresh_tokens.create_index(family_index).await?;
+
+        Ok(Self { refresh_tokens })
+    }
+}
+
+#[async_trait]
+impl RefreshTokenStore for MongoRefreshTokenStore {
+    async fn insert(&self, token: &RefreshTokenDoc) -> Result<(), AppError> {
+        self.refresh_tokens.insert_one(token).await?;
+        Ok(())
+    }
+
+    async fn find_by_hash(&self, token_hash: &str) -> Result<Option<RefreshTokenDoc>, AppError> {
+        Ok(self
+            .refresh_tokens
+            .find_one(doc! { "token_hash": token_hash })
+            .await?)
+    }
+
+    async fn mark_replaced(&self, id: ObjectId, replaced_by: ObjectId) -> Result<bool, AppError> {
+        let replaced = self
+            .refresh_tokens
+            .find_one_and_update(
+                doc! { "_id": id, "revoked_at": Bson::Null },
+                doc! { "$set": { "revoked_at": BsonDateTime::now(), "replaced_by": replaced_by } },
+            )
+            .await?;
+        Ok(replaced.is_some())
+    }
+
+    async fn revoke_by_hash(&self, token_hash: &str) -> Result<(), AppError> {
+        self.refresh_tokens
+            .update_one(
+                doc! { "token_hash": token_hash, "revoked_at": Bson::Null },
+                doc! { "$set": { "revoked_at": BsonDateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: ObjectId) -> Result<u64, AppError> {
+        let res = self
+            .refresh_tokens
+            .update_many(
+                doc! { "user_id": user_id, "revoked_at": Bson::Null },
+                doc! { "$set": { "revoked_at": BsonDateTime::now() } },
+            )
+            .await?;
+        Ok(res.modified_count)
+    }
+
+    async fn revoke_family(&self, family_id: ObjectId, user_id: ObjectId) -> Result<u64, AppError> {
+        let res = self
+            .refresh_tokens
+            .update_many(
+                doc! { "family_id": family_id, "user_id": user_id, "revoked_at": Bson::Null },
+                doc! { "$set": { "revoked_at": BsonDateTime::now() } },
+            )
+            .await?;
+        Ok(res.modified_count)
+    }
+
+    async fn list_active_for_user(
+        &self,
+        user_id: ObjectId,
+        now: BsonDateTime,
+    ) -> Result<Vec<RefreshTokenDoc>, AppError> {
+        let cursor = self
+            .refresh_tokens
+            .find(doc! {
+                "user_id": user_id,
+                "revoked_at": Bson::Null,
+                "expires_at": { "$gt": now },
+            })
+            .sort(doc! { "created_at": -1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn revoke_for_user(
+        &self,
+        id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<RefreshTokenDoc>, AppError> {
+        Ok(self
+            .refresh_tokens
+            .find_one_and_update(
+                doc! { "_id": id, "user_id": user_id, "revoked_at": Bson::Null },
+                doc! { "$set": { "revoked_at": BsonDateTime::now() } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+}
+
+pub struct MongoApiKeyStore {
+    api_keys: Collection<ApiKeyDoc>,
+}
+
+impl MongoApiKeyStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let api_keys: Collection<ApiKeyDoc> = db.collection("api_keys");
+
+        // unique key_hash
+        let key_hash_index = IndexModel::builder()
+            .keys(doc! { "key_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        api_keys.create_index(key_hash_index).await?;
+
+        // compound index для быстрого поиска active + user_id + scopes
+        let active_user_index = IndexModel::builder()
+            .keys(doc! {
+                "user_id": 1,
+                "active": 1,
+                "expires_at": 1
+            })
+            .build();
+        api_keys.create_index(active_user_index).await?;
+
+        // plan changes are applied to keys by plan name
+        let plan_index = IndexModel::builder().keys(doc! { "plan": 1 }).build();
+        api_keys.create_index(plan_index).await?;
+
+        Ok(Self { api_keys })
+    }
+
+    async fn apply_patch(
+        &self,
+        filter: Document,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut set = Document::new();
+        if let Some(name) = &patch.name {
+            set.insert("name", name);
+        }
+        if let Some(scopes) = &patch.scopes {
+            set.insert("scopes", scopes);
+        }
+        if let Some(expires_at) = patch.expires_at {
+            set.insert("expires_at", expires_at);
+        }
+        if let Some(rpm) = patch.requests_per_minute {
+            set.insert("requests_per_minute", rpm);
+        }
+        if let Some(rpd) = patch.requests_per_day {
+            set.insert("requests_per_day", rpd);
+        }
+        if let Some(custom) = patch.custom_limits {
+            set.insert("custom_limits", custom);
+        }
+        if let Some(algorithm) = patch.quota_algorithm {
+            set.insert("quota_algorithm", algorithm.as_str());
+        }
+        if let Some(active) = patch.active {
+            set.insert("active", active);
+        }
+
+        if patch.reset_usage {
+            set.insert("requests_used_minute", 0_i32);
+            set.insert("requests_prev_minute", 0_i32);
+            set.insert("requests_used_today", 0_i64);
+            set.insert("bucket_tokens", Bson::Null);
+        }
+
+        if set.is_empty() {
+            return Ok(self.api_keys.find_one(filter).await?);
+        }
+
+        Ok(self
+            .api_keys
+            .find_one_and_update(
+                filter,
+                doc! { "$set": set, "$inc": { "generation": 1_i64 } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+}
+
+/// Keys of `owner`: personal keys have no `org_id`.
+fn owner_filter(owner: ApiKeyOwner) -> Document {
+    match owner {
+        ApiKeyOwner::User(user_id) => doc! { "user_id": user_id, "org_id": null },
+        ApiKeyOwner::Org(org_id) => doc! { "org_id": org_id },
+    }
+}
+
+fn key_filter(id: ObjectId, owner: ApiKeyOwner) -> Document {
+    let mut filter = owner_filter(owner);
+    filter.insert("_id", id);
+    filter
+}
+
+#[async_trait]
+impl ApiKeyStore for MongoApiKeyStore {
+    async fn insert(&self, key: &ApiKeyDoc) -> Result<(), AppError> {
+        self.api_keys.insert_one(key).await?;
+        Ok(())
+    }
+
+    async fn find_active_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut filter = doc! { "key_hash": key_hash, "active": true };
+        filter.extend(not_expired(BsonDateTime::now()));
+        Ok(self.api_keys.find_one(filter).await?)
+    }
+
+    async fn find_active_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let mut filter = key_filter(id, owner);
+        filter.insert("active", true);
+        Ok(self.api_keys.find_one(filter).await?)
+    }
+
+    async fn list_for_owner(&self, owner: ApiKeyOwner) -> Result<Vec<ApiKeyDoc>, AppError> {
+        let cursor = self
+            .api_keys
+            .find(owner_filter(owner))
+            .sort(doc! { "created_at": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn find_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        Ok(self.api_keys.find_one(key_filter(id, owner)).await?)
+    }
+
+    async fn update_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        self.apply_patch(key_filter(id, owner), patch).await
+    }
+
+    async fn update(
+        &self,
+        id: ObjectId,
+        patch: &ApiKeyPatch,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        self.apply_patch(doc! { "_id": id }, patch).await
+    }
+
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<ApiKeyDoc>, AppError> {
+        Ok(self.api_keys.find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn search(&self, filter: &ApiKeyFilter, limit: i64) -> Result<Vec<ApiKeyDoc>, AppError> {
+        let mut query = Document::new();
+        if let Some(user_id) = filter.user_id {
+            query.extend(owner_filter(ApiKeyOwner::User(user_id)));
+        }
+        if let Some(org_id) = filter.org_id {
+            query.extend(owner_filter(ApiKeyOwner::Org(org_id)));
+        }
+        if let Some(created_by) = filter.created_by {
+            // keys from before `created_by` have their creator in `user_id`
+            query.insert(
+                "$or",
+                vec![
+                    doc! { "created_by": created_by },
+                    doc! { "created_by": null, "user_id": created_by },
+                ],
+            );
+        }
+        if let Some(active) = filter.active {
+            query.insert("active", active);
+        }
+        if let Some(before) = filter.before {
+            query.insert("_id", doc! { "$lt": before });
+        }
+
+        let cursor = self
+            .api_keys
+            .find(query)
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn apply_plan(&self, target: PlanKeys, plan: &PlanDoc) -> Result<u64, AppError> {
+        let filter = match target {
+            PlanKeys::Owner(owner) => owner_filter(owner),
+            PlanKeys::Plan(name) => doc! { "plan": name },
+            PlanKeys::Unassigned => doc! { "plan": null },
+        };
+
+        // only keys that actually change, so `generation` moves just for them
+        let mut following = filter.clone();
+        following.insert("custom_limits", doc! { "$ne": true });
+        following.insert(
+            "$or",
+            vec![
+                doc! { "plan": { "$ne": &plan.name } },
+                doc! { "requests_per_minute": { "$ne": plan.requests_per_minute } },
+                doc! { "requests_per_day": { "$ne": plan.requests_per_day } },
+            ],
+        );
+        let res = self
+            .api_keys
+            .update_many(
+                following,
+                doc! {
+                    "$set": {
+                        "plan": &plan.name,
+                        "requests_per_minute": plan.requests_per_minute,
+                        "requests_per_day": plan.requests_per_day,
+                    },
+                    "$inc": { "generation": 1_i64 },
+                },
+            )
+            .await?;
+
+        let mut custom = filter;
+        custom.insert("custom_limits", true);
+        custom.insert(
+            "$or",
+            vec![
+                doc! { "plan": { "$ne": &plan.name } },
+                doc! { "requests_per_minute": { "$gt": plan.requests_per_minute } },
+                doc! { "requests_per_day": { "$gt": plan.requests_per_day } },
+            ],
+        );
+        let res_custom = self
+            .api_keys
+            .update_many(
+                custom,
+                doc! {
+                    "$set": { "plan": &plan.name },
+                    "$min": {
+                        "requests_per_minute": plan.requests_per_minute,
+                        "requests_per_day": plan.requests_per_day,
+                    },
+                    "$inc": { "generation": 1_i64 },
+                },
+            )
+            .await?;
+
+        Ok(res.modified_count + res_custom.modified_count)
+    }
+
+    async fn delete_all_for_owner(&self, owner: ApiKeyOwner) -> Result<u64, AppError> {
+        let res = self.api_keys.delete_many(owner_filter(owner)).await?;
+        Ok(res.deleted_count)
+    }
+
+    async fn delete_for_owner(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        Ok(self
+            .api_keys
+            .find_one_and_delete(key_filter(id, owner))
+            .await?)
+    }
+
+    async fn replace_secret(
+        &self,
+        id: ObjectId,
+        owner: ApiKeyOwner,
+        key_hash: &str,
+        key_ciphertext: Vec<u8>,
+        key_nonce: [u8; 12],
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        let upd = doc! {
+            "$set": {
+                "key_hash": key_hash,
+                "key_ciphertext": Binary { subtype: BinarySubtype::Generic, bytes: key_ciphertext },
+                "key_nonce": Binary { subtype: BinarySubtype::Generic, bytes: key_nonce.to_vec() },
+                "last_used_at": BsonDateTime::now(),
+                "active": true,
+            },
+            "$inc": { "generation": 1_i64 },
+        };
+
+        Ok(self
+            .api_keys
+            .find_one_and_update(key_filter(id, owner), upd)
+            .return_document(ReturnDocument::Before)
+            .await?)
+    }
+
+    async fn bump_generation(&self, owner: ApiKeyOwner) -> Result<u64, AppError> {
+        let res = self
+            .api_keys
+            .update_many(
+                owner_filter(owner),
+                doc! { "$inc": { "generation": 1_i64 } },
+            )
+            .await?;
+        Ok(res.modified_count)
+    }
+
+    async fn consume_quota(
+        &self,
+        key_hash: &str,
+        now: BsonDateTime,
+        minute: i64,
+        day: i32,
+        count: i32,
+    ) -> Result<Option<ApiKeyDoc>, AppError> {
+        // счётчики минуты после перехода в `minute`: текущая и предыдущая минута
+        let used = doc! { "$cond": [ { "$eq": ["$minute_bucket", minute] }, "$requests_used_minute", 0i32 ] };
+        let prev = doc! { "$switch": {
+            "branches": [
+                { "case": { "$eq": ["$minute_bucket", minute] }, "then": { "$ifNull": ["$requests_prev_minute", 0i32] } },
+                { "case": { "$eq": ["$minute_bucket", minute - 1] }, "then": "$requests_used_minute" },
+            ],
+            "default": 0i32,
+        }};
+        // token bucket, пополненный до `now` (нет значения — полный)
+        let tokens = doc! { "$min": [
+            "$requests_per_minute",
+            { "$add": [
+                { "$ifNull": ["$bucket_tokens", "$requests_per_minute"] },
+                { "$multiply": [
+                    { "$subtract": [now, { "$ifNull": ["$bucket_updated_at", now] }] },
+                    { "$divide": ["$requests_per_minute", 60_000.0] },
+                ]},
+            ]},
+        ]};
+        let weight = previous_minute_weight(now, minute);
+
+        let used_today =
+            doc! { "$cond": [ { "$eq": ["$usage_day", day] }, "$requests_used_today", 0i64 ] };
+
+        // фильтр: ключ активен, не истёк, и осталось `count` запросов по минуте (по
+        // алгоритму ключа) и дню
+        let filter = doc! {
+            "key_hash": key_hash,
+            "active": true,
+            "$and": [
+                not_expired(now),
+                { "$expr": { "$switch": {
+                    "branches": [
+                        { "case": { "$eq": ["$quota_algorithm", QuotaAlgorithm::SlidingWindow.as_str()] },
+                          "then": { "$lte": [
+                              { "$add": [{ "$multiply": [prev.clone(), weight] }, used.clone(), count] },
+                              "$requests_per_minute",
+                          ]}},
+                        { "case": { "$eq": ["$quota_algorithm", QuotaAlgorithm::TokenBucket.as_str()] },
+                          "then": { "$gte": [tokens.clone(), f64::from(count)] } },
+                    ],
+                    "default": { "$lte": [{ "$add": [used.clone(), count] }, "$requests_per_minute"] },
+                }}},
+                { "$expr": { "$lte": [{ "$add": [used_today.clone(), i64::from(count)] }, "$requests_per_day"] } },
+            ],
+        };
+
+        // update pipeline: при смене окна/дня сдвинуть счетчики, пополнить bucket, затем +count
+        let update: Vec<Document> = vec![
+            doc! { "$set": {
+                "last_used_at": now,
+
+                "minute_bucket": minute,
+                "requests_prev_minute": prev,
+                "requests_used_minute": used,
+
+                "usage_day": day,
+                "requests_used_today": used_today,
+
+                "bucket_tokens": tokens,
+                "bucket_updated_at": now,
+            }},
+            doc! { "$set": {
+                "requests_used_minute": { "$add": ["$requests_used_minute", count] },
+                "requests_used_today": { "$add": ["$requests_used_today", i64::from(count)] },
+                "bucket_tokens": { "$subtract": ["$bucket_tokens", f64::from(count)] },
+            }},
+        ];
+
+        Ok(self
+            .api_keys
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    async fn release_quota(
+        &self,
+        id: ObjectId,
+        minute: i64,
+        day: i32,
+        count: i32,
+        last_used_at: BsonDateTime,
+    ) -> Result<(), AppError> {
+        // счётчики уменьшаются, только если окно лизинга ещё текущее
+        let update: Vec<Document> = vec![doc! { "$set": {
+            "requests_used_minute": { "$cond": [
+                { "$eq": ["$minute_bucket", minute] },
+                { "$max": [{ "$subtract": ["$requests_used_minute", count] }, 0i32] },
+                "$requests_used_minute",
+            ]},
+            "requests_used_today": { "$cond": [
+                { "$eq": ["$usage_day", day] },
+                { "$max": [{ "$subtract": ["$requests_used_today", i64::from(count)] }, 0i64] },
+                "$requests_used_today",
+            ]},
+            "bucket_tokens": { "$cond": [
+                { "$isNumber": "$bucket_tokens" },
+                { "$min": [{ "$add": ["$bucket_tokens", f64::from(count)] }, "$requests_per_minute"] },
+                "$bucket_tokens",
+            ]},
+            "last_used_at": { "$max": ["$last_used_at", last_used_at] },
+        }}];
+
+        self.api_keys.update_one(doc! { "_id": id }, update).await?;
+        Ok(())
+    }
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    match &*e.kind {
+        ErrorKind::Write(WriteFailure::WriteError(we)) => we.code == 11000,
+        ErrorKind::Command(ce) => ce.code == 11000,
+        _ => false,
+    }
+}
+
+pub struct MongoApiKeyUsageStore {
+    usage: Collection<ApiKeyUsageDoc>,
+}
+
+impl MongoApiKeyUsageStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let usage: Collection<ApiKeyUsageDoc> = db.collection("api_key_usage");
+
+        let rollup_index = IndexModel::builder()
+            .keys(doc! { "key_id": 1, "granularity": 1, "period_start": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        usage.create_index(rollup_index).await?;
+
+        Ok(Self { usage })
+    }
+}
+
+#[async_trait]
+impl ApiKeyUsageStore for MongoApiKeyUsageStore {
+    async fn add(&self, increment: &UsageIncrement) -> Result<(), AppError> {
+        let rollup = doc! {
+            "key_id": increment.key_id,
+            "granularity": increment.granularity.as_str(),
+            "period_start": increment.period_start,
+        };
+        let mut pending = rollup.clone();
+        pending.insert("applied", doc! { "$ne": increment.id });
+        let update = doc! {
+            "$inc": { "requests": increment.requests },
+            "$push": { "applied": {
+                "$each": [increment.id],
+                "$slice": -(APPLIED_INCREMENTS_KEPT as i32),
+            } },
+            "$setOnInsert": match increment.owner {
+                ApiKeyOwner::User(user_id) => doc! { "user_id": user_id },
+                ApiKeyOwner::Org(org_id) => doc! { "org_id": org_id },
+            },
+        };
+
+        // a duplicate key means the rollup exists but did not match: the increment is
+        // already in it, or another instance created the rollup first (retry once)
+        for _ in 0..2 {
+            match self
+                .usage
+                .update_one(pending.clone(), update.clone())
+                .upsert(true)
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(e) if is_duplicate_key(&e) => {
+                    let mut applied = rollup.clone();
+                    applied.insert("applied", increment.id);
+                    if self.usage.find_one(applied).await?.is_some() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(AppError::Db("usage rollup upsert conflicted twice".into()))
+    }
+
+    async fn find(
+        &self,
+        key_id: ObjectId,
+        granularity: UsageGranularity,
+        from: BsonDateTime,
+        to: BsonDateTime,
+    ) -> Result<Vec<ApiKeyUsageDoc>, AppError> {
+        let cursor = self
+            .usage
+            .find(doc! {
+                "key_id": key_id,
+                "granularity": granularity.as_str(),
+                "period_start": { "$gte": from, "$lt": to },
+            })
+            .sort(doc! { "period_start": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+pub struct MongoSigningKeyStore {
+    signing_keys: Collection<SigningKeyDoc>,
+}
+
+impl MongoSigningKeyStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let signing_keys: Collection<SigningKeyDoc> = db.collection("signing_keys");
+
+        // unique kid
+        let kid_index = IndexModel::builder()
+            .keys(doc! { "kid": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        signing_keys.create_index(kid_index).await?;
+
+        Ok(Self { signing_keys })
+    }
+}
+
+#[async_trait]
+impl SigningKeyStore for MongoSigningKeyStore {
+    async fn list(&self) -> Result<Vec<SigningKeyDoc>, AppError> {
+        let cursor = self
+            .signing_keys
+            .find(doc! {})
+            .sort(doc! { "created_at": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn insert(&self, key: &SigningKeyDoc) -> Result<(), AppError> {
+        self.signing_keys.insert_one(key).await?;
+        Ok(())
+    }
+
+    async fn activate(&self, id: ObjectId, now: BsonDateTime) -> Result<(), AppError> {
+        self.signing_keys
+            .update_many(
+                doc! { "_id": { "$ne": id }, "active": true },
+                doc! { "$set": { "active": false, "retired_at": now } },
+            )
+            .await?;
+        self.signing_keys
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "active": true, "retired_at": Bson::Null } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_retired_before(&self, cutoff: BsonDateTime) -> Result<u64, AppError> {
+        let res = self
+            .signing_keys
+            .delete_many(doc! { "active": false, "retired_at": { "$lt": cutoff } })
+            .await?;
+        Ok(res.deleted_count)
+    }
+}
+
+pub struct MongoOAuthClientStore {
+    clients: Collection<OAuthClientDoc>,
+}
+
+impl MongoOAuthClientStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let clients: Collection<OAuthClientDoc> = db.collection("oauth_clients");
+
+        // unique client_id
+        let client_id_index = IndexModel::builder()
+            .keys(doc! { "client_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        clients.create_index(client_id_index).await?;
+
+        Ok(Self { clients })
+    }
+}
+
+#[async_trait]
+impl OAuthClientStore for MongoOAuthClientStore {
+    async fn insert(&self, client: &OAuthClientDoc) -> Result<(), AppError> {
+        self.clients.insert_one(client).await?;
+        Ok(())
+    }
+
+    async fn find_active(&self, client_id: &str) -> Result<Option<OAuthClientDoc>, AppError> {
+        Ok(self
+            .clients
+            .find_one(doc! { "client_id": client_id, "active": true })
+            .await?)
+    }
+
+    async fn list(&self) -> Result<Vec<OAuthClientDoc>, AppError> {
+        let cursor = self
+            .clients
+            .find(doc! {})
+            .sort(doc! { "created_at": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+pub struct MongoAuthCodeStore {
+    codes: Collection<AuthCodeDoc>,
+}
+
+impl MongoAuthCodeStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let codes: Collection<AuthCodeDoc> = db.collection("oauth_codes");
+
+        // unique code_hash
+        let code_hash_index = IndexModel::builder()
+            .keys(doc! { "code_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        codes.create_index(code_hash_index).await?;
+
+        // TTL: MongoDB drops expired codes by itself
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        codes.create_index(ttl_index).await?;
+
+        Ok(Self { codes })
+    }
+}
+
+#[async_trait]
+impl AuthCodeStore for MongoAuthCodeStore {
+    async fn insert(&self, code: &AuthCodeDoc) -> Result<(), AppError> {
+        self.codes.insert_one(code).await?;
+        Ok(())
+    }
+
+    async fn take_by_hash(
+        &self,
+        code_hash: &str,
+        now: BsonDateTime,
+    ) -> Result<Option<AuthCodeDoc>, AppError> {
+        Ok(self
+            .codes
+            .find_one_and_delete(doc! { "code_hash": code_hash, "expires_at": { "$gt": now } })
+            .await?)
+    }
+}
+
+pub struct MongoUserTokenStore {
+    tokens: Collection<UserTokenDoc>,
+}
+
+impl MongoUserTokenStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let tokens: Collection<UserTokenDoc> = db.collection("user_tokens");
+
+        // unique token_hash
+        let token_hash_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        tokens.create_index(token_hash_index).await?;
+
+        // invalidation on resend
+        let user_purpose_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "purpose": 1 })
+            .build();
+        tokens.create_index(user_purpose_index).await?;
+
+        // TTL: MongoDB drops expired tokens by itself
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        tokens.create_index(ttl_index).await?;
+
+        Ok(Self { tokens })
+    }
+}
+
+#[async_trait]
+impl UserTokenStore for MongoUserTokenStore {
+    async fn insert(&self, token: &UserTokenDoc) -> Result<(), AppError> {
+        self.tokens.insert_one(token).await?;
+        Ok(())
+    }
+
+    async fn take_by_hash(
+        &self,
+        token_hash: &str,
+        purpose: UserTokenPurpose,
+        now: BsonDateTime,
+    ) -> Result<Option<UserTokenDoc>, AppError> {
+        Ok(self
+            .tokens
+            .find_one_and_delete(doc! {
+                "token_hash": token_hash,
+                "purpose": purpose.as_str(),
+                "expires_at": { "$gt": now },
+            })
+            .await?)
+    }
+
+    async fn delete_for_user(
+        &self,
+        user_id: ObjectId,
+        purpose: UserTokenPurpose,
+    ) -> Result<u64, AppError> {
+        let res = self
+            .tokens
+            .delete_many(doc! { "user_id": user_id, "purpose": purpose.as_str() })
+            .await?;
+        Ok(res.deleted_count)
+    }
+}
+
+pub struct MongoTotpFactorStore {
+    factors: Collection<TotpFactorDoc>,
+}
+
+impl MongoTotpFactorStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let factors: Collection<TotpFactorDoc> = db.collection("totp_factors");
+
+        // one factor per user
+        let user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        factors.create_index(user_index).await?;
+
+        Ok(Self { factors })
+    }
+}
+
+#[async_trait]
+impl TotpFactorStore for MongoTotpFactorStore {
+    async fn find_for_user(&self, user_id: ObjectId) -> Result<Option<TotpFactorDoc>, AppError> {
+        Ok(self.factors.find_one(doc! { "user_id": user_id }).await?)
+    }
+
+    async fn insert(&self, factor: &TotpFactorDoc) -> Result<(), AppError> {
+        self.factors.insert_one(factor).await?;
+        Ok(())
+    }
+
+    async fn enable(
+        &self,
+        user_id: ObjectId,
+        recovery_code_hashes: &[String],
+        now: BsonDateTime,
+    ) -> Result<bool, AppError> {
+        let res = self
+            .factors
+            .update_one(
+                doc! { "user_id": user_id, "enabled": false },
+                doc! { "$set": {
+                    "enabled": true,
+                    "enabled_at": now,
+                    "recovery_code_hashes": recovery_code_hashes,
+                } },
+            )
+            .await?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<bool, AppError> {
+        let res = self
+            .factors
+            .update_one(
+                doc! { "user_id": user_id, "last_used_step": { "$lt": step } },
+                doc! { "$set": { "last_used_step": step } },
+            )
+            .await?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn take_recovery_code(
+        &self,
+        user_id: ObjectId,
+        code_hash: &str,
+    ) -> Result<bool, AppError> {
+        let res = self
+            .factors
+            .update_one(
+                doc! { "user_id": user_id, "recovery_code_hashes": code_hash },
+                doc! { "$pull": { "recovery_code_hashes": code_hash } },
+            )
+            .await?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn set_recovery_codes(
+        &self,
+        user_id: ObjectId,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), AppError> {
+        self.factors
+            .update_one(
+                doc! { "user_id": user_id },
+                doc! { "$set": { "recovery_code_hashes": recovery_code_hashes } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_for_user(&self, user_id: ObjectId) -> Result<bool, AppError> {
+        let res = self.factors.delete_one(doc! { "user_id": user_id }).await?;
+        Ok(res.deleted_count == 1)
+    }
+}
+
+pub struct MongoPasskeyStore {
+    passkeys: Collection<PasskeyDoc>,
+}
+
+impl MongoPasskeyStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let passkeys: Collection<PasskeyDoc> = db.collection("passkeys");
+
+        // lookup on login
+        let credential_index = IndexModel::builder()
+            .keys(doc! { "credential_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        passkeys.create_index(credential_index).await?;
+
+        let user_index = IndexModel::builder().keys(doc! { "user_id": 1 }).build();
+        passkeys.create_index(user_index).await?;
+
+        Ok(Self { passkeys })
+    }
+}
+
+#[async_trait]
+impl PasskeyStore for MongoPasskeyStore {
+    async fn insert(&self, passkey: &PasskeyDoc) -> Result<(), AppError> {
+        self.passkeys.insert_one(passkey).await?;
+        Ok(())
+    }
+
+    async fn find_by_credential_id(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<PasskeyDoc>, AppError> {
+        Ok(self
+            .passkeys
+            .find_one(doc! { "credential_id": credential_id })
+            .await?)
+    }
+
+    async fn list_for_user(&self, user_id: ObjectId) -> Result<Vec<PasskeyDoc>, AppError> {
+        let cursor = self
+            .passkeys
+            .find(doc! { "user_id": user_id })
+            .sort(doc! { "created_at": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_for_user(&self, id: ObjectId, user_id: ObjectId) -> Result<bool, AppError> {
+        let res = self
+            .passkeys
+            .delete_one(doc! { "_id": id, "user_id": user_id })
+            .await?;
+        Ok(res.deleted_count == 1)
+    }
+
+    async fn record_use(
+        &self,
+        id: ObjectId,
+        sign_count: i64,
+        now: BsonDateTime,
+    ) -> Result<bool, AppError> {
+        let counter = if sign_count == 0 {
+            doc! { "$eq": 0_i64 }
+        } else {
+            doc! { "$lt": sign_count }
+        };
+        let res = self
+            .passkeys
+            .update_one(
+                doc! { "_id": id, "sign_count": counter },
+                doc! { "$set": { "sign_count": sign_count, "last_used_at": now } },
+            )
+            .await?;
+        Ok(res.matched_count == 1)
+    }
+}
+
+pub struct MongoWebAuthnChallengeStore {
+    challenges: Collection<WebAuthnChallengeDoc>,
+}
+
+impl MongoWebAuthnChallengeStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let challenges: Collection<WebAuthnChallengeDoc> = db.collection("webauthn_challenges");
+
+        let hash_index = IndexModel::builder()
+            .keys(doc! { "challenge_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        challenges.create_index(hash_index).await?;
+
+        // TTL: MongoDB drops abandoned ceremonies by itself
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        challenges.create_index(ttl_index).await?;
+
+        Ok(Self { challenges })
+    }
+}
+
+#[async_trait]
+impl WebAuthnChallengeStore for MongoWebAuthnChallengeStore {
+    async fn insert(&self, challenge: &WebAuthnChallengeDoc) -> Result<(), AppError> {
+        self.challenges.insert_one(challenge).await?;
+        Ok(())
+    }
+
+    async fn take_by_hash(
+        &self,
+        challenge_hash: &str,
+        ceremony: WebAuthnCeremony,
+        now: BsonDateTime,
+    ) -> Result<Option<WebAuthnChallengeDoc>, AppError> {
+        Ok(self
+            .challenges
+            .find_one_and_delete(doc! {
+                "challenge_hash": challenge_hash,
+                "ceremony": ceremony.as_str(),
+                "expires_at": { "$gt": now },
+            })
+            .await?)
+    }
+}
+
+pub struct MongoLoginAttemptStore {
+    attempts: Collection<LoginAttemptDoc>,
+}
+
+impl MongoLoginAttemptStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let attempts: Collection<LoginAttemptDoc> = db.collection("login_attempts");
+
+        let key_index = IndexModel::builder()
+            .keys(doc! { "key": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        attempts.create_index(key_index).await?;
+
+        // TTL: counters are forgotten after the window (or the lock) is over
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        attempts.create_index(ttl_index).await?;
+
+        Ok(Self { attempts })
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for MongoLoginAttemptStore {
+    async fn find(
+        &self,
+        key: &str,
+        now: BsonDateTime,
+    ) -> Result<Option<LoginAttemptDoc>, AppError> {
+        Ok(self
+            .attempts
+            .find_one(doc! { "key": key, "expires_at": { "$gt": now } })
+            .await?)
+    }
+
+    async fn record_failure(
+        &self,
+        key: &str,
+        now: BsonDateTime,
+        expires_at: BsonDateTime,
+    ) -> Result<LoginAttemptDoc, AppError> {
+        // the TTL monitor runs about once a minute: do not count on top of an expired record
+        self.attempts
+            .delete_one(doc! { "key": key, "expires_at": { "$lte": now } })
+            .await?;
+
+        let updated = self
+            .attempts
+            .find_one_and_update(
+                doc! { "key": key },
+                doc! {
+                    "$inc": { "failures": 1 },
+                    "$set": { "last_failure_at": now },
+                    "$max": { "expires_at": expires_at },
+                    "$setOnInsert": { "_id": ObjectId::new(), "locked_until": null },
+                },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await?;
+
+        updated.ok_or_else(|| AppError::Internal("login attempt upsert returned nothing".into()))
+    }
+
+    async fn lock(
+        &self,
+        key: &str,
+        locked_until: BsonDateTime,
+        expires_at: BsonDateTime,
+    ) -> Result<(), AppError> {
+        self.attempts
+            .update_one(
+                doc! { "key": key },
+                doc! {
+                    "$set": { "locked_until": locked_until },
+                    "$max": { "expires_at": expires_at },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<bool, AppError> {
+        let res = self.attempts.delete_one(doc! { "key": key }).await?;
+        Ok(res.deleted_count == 1)
+    }
+
+    async fn list_locked(&self, now: BsonDateTime) -> Result<Vec<LoginAttemptDoc>, AppError> {
+        let cursor = self
+            .attempts
+            .find(doc! { "locked_until": { "$gt": now } })
+            .sort(doc! { "locked_until": -1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+pub struct MongoRevokedTokenStore {
+    revoked: Collection<RevokedTokenDoc>,
+}
+
+impl MongoRevokedTokenStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let revoked: Collection<RevokedTokenDoc> = db.collection("revoked_tokens");
+
+        let key_index = IndexModel::builder()
+            .keys(doc! { "key": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        revoked.create_index(key_index).await?;
+
+        // TTL: an entry is useless once the tokens it rejects have expired
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        revoked.create_index(ttl_index).await?;
+
+        Ok(Self { revoked })
+    }
+}
+
+#[async_trait]
+impl RevokedTokenStore for MongoRevokedTokenStore {
+    async fn revoke(
+        &self,
+        key: &str,
+        token_version: Option<i64>,
+        expires_at: BsonDateTime,
+    ) -> Result<(), AppError> {
+        let mut max = doc! { "expires_at": expires_at };
+        if let Some(v) = token_version {
+            max.insert("token_version", v);
+        }
+
+        self.revoked
+            .update_one(
+                doc! { "key": key },
+                doc! {
+                    "$max": max,
+                    "$setOnInsert": { "_id": ObjectId::new(), "created_at": BsonDateTime::now() },
+                },
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_active(&self, now: BsonDateTime) -> Result<Vec<RevokedTokenDoc>, AppError> {
+        let cursor = self
+            .revoked
+            .find(doc! { "expires_at": { "$gt": now } })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+pub struct MongoAuditEventStore {
+    events: Collection<AuditEventDoc>,
+}
+
+impl MongoAuditEventStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let events: Collection<AuditEventDoc> = db.collection("audit_events");
+
+        // a user's own history, newest first
+        let user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "_id": -1 })
+            .build();
+        events.create_index(user_index).await?;
+
+        // admin queries by action / time
+        let action_index = IndexModel::builder()
+            .keys(doc! { "action": 1, "_id": -1 })
+            .build();
+        events.create_index(action_index).await?;
+
+        let created_index = IndexModel::builder()
+            .keys(doc! { "created_at": -1 })
+            .build();
+        events.create_index(created_index).await?;
+
+        Ok(Self { events })
+    }
+}
+
+#[async_trait]
+impl AuditEventStore for MongoAuditEventStore {
+    async fn insert(&self, event: &AuditEventDoc) -> Result<(), AppError> {
+        self.events.insert_one(event).await?;
+        Ok(())
+    }
+
+    async fn find(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditEventDoc>, AppError> {
+        let mut query = Document::new();
+        if let Some(user_id) = filter.user_id {
+            query.insert("user_id", user_id);
+        }
+        if let Some(actor) = &filter.actor {
+            query.insert("actor", actor);
+        }
+        if let Some(action) = filter.action {
+            query.insert("action", action.as_str());
+        }
+        if let Some(outcome) = filter.outcome {
+            query.insert("outcome", outcome.as_str());
+        }
+
+        let mut created_at = Document::new();
+        if let Some(from) = filter.from {
+            created_at.insert("$gte", from);
+        }
+        if let Some(to) = filter.to {
+            created_at.insert("$lt", to);
+        }
+        if !created_at.is_empty() {
+            query.insert("created_at", created_at);
+        }
+        if let Some(before) = filter.before {
+            query.insert("_id", doc! { "$lt": before });
+        }
+
+        let cursor = self
+            .events
+            .find(query)
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+pub struct MongoOrganizationStore {
+    orgs: Collection<OrganizationDoc>,
+}
+
+impl MongoOrganizationStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        Ok(Self {
+            orgs: db.collection("organizations"),
+        })
+    }
+}
+
+#[async_trait]
+impl OrganizationStore for MongoOrganizationStore {
+    async fn insert(&self, org: &OrganizationDoc) -> Result<(), AppError> {
+        self.orgs.insert_one(org).await?;
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: ObjectId) -> Result<Option<OrganizationDoc>, AppError> {
+        Ok(self.orgs.find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn find_many(&self, ids: &[ObjectId]) -> Result<Vec<OrganizationDoc>, AppError> {
+        let cursor = self.orgs.find(doc! { "_id": { "$in": ids } }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<bool, AppError> {
+        let res = self.orgs.delete_one(doc! { "_id": id }).await?;
+        Ok(res.deleted_count == 1)
+    }
+
+    async fn set_plan(
+        &self,
+        id: ObjectId,
+        plan: &str,
+    ) -> Result<Option<OrganizationDoc>, AppError> {
+        Ok(self
+            .orgs
+            .find_one_and_update(doc! { "_id": id }, doc! { "$set": { "plan": plan } })
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+}
+
+pub struct MongoMembershipStore {
+    memberships: Collection<MembershipDoc>,
+}
+
+impl MongoMembershipStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let memberships: Collection<MembershipDoc> = db.collection("memberships");
+
+        let member_index = IndexModel::builder()
+            .keys(doc! { "org_id": 1, "user_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        memberships.create_index(member_index).await?;
+
+        // a user's organizations
+        let user_index = IndexModel::builder().keys(doc! { "user_id": 1 }).build();
+        memberships.create_index(user_index).await?;
+
+        Ok(Self { memberships })
+    }
+}
+
+#[async_trait]
+impl MembershipStore for MongoMembershipStore {
+    async fn insert(&self, membership: &MembershipDoc) -> Result<(), AppError> {
+        self.memberships.insert_one(membership).await?;
+        Ok(())
+    }
+
+    async fn find(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<MembershipDoc>, AppError> {
+        Ok(self
+            .memberships
+            .find_one(doc! { "org_id": org_id, "user_id": user_id })
+            .await?)
+    }
+
+    async fn list_for_org(&self, org_id: ObjectId) -> Result<Vec<MembershipDoc>, AppError> {
+        let cursor = self
+            .memberships
+            .find(doc! { "org_id": org_id })
+            .sort(doc! { "_id": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn list_for_user(&self, user_id: ObjectId) -> Result<Vec<MembershipDoc>, AppError> {
+        let cursor = self
+            .memberships
+            .find(doc! { "user_id": user_id })
+            .sort(doc! { "_id": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn set_role(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+        role: OrgRole,
+    ) -> Result<Option<MembershipDoc>, AppError> {
+        Ok(self
+            .memberships
+            .find_one_and_update(
+                doc! { "org_id": org_id, "user_id": user_id },
+                doc! { "$set": { "role": role.as_str() } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    async fn delete(&self, org_id: ObjectId, user_id: ObjectId) -> Result<bool, AppError> {
+        let res = self
+            .memberships
+            .delete_one(doc! { "org_id": org_id, "user_id": user_id })
+            .await?;
+        Ok(res.deleted_count == 1)
+    }
+
+    async fn delete_for_org(&self, org_id: ObjectId) -> Result<u64, AppError> {
+        let res = self
+            .memberships
+            .delete_many(doc! { "org_id": org_id })
+            .await?;
+        Ok(res.deleted_count)
+    }
+}
+
+pub struct MongoPlanStore {
+    plans: Collection<PlanDoc>,
+}
+
+impl MongoPlanStore {
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        Ok(Self {
+            plans: db.collection("plans"),
+        })
+    }
+}
+
+#[async_trait]
+impl PlanStore for MongoPlanStore {
+    async fn list(&self) -> Result<Vec<PlanDoc>, AppError> {
+        let cursor = self.plans.find(doc! {}).sort(doc! { "_id": 1 }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn find(&self, name: &str) -> Result<Option<PlanDoc>, AppError> {
+        Ok(self.plans.find_one(doc! { "_id": name }).await?)
+    }
+
+    async fn upsert(&self, plan: &PlanDoc) -> Result<(), AppError> {
+        self.plans
+            .replace_one(doc! { "_id": &plan.name }, plan)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+}
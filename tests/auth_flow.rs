@@ -0,0 +1,140 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+
+use common::{app, call, register, str_field};
+
+const PASSWORD: &str = "Correct-Horse-9";
+
+#[tokio::test]
+async fn register_login_and_refresh() {
+    let app = app().await;
+    let registered = register(&app, "alice@example.com", PASSWORD).await;
+    assert!(!str_field(&registered, "api_key").is_empty());
+
+    let (status, me) = call(
+        &app,
+        "GET",
+        "/auth/me",
+        Some(str_field(&registered, "access_token")),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["email"], "alice@example.com");
+
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/register",
+        None,
+        Some(json!({ "name": "Again", "email": "alice@example.com", "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/login",
+        None,
+        Some(json!({ "email": "alice@example.com", "password": "wrong-password-1" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, login) = call(
+        &app,
+        "POST",
+        "/auth/login",
+        None,
+        Some(json!({ "email": "alice@example.com", "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "login: {login}");
+
+    let (status, refreshed) = call(
+        &app,
+        "POST",
+        "/auth/refresh",
+        None,
+        Some(json!({ "refresh_token": str_field(&login, "refresh_token") })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "refresh: {refreshed}");
+    assert_ne!(
+        str_field(&refreshed, "refresh_token"),
+        str_field(&login, "refresh_token")
+    );
+
+    let (status, _) = call(
+        &app,
+        "GET",
+        "/auth/me",
+        Some(str_field(&refreshed, "access_token")),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn reused_refresh_token_revokes_the_family() {
+    let app = app().await;
+    let registered = register(&app, "bob@example.com", PASSWORD).await;
+    let first = str_field(&registered, "refresh_token");
+
+    let (status, rotated) = call(
+        &app,
+        "POST",
+        "/auth/refresh",
+        None,
+        Some(json!({ "refresh_token": first })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let second = str_field(&rotated, "refresh_token");
+
+    // the replaced token again: treated as stolen
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/refresh",
+        None,
+        Some(json!({ "refresh_token": first })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // ...so its successor is revoked too
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/refresh",
+        None,
+        Some(json!({ "refresh_token": second })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // a fresh login starts a new family
+    let (status, login) = call(
+        &app,
+        "POST",
+        "/auth/login",
+        None,
+        Some(json!({ "email": "bob@example.com", "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/refresh",
+        None,
+        Some(json!({ "refresh_token": str_field(&login, "refresh_token") })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
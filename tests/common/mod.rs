@@ -0,0 +1,104 @@
+//! The router on in-memory stores (`STORAGE_BACKEND=memory`) and JSON requests
+//! against it, without a listener.
+
+// each test binary uses only some of the helpers
+#![allow(dead_code)]
+
+use auth_service::{config::Config, routes::app_router, state::AppState};
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use std::sync::{Arc, Once};
+use tower::ServiceExt;
+
+static ENV: Once = Once::new();
+
+pub async fn app() -> Router {
+    app_router(state(|_| {}).await)
+}
+
+/// State from the test environment, with `configure` applied to the config.
+pub async fn state(configure: impl FnOnce(&mut Config)) -> Arc<AppState> {
+    ENV.call_once(|| {
+        let outbox = std::env::temp_dir().join("auth-service-tests-outbox");
+        // SAFETY: every test calls `app` before anything else, and `call_once` makes
+        // the others wait until the variables are set, so nothing reads them meanwhile
+        unsafe {
+            std::env::set_var("STORAGE_BACKEND", "memory");
+            std::env::set_var("JWT_SECRET", "integration-test-secret");
+            // base64 of 32 bytes "k"
+            std::env::set_var(
+                "API_KEY_ENC_KEY_BASE64",
+                "a2tra2tra2tra2tra2tra2tra2tra2tra2tra2tra2s=",
+            );
+            std::env::set_var("MAIL_OUTBOX_DIR", outbox);
+        }
+    });
+
+    let mut cfg = Config::from_env();
+    configure(&mut cfg);
+    let state = AppState::in_memory(cfg).await.expect("in-memory state");
+    Arc::new(state)
+}
+
+/// Sends one request; the body is parsed as JSON (`Null` when empty).
+pub async fn call(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    bearer: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut req = Request::builder().method(method).uri(uri);
+    if let Some(token) = bearer {
+        req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let req = match body {
+        Some(body) => req
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => req.body(Body::empty()),
+    }
+    .unwrap();
+
+    let (status, _, json) = send(app, req).await;
+    (status, json)
+}
+
+/// Sends `req` as is; returns the headers too. The body is parsed as in `call`.
+pub async fn send(app: &Router, req: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let json = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+    };
+    (status, headers, json)
+}
+
+/// Registers `email` and returns the response body (user, api key and tokens).
+pub async fn register(app: &Router, email: &str, password: &str) -> Value {
+    let (status, body) = call(
+        app,
+        "POST",
+        "/auth/register",
+        None,
+        Some(serde_json::json!({ "name": "Test", "email": email, "password": password })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "register: {body}");
+    body
+}
+
+pub fn str_field<'a>(body: &'a Value, field: &str) -> &'a str {
+    body[field]
+        .as_str()
+        .unwrap_or_else(|| panic!("no `{field}` in {body}"))
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use common::{app, call, register, str_field};
+
+const PASSWORD: &str = "Correct-Horse-9";
+
+/// Same parameters as the server: SHA-1, 6 digits, 30 second steps.
+fn totp(secret: &str) -> TOTP {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes, None, "test".into()).unwrap()
+}
+
+/// Code of the time step `steps` after the current one.
+fn code_at(totp: &TOTP, steps: u64) -> String {
+    let now = chrono::Utc::now().timestamp() as u64;
+    totp.generate(now + steps * 30)
+}
+
+/// Registers `email` with TOTP on; returns the authenticator and the recovery codes.
+async fn register_with_totp(app: &Router, email: &str) -> (TOTP, Vec<String>) {
+    let registered = register(app, email, PASSWORD).await;
+    let access = str_field(&registered, "access_token");
+
+    let (status, setup) = call(app, "POST", "/auth/mfa/totp/setup", Some(access), None).await;
+    assert_eq!(status, StatusCode::OK, "setup: {setup}");
+    let totp = totp(str_field(&setup, "secret"));
+
+    let (status, enabled) = call(
+        app,
+        "POST",
+        "/auth/mfa/totp/enable",
+        Some(access),
+        Some(json!({ "code": code_at(&totp, 0) })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "enable: {enabled}");
+    let recovery_codes = enabled["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_str().unwrap().to_string())
+        .collect();
+    (totp, recovery_codes)
+}
+
+/// Password step; returns the `mfa_token` for the second one.
+async fn login(app: &Router, email: &str) -> String {
+    let (status, body) = call(
+        app,
+        "POST",
+        "/auth/login",
+        None,
+        Some(json!({ "email": email, "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "login: {body}");
+    assert!(
+        body["access_token"].is_null(),
+        "no tokens before the second factor"
+    );
+    str_field(&body, "mfa_token").to_string()
+}
+
+async fn login_mfa(app: &Router, mfa_token: &str, code: &str) -> (StatusCode, Value) {
+    call(
+        app,
+        "POST",
+        "/auth/login/mfa",
+        None,
+        Some(json!({ "mfa_token": mfa_token, "code": code })),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn totp_and_recovery_codes_work_once() {
+    let app = app().await;
+    let (totp, recovery_codes) = register_with_totp(&app, "frank@example.com").await;
+
+    // the enabling code used the current step, so the next one (within the drift)
+    let code = code_at(&totp, 1);
+    let mfa_token = login(&app, "frank@example.com").await;
+    let (status, tokens) = login_mfa(&app, &mfa_token, &code).await;
+    assert_eq!(status, StatusCode::OK, "totp: {tokens}");
+    let (status, _) = call(
+        &app,
+        "GET",
+        "/auth/me",
+        Some(str_field(&tokens, "access_token")),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // the challenge is single-use
+    let (status, _) = login_mfa(&app, &mfa_token, &code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // and so is each time step
+    let mfa_token = login(&app, "frank@example.com").await;
+    let (status, _) = login_mfa(&app, &mfa_token, &code).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let recovery_code = &recovery_codes[0];
+    let mfa_token = login(&app, "frank@example.com").await;
+    let (status, body) = login_mfa(&app, &mfa_token, recovery_code).await;
+    assert_eq!(status, StatusCode::OK, "recovery code: {body}");
+
+    let mfa_token = login(&app, "frank@example.com").await;
+    let (status, _) = login_mfa(&app, &mfa_token, recovery_code).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn wrong_codes_lock_the_account() {
+    let app = app().await;
+    let (totp, _) = register_with_totp(&app, "grace@example.com").await;
+    // taken before the lock, used after it
+    let pending = login(&app, "grace@example.com").await;
+
+    // LOGIN_MAX_FAILURES_PER_ACCOUNT (5); every guess needs the password again
+    for _ in 0..5 {
+        let mfa_token = login(&app, "grace@example.com").await;
+        let (status, _) = login_mfa(&app, &mfa_token, "000000").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // locked: even a valid code is refused, and so is the password step.
+    // Without a listener there is no client IP, so only the account lock (423) applies.
+    let (status, _) = login_mfa(&app, &pending, &code_at(&totp, 1)).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/login",
+        None,
+        Some(json!({ "email": "grace@example.com", "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::LOCKED);
+}
@@ -0,0 +1,290 @@
+mod common;
+
+use auth_service::routes::app_router;
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use url::{form_urlencoded, Url};
+
+use common::{register, send, state, str_field};
+
+const ADMIN_TOKEN: &str = "oauth-test-admin";
+const REDIRECT_URI: &str = "http://localhost:8080/callback";
+const PASSWORD: &str = "Correct-Horse-9";
+
+async fn app() -> Router {
+    app_router(state(|cfg| cfg.admin_token = Some(ADMIN_TOKEN.into())).await)
+}
+
+/// Registers a client through the admin API; returns `client_id` and the secret.
+async fn create_client(app: &Router, scopes: &[&str], confidential: bool) -> (String, Value) {
+    let body = json!({
+        "name": "Test client",
+        "redirect_uris": [REDIRECT_URI],
+        "scopes": scopes,
+        "confidential": confidential,
+    });
+    let req = Request::post("/admin/oauth/clients")
+        .header("x-admin-token", ADMIN_TOKEN)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, _, created) = send(app, req).await;
+    assert_eq!(status, StatusCode::OK, "create client: {created}");
+    (
+        str_field(&created["client"], "client_id").to_string(),
+        created["client_secret"].clone(),
+    )
+}
+
+fn encode(fields: &[(&str, &str)]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(fields)
+        .finish()
+}
+
+/// POSTs `fields` form-encoded (as browsers and OAuth clients do).
+async fn post_form(
+    app: &Router,
+    uri: &str,
+    fields: &[(&str, &str)],
+    basic: Option<(&str, &str)>,
+) -> (StatusCode, HeaderMap, Value) {
+    let mut req =
+        Request::post(uri).header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+    if let Some((user, password)) = basic {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+        req = req.header(header::AUTHORIZATION, format!("Basic {credentials}"));
+    }
+    send(app, req.body(Body::from(encode(fields))).unwrap()).await
+}
+
+fn challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn query_param(location: &str, name: &str) -> Option<String> {
+    Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn location(headers: &HeaderMap) -> &str {
+    headers[header::LOCATION].to_str().unwrap()
+}
+
+/// Signs in on the authorization page and returns the code sent to REDIRECT_URI.
+async fn authorize(app: &Router, client_id: &str, email: &str, verifier: &str) -> String {
+    let challenge = challenge(verifier);
+    let (status, headers, _) = post_form(
+        app,
+        "/oauth/authorize",
+        &[
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", REDIRECT_URI),
+            ("scope", "openid profile"),
+            ("state", "xyz"),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+            ("email", email),
+            ("password", PASSWORD),
+        ],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let location = location(&headers);
+    assert!(location.starts_with(REDIRECT_URI), "{location}");
+    assert_eq!(query_param(location, "state").as_deref(), Some("xyz"));
+    query_param(location, "code").expect("code in redirect")
+}
+
+async fn exchange(
+    app: &Router,
+    client_id: &str,
+    code: &str,
+    redirect_uri: &str,
+    verifier: &str,
+) -> (StatusCode, Value) {
+    let (status, _, body) = post_form(
+        app,
+        "/oauth/token",
+        &[
+            ("grant_type", "authorization_code"),
+            ("client_id", client_id),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", verifier),
+        ],
+        None,
+    )
+    .await;
+    (status, body)
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, HeaderMap) {
+    let req = Request::get(uri).body(Body::empty()).unwrap();
+    let (status, headers, _) = send(app, req).await;
+    (status, headers)
+}
+
+#[tokio::test]
+async fn authorization_code_with_pkce() {
+    let app = app().await;
+    register(&app, "erin@example.com", PASSWORD).await;
+    let (client_id, _) = create_client(&app, &["openid", "profile", "api"], false).await;
+    let (other_client_id, _) = create_client(&app, &["openid", "profile"], false).await;
+    let verifier = "a".repeat(43) + "-verifier";
+    let challenge = challenge(&verifier);
+
+    let query = |client_id: &str, redirect_uri: &str, method: &str| {
+        format!(
+            "/oauth/authorize?{}",
+            encode(&[
+                ("response_type", "code"),
+                ("client_id", client_id),
+                ("redirect_uri", redirect_uri),
+                ("code_challenge", &challenge),
+                ("code_challenge_method", method),
+            ])
+        )
+    };
+
+    let (status, _) = get(&app, &query(&client_id, REDIRECT_URI, "S256")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // unknown client and unregistered redirect_uri are shown, never redirected
+    let (status, _) = get(&app, &query("no-such-client", REDIRECT_URI, "S256")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get(
+        &app,
+        &query(&client_id, "http://localhost:8080/elsewhere", "S256"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // only S256
+    let (status, headers) = get(&app, &query(&client_id, REDIRECT_URI, "plain")).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        query_param(location(&headers), "error").as_deref(),
+        Some("invalid_request")
+    );
+
+    // every failed exchange below burns its code
+    let code = authorize(&app, &client_id, "erin@example.com", &verifier).await;
+    let (status, body) = exchange(&app, &client_id, &code, REDIRECT_URI, &"b".repeat(43)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+
+    let code = authorize(&app, &client_id, "erin@example.com", &verifier).await;
+    let (status, body) = exchange(
+        &app,
+        &client_id,
+        &code,
+        "http://localhost:8080/elsewhere",
+        &verifier,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+
+    let code = authorize(&app, &client_id, "erin@example.com", &verifier).await;
+    let (status, body) = exchange(&app, &other_client_id, &code, REDIRECT_URI, &verifier).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+
+    let (status, body) = exchange(&app, "no-such-client", &code, REDIRECT_URI, &verifier).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "invalid_client");
+
+    let code = authorize(&app, &client_id, "erin@example.com", &verifier).await;
+    let (status, tokens) = exchange(&app, &client_id, &code, REDIRECT_URI, &verifier).await;
+    assert_eq!(status, StatusCode::OK, "exchange: {tokens}");
+    assert_eq!(tokens["scope"], "openid profile");
+    assert!(tokens["id_token"].is_string());
+    assert!(tokens["refresh_token"].is_string());
+
+    // a code is single-use
+    let (status, body) = exchange(&app, &client_id, &code, REDIRECT_URI, &verifier).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+}
+
+async fn client_credentials(
+    app: &Router,
+    client_id: &str,
+    secret: &str,
+    scope: Option<&str>,
+) -> (StatusCode, Value) {
+    let mut fields = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", client_id),
+        ("client_secret", secret),
+    ];
+    fields.extend(scope.map(|s| ("scope", s)));
+    let (status, _, body) = post_form(app, "/oauth/token", &fields, None).await;
+    (status, body)
+}
+
+#[tokio::test]
+async fn client_credentials_limits_scopes_to_the_client() {
+    let app = app().await;
+    let (client_id, secret) = create_client(&app, &["api", "profile"], true).await;
+    let secret = secret.as_str().expect("confidential client secret");
+
+    // none requested: everything the client may have
+    let (status, body) = client_credentials(&app, &client_id, secret, None).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["scope"], "api profile");
+    assert!(body.get("refresh_token").is_none());
+
+    // repeated scopes are granted once, in the requested order
+    let (status, body) =
+        client_credentials(&app, &client_id, secret, Some("profile api profile")).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["scope"], "profile api");
+
+    let (status, body) = client_credentials(&app, &client_id, secret, Some("api api_keys")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_scope");
+
+    let (status, body) = client_credentials(&app, &client_id, "wrong-secret", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "invalid_client");
+
+    // client_secret_basic works the same
+    let (status, _, body) = post_form(
+        &app,
+        "/oauth/token",
+        &[("grant_type", "client_credentials"), ("scope", "api")],
+        Some((&client_id, secret)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["scope"], "api");
+
+    // public clients have nothing to authenticate with
+    let (public_id, _) = create_client(&app, &["api"], false).await;
+    let (status, _, body) = post_form(
+        &app,
+        "/oauth/token",
+        &[
+            ("grant_type", "client_credentials"),
+            ("client_id", &public_id),
+        ],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "unauthorized_client");
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ciborium::value::Value as Cbor;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use common::{app, call, register, str_field};
+
+/// rp id and origin that `Config::from_env` derives from the default OIDC_ISSUER.
+const RP_ID: &str = "localhost";
+const ORIGIN: &str = "http://localhost:3000";
+
+const FLAGS_UP_UV: u8 = 0x01 | 0x04;
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+
+/// A software ES256 authenticator: one credential, attestation "none".
+struct Authenticator {
+    key: SigningKey,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+}
+
+impl Authenticator {
+    fn new() -> Self {
+        Self {
+            key: SigningKey::random(&mut rand::rngs::OsRng),
+            credential_id: rand::random::<[u8; 16]>().to_vec(),
+            sign_count: 0,
+        }
+    }
+
+    fn id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    /// COSE_Key: kty EC2, alg ES256, crv P-256, x, y.
+    fn cose_key(&self) -> Vec<u8> {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let key = Cbor::Map(vec![
+            (Cbor::from(1), Cbor::from(2)),
+            (Cbor::from(3), Cbor::from(-7)),
+            (Cbor::from(-1), Cbor::from(1)),
+            (Cbor::from(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+            (Cbor::from(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut out = Vec::new();
+        ciborium::into_writer(&key, &mut out).unwrap();
+        out
+    }
+
+    fn auth_data(&self, attested: bool) -> Vec<u8> {
+        let mut data = Sha256::digest(RP_ID.as_bytes()).to_vec();
+        data.push(if attested {
+            FLAGS_UP_UV | FLAG_ATTESTED_DATA
+        } else {
+            FLAGS_UP_UV
+        });
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        if attested {
+            data.extend_from_slice(&[0; 16]); // aaguid
+            data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.credential_id);
+            data.extend_from_slice(&self.cose_key());
+        }
+        data
+    }
+
+    /// `RegistrationResponseJSON` for `challenge`.
+    fn attestation(&self, challenge: &str) -> Value {
+        let object = Cbor::Map(vec![
+            (Cbor::from("fmt"), Cbor::from("none")),
+            (Cbor::from("attStmt"), Cbor::Map(Vec::new())),
+            (Cbor::from("authData"), Cbor::Bytes(self.auth_data(true))),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::into_writer(&object, &mut attestation_object).unwrap();
+
+        json!({
+            "id": self.id(),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": client_data("webauthn.create", challenge),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object),
+            },
+        })
+    }
+
+    /// `AuthenticationResponseJSON` for `challenge`, signed with the current counter.
+    fn assertion(&self, challenge: &str, user_handle: &str) -> Value {
+        let auth_data = self.auth_data(false);
+        let client_data = client_data("webauthn.get", challenge);
+
+        let mut message = auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(
+            URL_SAFE_NO_PAD.decode(&client_data).unwrap(),
+        ));
+        let signature: Signature = self.key.sign(&message);
+
+        json!({
+            "id": self.id(),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": client_data,
+                "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+                "userHandle": user_handle,
+            },
+        })
+    }
+}
+
+fn client_data(typ: &str, challenge: &str) -> String {
+    let json = json!({ "type": typ, "challenge": challenge, "origin": ORIGIN });
+    URL_SAFE_NO_PAD.encode(json.to_string())
+}
+
+async fn login(
+    app: &Router,
+    authenticator: &Authenticator,
+    user_handle: &str,
+) -> (StatusCode, Value) {
+    let (status, options) = call(app, "POST", "/auth/passkeys/login/start", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let assertion = authenticator.assertion(str_field(&options, "challenge"), user_handle);
+    call(
+        app,
+        "POST",
+        "/auth/passkeys/login/finish",
+        None,
+        Some(assertion),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn passkey_register_and_login() {
+    let app = app().await;
+    let registered = register(&app, "carol@example.com", "Correct-Horse-9").await;
+    let access = str_field(&registered, "access_token");
+
+    let mut authenticator = Authenticator::new();
+    let (status, options) = call(
+        &app,
+        "POST",
+        "/auth/passkeys/register/start",
+        Some(access),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "register start: {options}");
+    assert_eq!(options["rp"]["id"], RP_ID);
+    let user_handle = options["user"]["id"].as_str().unwrap().to_string();
+
+    let credential = authenticator.attestation(str_field(&options, "challenge"));
+    let (status, passkey) = call(
+        &app,
+        "POST",
+        "/auth/passkeys/register/finish",
+        Some(access),
+        Some(json!({ "name": "Test key", "credential": credential })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "register finish: {passkey}");
+    assert_eq!(passkey["name"], "Test key");
+
+    // the registration challenge is single-use
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/passkeys/register/finish",
+        Some(access),
+        Some(json!({ "credential": credential })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    authenticator.sign_count = 1;
+    let (status, tokens) = login(&app, &authenticator, &user_handle).await;
+    assert_eq!(status, StatusCode::OK, "login: {tokens}");
+
+    let (status, me) = call(
+        &app,
+        "GET",
+        "/auth/me",
+        Some(str_field(&tokens, "access_token")),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["email"], "carol@example.com");
+}
+
+#[tokio::test]
+async fn passkey_login_rejects_sign_count_regression() {
+    let app = app().await;
+    let registered = register(&app, "dave@example.com", "Correct-Horse-9").await;
+    let access = str_field(&registered, "access_token");
+
+    let mut authenticator = Authenticator::new();
+    let (_, options) = call(
+        &app,
+        "POST",
+        "/auth/passkeys/register/start",
+        Some(access),
+        None,
+    )
+    .await;
+    let user_handle = options["user"]["id"].as_str().unwrap().to_string();
+    let credential = authenticator.attestation(str_field(&options, "challenge"));
+    let (status, _) = call(
+        &app,
+        "POST",
+        "/auth/passkeys/register/finish",
+        Some(access),
+        Some(json!({ "credential": credential })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    authenticator.sign_count = 5;
+    let (status, _) = login(&app, &authenticator, &user_handle).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // a clone replaying the same or an older counter
+    for count in [5, 3] {
+        authenticator.sign_count = count;
+        let (status, _) = login(&app, &authenticator, &user_handle).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "sign count {count}");
+    }
+
+    authenticator.sign_count = 6;
+    let (status, _) = login(&app, &authenticator, &user_handle).await;
+    assert_eq!(status, StatusCode::OK);
+}
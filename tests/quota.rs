@@ -0,0 +1,210 @@
+mod common;
+
+use auth_service::{models::api_key::ApiKeyOwner, routes::app_router, state::AppState};
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    Router,
+};
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+use common::{app, call, register, send, state, str_field};
+
+/// A fresh key with `requests_per_minute` and `quota_algorithm`; returns its id and plaintext.
+async fn create_key(app: &Router, email: &str, rpm: i32, algorithm: &str) -> (String, String) {
+    let registered = register(app, email, "Correct-Horse-9").await;
+    let (status, created) = call(
+        app,
+        "POST",
+        "/auth/api-keys",
+        Some(str_field(&registered, "access_token")),
+        Some(json!({
+            "name": "quota",
+            "requests_per_minute": rpm,
+            "requests_per_day": 1000,
+            "quota_algorithm": algorithm,
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "create key: {created}");
+    (
+        created["key"]["id"].as_str().unwrap().to_string(),
+        str_field(&created, "api_key").to_string(),
+    )
+}
+
+async fn ping(app: &Router, api_key: &str) -> (StatusCode, HeaderMap) {
+    let req = Request::builder()
+        .uri("/api/ping")
+        .header("x-api-key", api_key)
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, _) = send(app, req).await;
+    (status, headers)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .unwrap_or_else(|| panic!("no {name} header"))
+        .to_str()
+        .unwrap()
+}
+
+/// First member of a `RateLimit-*` list: the value of the tightest window.
+fn tightest(headers: &HeaderMap, name: &str) -> i64 {
+    header(headers, name)
+        .split(',')
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+/// Fixed and sliding windows count per UTC minute: start far enough from its end.
+async fn skip_minute_end() {
+    let into_minute = chrono::Utc::now().timestamp_millis() % 60_000;
+    if into_minute > 50_000 {
+        tokio::time::sleep(Duration::from_millis((61_000 - into_minute) as u64)).await;
+    }
+}
+
+/// Spends a 3-per-minute quota and returns the headers of the rejected request.
+async fn exhaust(app: &Router, api_key: &str) -> HeaderMap {
+    for remaining in [2, 1, 0] {
+        let (status, headers) = ping(app, api_key).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            header(&headers, "ratelimit-remaining"),
+            format!("{remaining}, {remaining};w=60, {};w=86400", 997 + remaining)
+        );
+    }
+
+    let (status, headers) = ping(app, api_key).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header(&headers, "ratelimit-policy"), "3;w=60, 1000;w=86400");
+    assert_eq!(
+        header(&headers, "ratelimit-limit"),
+        "3, 3;w=60, 1000;w=86400"
+    );
+    assert_eq!(tightest(&headers, "ratelimit-remaining"), 0);
+    headers
+}
+
+fn retry_after(headers: &HeaderMap) -> i64 {
+    header(headers, "retry-after").parse().unwrap()
+}
+
+#[tokio::test]
+async fn fixed_window_rejects_until_the_minute_ends() {
+    let app = app().await;
+    let (_, api_key) = create_key(&app, "fixed@example.com", 3, "fixed_window").await;
+    skip_minute_end().await;
+
+    let headers = exhaust(&app, &api_key).await;
+    let retry = retry_after(&headers);
+    assert!((1..=60).contains(&retry), "retry-after {retry}");
+    // the minute window is the one that is exhausted
+    assert_eq!(tightest(&headers, "ratelimit-reset"), retry);
+
+    // still rejected, now from the cache
+    let (status, headers) = ping(&app, &api_key).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(retry_after(&headers) <= retry);
+}
+
+#[tokio::test]
+async fn sliding_window_rejects_until_the_minute_ends() {
+    let app = app().await;
+    let (_, api_key) = create_key(&app, "sliding@example.com", 3, "sliding_window").await;
+    skip_minute_end().await;
+
+    // nothing in the previous minute: the whole limit is available in this one.
+    // Next minute these 3 requests weigh 3 * (60s - t) / 60s, so one more fits
+    // 20 seconds in, and all of them have slid out a minute later.
+    let headers = exhaust(&app, &api_key).await;
+    let retry = retry_after(&headers);
+    assert!((21..=80).contains(&retry), "retry-after {retry}");
+    assert_eq!(tightest(&headers, "ratelimit-reset"), retry + 40);
+}
+
+#[tokio::test]
+async fn token_bucket_refills_one_token_per_interval() {
+    let app = app().await;
+    let (_, api_key) = create_key(&app, "bucket@example.com", 3, "token_bucket").await;
+
+    // 3 per minute: one token every 20 seconds
+    let headers = exhaust(&app, &api_key).await;
+    let retry = retry_after(&headers);
+    assert!((1..=20).contains(&retry), "retry-after {retry}");
+    // the whole bucket is back after three intervals
+    let reset = tightest(&headers, "ratelimit-reset");
+    assert!((41..=60).contains(&reset), "reset {reset}");
+}
+
+async fn used_minute(state: &AppState, owner: ApiKeyOwner, key_id: &str) -> i32 {
+    state
+        .api_keys
+        .find_for_owner(ObjectId::parse_str(key_id).unwrap(), owner)
+        .await
+        .unwrap()
+        .unwrap()
+        .requests_used_minute
+}
+
+#[tokio::test]
+async fn unused_leases_are_given_back() {
+    // each reservation takes half of the minute limit
+    let state = state(|cfg| cfg.quota_margin = 0.5).await;
+    let app = app_router(Arc::clone(&state));
+    skip_minute_end().await;
+
+    let registered = register(&app, "lease@example.com", "Correct-Horse-9").await;
+    let access = str_field(&registered, "access_token");
+    let owner =
+        ApiKeyOwner::User(ObjectId::parse_str(str_field(&registered["user"], "id")).unwrap());
+    let (status, created) = call(
+        &app,
+        "POST",
+        "/auth/api-keys",
+        Some(access),
+        Some(json!({ "name": "lease", "requests_per_minute": 4 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let key_id = created["key"]["id"].as_str().unwrap();
+    let api_key = str_field(&created, "api_key");
+
+    // one request served, two reserved in the store
+    let (status, headers) = ping(&app, api_key).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tightest(&headers, "ratelimit-remaining"), 3);
+    assert_eq!(used_minute(&state, owner, key_id).await, 2);
+
+    // shutdown gives the unused request back
+    state.quota.release_all().await.unwrap();
+    assert_eq!(used_minute(&state, owner, key_id).await, 1);
+
+    let (status, headers) = ping(&app, api_key).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tightest(&headers, "ratelimit-remaining"), 2);
+    assert_eq!(used_minute(&state, owner, key_id).await, 3);
+
+    // so does deactivation, at the next flush
+    let (status, _) = call(
+        &app,
+        "POST",
+        &format!("/auth/api-keys/{key_id}/deactivate"),
+        Some(access),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    state.quota.flush().await.unwrap();
+    assert_eq!(used_minute(&state, owner, key_id).await, 2);
+
+    let (status, _) = ping(&app, api_key).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}